@@ -22,7 +22,13 @@ impl Aabb {
         &self.maximum
     }
 
-    pub fn hit(&self, r: &Ray, time_min: f64, time_max: f64) -> bool {
+    pub fn surface_area(&self) -> f64 {
+        let d = self.maximum - self.minimum;
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
+    pub fn hit(&self, r: &Ray, mut time_min: f64, mut time_max: f64) -> bool {
+        // The ray must be inside all three slabs at once, so the interval narrows per axis
         for i in 0..3 {
             let inv_d = 1.0 / r.direction()[i];
             let mut t0 = (self.minimum[i] - r.origin()[i]) * inv_d;
@@ -32,7 +38,9 @@ impl Aabb {
                 (t0, t1) = (t1, t0);
             }
 
-            if time_max.min(t1) <= time_min.max(t0) {
+            time_min = time_min.max(t0);
+            time_max = time_max.min(t1);
+            if time_max <= time_min {
                 return false;
             }
         }
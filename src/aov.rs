@@ -0,0 +1,178 @@
+use std::str::FromStr;
+
+use rayon::prelude::*;
+
+use crate::codec::HdrImage;
+use crate::hittable::Hittable;
+use crate::scene::Scene;
+use crate::tiles::Tile;
+use crate::utils;
+use crate::vec3::{Color, Point3, Vec3};
+
+// Camera rays each pixel's AOVs are averaged over, the same as its first samples trace
+const SAMPLES: u64 = 16;
+
+// Arbitrary output variables: what the camera rays first hit, written alongside the image for
+// compositing and denoising
+#[derive(Clone, Copy, PartialEq)]
+pub enum Aov {
+    // Distance along the ray, infinite where nothing was hit
+    Depth,
+    // World space normal, facing the camera
+    Normal,
+    // Color the surface reflects, without lighting
+    Albedo,
+    Uv,
+    // World space position
+    Position,
+    // Number of the object hit, counting from 1 in the order the scene added them, 0 for none
+    ObjectId,
+    // Number of the material hit, counting from 1 in the order the scene created them, 0 for none
+    MaterialId,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 7] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::Uv,
+        Aov::Position,
+        Aov::ObjectId,
+        Aov::MaterialId,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Uv => "uv",
+            Aov::Position => "position",
+            Aov::ObjectId => "object-id",
+            Aov::MaterialId => "material-id",
+        }
+    }
+
+    // Names of the channels holding the AOV in a multi-layer file, one per value stored in the
+    // image. Single-channel files repeat a lone value in all three channels
+    pub fn channels(self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Normal | Aov::Position => &["X", "Y", "Z"],
+            Aov::Albedo => &["R", "G", "B"],
+            Aov::Uv => &["U", "V"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+        }
+    }
+}
+
+impl FromStr for Aov {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Aov, ()> {
+        let name = s.to_ascii_lowercase();
+        Aov::ALL.into_iter().find(|a| a.name() == name).ok_or(())
+    }
+}
+
+// Sums over the first hits of a pixel's camera rays
+#[derive(Default)]
+struct Hits {
+    count: u32,
+    depth: f64,
+    normal: Vec3,
+    // Over every ray, with the background standing in for misses
+    albedo: Color,
+    uv: (f64, f64),
+    position: Point3,
+    // What the first ray hit, so that IDs are never blended between objects
+    object: Option<usize>,
+    material: usize,
+}
+
+// Renders the AOVs of the pixels in the crop window, one image each, row by row from the top
+pub fn render(scene: &Scene, crop: &Tile, aovs: &[Aov]) -> Vec<HdrImage> {
+    if aovs.is_empty() {
+        return Vec::new();
+    }
+
+    let height = usize::try_from(scene.image_height).expect("Image dimensions are positive");
+    let pixels: Vec<Hits> = (crop.y0..crop.y1)
+        .into_par_iter()
+        .flat_map_iter(|y| (crop.x0..crop.x1).map(move |x| first_hits(scene, x, height - 1 - y)))
+        .collect();
+
+    aovs.iter()
+        .map(|&aov| {
+            let mut image = HdrImage::new(crop.width(), crop.height());
+            for (index, hits) in pixels.iter().enumerate() {
+                let value = hits.value(aov);
+                image.set(index % crop.width(), index / crop.width(), value);
+            }
+            image
+        })
+        .collect()
+}
+
+// Traces the pixel's first camera rays as its samples do, stopping at the first hit
+fn first_hits(scene: &Scene, i: usize, j: usize) -> Hits {
+    let index = scene.pixel_index(i, j);
+    let limit = u64::try_from(scene.samples_per_pixel).expect("Sample count");
+    let rays = SAMPLES.min(limit);
+    let mut sampler = scene.sampler.create(scene.seed, index, crate::PASS_SAMPLES);
+
+    let mut hits = Hits::default();
+    for sample in 0..rays {
+        sampler.start_sample(sample);
+        let mut rng = utils::sample_rng(scene.seed, index, sample);
+        let ray = scene.camera_ray(i, j, sampler.as_mut());
+        let Some(rec) = scene.world.hit(&ray, 0.001, f64::INFINITY, &mut rng) else {
+            let background = scene.environment.radiance(ray.direction());
+            hits.albedo += &Color::new(
+                background.x().clamp(0.0, 1.0),
+                background.y().clamp(0.0, 1.0),
+                background.z().clamp(0.0, 1.0),
+            );
+            continue;
+        };
+
+        hits.count += 1;
+        hits.depth += rec.t * ray.direction().length();
+        hits.normal += &rec.normal;
+        hits.albedo += &rec.material.albedo(&rec);
+        hits.uv = (hits.uv.0 + rec.u, hits.uv.1 + rec.v);
+        hits.position += &rec.p;
+        if sample == 0 {
+            hits.object = Some(rec.object);
+            hits.material = rec.material.id();
+        }
+    }
+    #[allow(clippy::cast_precision_loss)] // At most SAMPLES
+    let rays = rays as f64;
+    hits.albedo /= rays;
+    hits
+}
+
+impl Hits {
+    fn value(&self, aov: Aov) -> [f32; 3] {
+        let count = f64::from(self.count.max(1));
+        let vector = |v: Vec3| v / count;
+        let value = match aov {
+            Aov::Depth if self.count == 0 => Vec3::new_single(f64::INFINITY),
+            Aov::Depth => Vec3::new_single(self.depth / count),
+            Aov::Normal => vector(self.normal),
+            Aov::Albedo => self.albedo,
+            Aov::Uv => vector(Vec3::new(self.uv.0, self.uv.1, 0.0)),
+            Aov::Position => vector(self.position),
+            #[allow(clippy::cast_precision_loss)] // Counts of objects are far below 2^52
+            Aov::ObjectId => {
+                Vec3::new_single(self.object.map_or(0.0, |object| object as f64 + 1.0))
+            }
+            #[allow(clippy::cast_precision_loss)] // As above
+            Aov::MaterialId => Vec3::new_single(self.material as f64),
+        };
+        #[allow(clippy::cast_possible_truncation)] // f32 is plenty for output
+        [value.x() as f32, value.y() as f32, value.z() as f32]
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{
     ray::Ray,
+    sampler::Sampler,
     utils,
     vec3::{Point3, Vec3},
 };
@@ -59,14 +60,15 @@ impl Camera {
         }
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = self.lens_radius * Vec3::random_in_unit_disk();
+    // Takes the lens position and then the time from the sampler
+    pub fn get_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Ray {
+        let rd = self.lens_radius * Vec3::concentric_disk(sampler.get_2d());
         let offset = self.u * rd.x() + self.v * rd.y();
 
         Ray::new(
             self.origin + offset,
             self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
-            utils::random_float_range(self.time_start, self.time_end),
+            self.time_start + sampler.get_1d() * (self.time_end - self.time_start),
         )
     }
 }
@@ -0,0 +1,385 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::aov::Aov;
+use crate::output;
+use crate::sampler;
+use crate::scene;
+use crate::tiles;
+use crate::tonemap;
+
+const USAGE: &str = "\
+Usage: raytracer [OPTIONS]
+
+Options:
+  -s, --scene <NAME>            Scene to render (default: final)
+      --scene-file <PATH>       Load the scene from a TOML file instead of a built-in scene
+  -o, --output <PATH>           Output image path (default: output.ppm)
+  -f, --format <FORMAT>         Output format: png, p3, p6, pfm, hdr, exr or exr-none
+                                (default: from the extension, where .ppm is ASCII P3 and .pnm
+                                binary P6)
+  -w, --width <PIXELS>          Override the image width
+  -n, --samples <COUNT>         Override the samples per pixel
+  -d, --max-depth <BOUNCES>     Override the maximum ray bounce depth
+  -a, --aspect-ratio <RATIO>    Override the aspect ratio, e.g. 1.5 or 16:9
+      --seed <NUMBER>           Seed for all random numbers; the same seed gives the same image
+                                on any number of threads (default: 0, or the scene file's)
+      --sampler <NAME>          Sample pattern: independent, stratified, halton or sobol
+                                (default: sobol, or the scene file's)
+      --noise-threshold <ERROR> Stop sampling a pixel once the standard error of its luminance is
+                                below this fraction of the luminance, using the samples per pixel
+                                as the limit; 0 always takes every sample (default: 0, or the
+                                scene file's)
+      --environment <PATH>      Light the scene with an equirectangular HDR image in place of its
+                                background or environment: a PFM, Radiance .hdr or OpenEXR file
+      --tone-map <OPERATOR>     Curve fitting the radiance into 8-bit output: clamp, reinhard, aces
+                                or hable (default: clamp, or the scene's)
+      --exposure <STOPS>        Brighten 8-bit output by this many stops, or darken it if negative
+                                (default: 0, or the scene's)
+      --aov <NAMES>             Also write these of what the camera rays first hit, separated by
+                                commas, or all: depth, normal, albedo, uv, position, object-id
+                                and material-id. EXR output holds them as layers of the image;
+                                other formats get a file each beside it, such as
+                                output.depth.pfm, in PFM unless the output is already HDR
+      --denoise                 Denoise the image with a filter guided by the albedo and normal of
+                                what the camera rays first hit
+      --denoise-strength <SIGMA>
+                                How far apart colors may be before the denoiser keeps them
+                                separate; higher is smoother (default: 0.5)
+      --denoise-input <PATH>    Denoise a saved image into the output instead of rendering: an EXR
+                                with albedo and normal layers, or a PFM or HDR image with
+                                <name>.albedo and <name>.normal files beside it, as --aov writes
+      --sample-heatmap <PATH>   Also write an image of the samples taken in each pixel, from
+                                blue for the fewest to red for the most
+      --time-budget <SECONDS>   Keep adding passes of samples until this much time has passed
+                                since starting, taking no more than the samples per pixel only
+                                if they are given with --samples
+      --tile-size <PIXELS>      Width and height of the tiles the image is rendered in (default: 32)
+      --tile-order <ORDER>      Order the tiles are rendered in: scanline, spiral (from the center)
+                                or hilbert (default: spiral)
+      --crop <X0,Y0,X1,Y1>      Only render this part of the image, given as fractions of its
+                                width and height from the top left, e.g. 0.25,0.25,0.75,0.75
+      --checkpoint <PATH>       Save the accumulated samples here, and the image so far to the
+                                output, every checkpoint interval and when done
+      --checkpoint-interval <SECONDS>
+                                Time between checkpoints (default: 60)
+      --resume                  Continue the render saved in the checkpoint file; the scene and
+                                settings must match, except the samples per pixel, which may grow
+      --benchmark               Render a built-in scene once with each BVH layout and compare
+                                timings instead of writing an image (default: 200 pixels wide,
+                                16 samples)
+  -v, --verbose                 Print statistics of the BVHs built for the scene before rendering
+      --list-scenes             Print the available scene names and exit
+  -h, --help                    Print this help and exit";
+
+pub enum Command {
+    Render(Options),
+    Benchmark(Options),
+    // Denoises the saved image in `denoise_input`
+    Denoise(Options),
+    ListScenes,
+    Help,
+}
+
+pub struct Options {
+    pub scene: scene::Type,
+    // Takes precedence over `scene` when set
+    pub scene_file: Option<PathBuf>,
+    pub output: PathBuf,
+    pub format: Option<output::Format>,
+    pub overrides: scene::Overrides,
+    pub environment: Option<PathBuf>,
+    pub aovs: Vec<Aov>,
+    pub denoise: bool,
+    pub denoise_strength: f32,
+    pub denoise_input: Option<PathBuf>,
+    pub sample_heatmap: Option<PathBuf>,
+    pub time_budget: Option<Duration>,
+    pub tile_size: usize,
+    pub tile_order: tiles::Order,
+    pub crop: Option<[f64; 4]>,
+    pub checkpoint: Option<PathBuf>,
+    pub checkpoint_interval: Duration,
+    pub resume: bool,
+    pub verbose: bool,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue { option: String, value: String },
+    UnknownScene(String),
+    UnknownSampler(String),
+    UnknownAov(String),
+    // An option that only works together with another
+    Requires(&'static str, &'static str),
+    // The width and aspect ratio leave fewer than two pixels across or down
+    ImageTooSmall { width: i32, height: i32 },
+    Output(output::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownOption(option) => write!(f, "unknown option '{option}'"),
+            Error::MissingValue(option) => write!(f, "option '{option}' requires a value"),
+            Error::InvalidValue { option, value } => {
+                write!(f, "invalid value '{value}' for option '{option}'")
+            }
+            Error::UnknownScene(name) => {
+                write!(f, "unknown scene '{name}', expected one of: ")?;
+                let names: Vec<&str> = scene::Type::ALL.iter().map(scene::Type::name).collect();
+                write!(f, "{}", names.join(", "))
+            }
+            Error::UnknownSampler(name) => {
+                write!(f, "unknown sampler '{name}', expected one of: ")?;
+                let names: Vec<&str> = sampler::Type::ALL.iter().map(|t| t.name()).collect();
+                write!(f, "{}", names.join(", "))
+            }
+            Error::UnknownAov(name) => {
+                write!(f, "unknown AOV '{name}', expected all or any of: ")?;
+                let names: Vec<&str> = Aov::ALL.iter().map(|a| a.name()).collect();
+                write!(f, "{}", names.join(", "))
+            }
+            Error::Requires(option, required) => {
+                write!(f, "option '{option}' requires '{required}'")
+            }
+            Error::ImageTooSmall { width, height } => write!(
+                f,
+                "image of {width}x{height} pixels is too small, the width and aspect ratio must give at least 2x2"
+            ),
+            Error::Output(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub fn usage() -> &'static str {
+    USAGE
+}
+
+#[allow(clippy::too_many_lines)] // One arm per option
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, Error> {
+    let mut options = Options {
+        scene: scene::Type::Final,
+        scene_file: None,
+        output: PathBuf::from("output.ppm"),
+        format: None,
+        overrides: scene::Overrides::default(),
+        environment: None,
+        aovs: Vec::new(),
+        denoise: false,
+        denoise_strength: 0.5,
+        denoise_input: None,
+        sample_heatmap: None,
+        time_budget: None,
+        tile_size: 32,
+        tile_order: tiles::Order::Spiral,
+        crop: None,
+        checkpoint: None,
+        checkpoint_interval: Duration::from_mins(1),
+        resume: false,
+        verbose: false,
+    };
+
+    let mut benchmark = false;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // Support both "--option value" and "--option=value"
+        let (option, inline_value) = match arg.split_once('=') {
+            Some((option, value)) if option.starts_with("--") => {
+                (option.to_string(), Some(value.to_string()))
+            }
+            _ => (arg, None),
+        };
+
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| Error::MissingValue(option.clone()))
+        };
+
+        match option.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--list-scenes" => return Ok(Command::ListScenes),
+            "--benchmark" => benchmark = true,
+            "-s" | "--scene" => {
+                let name = value()?;
+                options.scene = name.parse().map_err(|()| Error::UnknownScene(name))?;
+            }
+            "--scene-file" => options.scene_file = Some(PathBuf::from(value()?)),
+            "-o" | "--output" => options.output = PathBuf::from(value()?),
+            "-f" | "--format" => options.format = Some(value()?.parse().map_err(Error::Output)?),
+            "-w" | "--width" => {
+                options.overrides.image_width = Some(parse_positive(&option, &value()?)?);
+            }
+            "-n" | "--samples" => {
+                options.overrides.samples_per_pixel = Some(parse_positive(&option, &value()?)?);
+            }
+            "-d" | "--max-depth" => {
+                options.overrides.max_depth = Some(parse_positive(&option, &value()?)?);
+            }
+            "-a" | "--aspect-ratio" => {
+                options.overrides.aspect_ratio = Some(parse_aspect_ratio(&option, &value()?)?);
+            }
+            "--seed" => {
+                let seed = value()?;
+                options.overrides.seed = Some(seed.parse().map_err(|_| invalid(&option, &seed))?);
+            }
+            "--sampler" => {
+                let name = value()?;
+                let sampler = name.parse().map_err(|()| Error::UnknownSampler(name))?;
+                options.overrides.sampler = Some(sampler);
+            }
+            "--noise-threshold" => {
+                let threshold = value()?;
+                options.overrides.noise_threshold = Some(
+                    threshold
+                        .parse()
+                        .ok()
+                        .filter(|t: &f64| *t >= 0.0 && t.is_finite())
+                        .ok_or_else(|| invalid(&option, &threshold))?,
+                );
+            }
+            "--environment" => options.environment = Some(PathBuf::from(value()?)),
+            "--tone-map" => {
+                let name = value()?;
+                let operator: tonemap::Operator =
+                    name.parse().map_err(|()| invalid(&option, &name))?;
+                options.overrides.tone_map = Some(operator);
+            }
+            "--exposure" => {
+                let stops = value()?;
+                options.overrides.exposure = Some(
+                    stops
+                        .parse()
+                        .ok()
+                        .filter(|s: &f64| s.is_finite())
+                        .ok_or_else(|| invalid(&option, &stops))?,
+                );
+            }
+            "--aov" => options.aovs = parse_aovs(&value()?)?,
+            "--denoise" => options.denoise = true,
+            "--denoise-strength" => {
+                let strength = value()?;
+                options.denoise_strength = strength
+                    .parse()
+                    .ok()
+                    .filter(|s: &f32| *s > 0.0 && s.is_finite())
+                    .ok_or_else(|| invalid(&option, &strength))?;
+            }
+            "--denoise-input" => options.denoise_input = Some(PathBuf::from(value()?)),
+            "--sample-heatmap" => options.sample_heatmap = Some(PathBuf::from(value()?)),
+            "--time-budget" => {
+                let seconds = value()?;
+                options.time_budget = Some(parse_seconds(&option, &seconds)?);
+            }
+            "--tile-size" => {
+                let size = parse_positive(&option, &value()?)?;
+                options.tile_size = usize::try_from(size).expect("Positive");
+            }
+            "--tile-order" => {
+                let name = value()?;
+                options.tile_order = name.parse().map_err(|()| invalid(&option, &name))?;
+            }
+            "--crop" => options.crop = Some(parse_crop(&option, &value()?)?),
+            "--checkpoint" => options.checkpoint = Some(PathBuf::from(value()?)),
+            "--checkpoint-interval" => {
+                let seconds = value()?;
+                options.checkpoint_interval = parse_seconds(&option, &seconds)?;
+            }
+            "--resume" => options.resume = true,
+            "-v" | "--verbose" => options.verbose = true,
+            _ => return Err(Error::UnknownOption(option)),
+        }
+    }
+
+    if options.resume && options.checkpoint.is_none() {
+        return Err(Error::Requires("--resume", "--checkpoint"));
+    }
+
+    if benchmark {
+        Ok(Command::Benchmark(options))
+    } else if options.denoise_input.is_some() {
+        Ok(Command::Denoise(options))
+    } else {
+        Ok(Command::Render(options))
+    }
+}
+
+fn invalid(option: &str, value: &str) -> Error {
+    Error::InvalidValue {
+        option: option.to_string(),
+        value: value.to_string(),
+    }
+}
+
+fn parse_positive(option: &str, value: &str) -> Result<i32, Error> {
+    match value.parse::<i32>() {
+        Ok(v) if v > 0 => Ok(v),
+        _ => Err(invalid(option, value)),
+    }
+}
+
+fn parse_seconds(option: &str, value: &str) -> Result<Duration, Error> {
+    value
+        .parse()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| invalid(option, value))
+}
+
+// Comma separated AOV names, each taken once in the order given, or "all"
+fn parse_aovs(value: &str) -> Result<Vec<Aov>, Error> {
+    if value.eq_ignore_ascii_case("all") {
+        return Ok(Aov::ALL.to_vec());
+    }
+    let mut aovs = Vec::new();
+    for name in value.split(',').map(str::trim) {
+        let aov = name
+            .parse()
+            .map_err(|()| Error::UnknownAov(name.to_string()))?;
+        if !aovs.contains(&aov) {
+            aovs.push(aov);
+        }
+    }
+    Ok(aovs)
+}
+
+// Four fractions in [0, 1], with each minimum below its maximum
+fn parse_crop(option: &str, value: &str) -> Result<[f64; 4], Error> {
+    let fractions: Vec<f64> = value
+        .split(',')
+        .map(|f| f.trim().parse::<f64>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid(option, value))?;
+    match fractions[..] {
+        [x0, y0, x1, y1]
+            if fractions.iter().all(|f| (0.0..=1.0).contains(f)) && x0 < x1 && y0 < y1 =>
+        {
+            Ok([x0, y0, x1, y1])
+        }
+        _ => Err(invalid(option, value)),
+    }
+}
+
+// Accepts either a plain ratio ("1.5") or a width:height pair ("16:9")
+fn parse_aspect_ratio(option: &str, value: &str) -> Result<f64, Error> {
+    let ratio = match value.split_once(':') {
+        Some((w, h)) => match (w.trim().parse::<f64>(), h.trim().parse::<f64>()) {
+            (Ok(w), Ok(h)) if h != 0.0 => w / h,
+            _ => return Err(invalid(option, value)),
+        },
+        None => value.parse::<f64>().map_err(|_| invalid(option, value))?,
+    };
+
+    if ratio.is_finite() && ratio > 0.0 {
+        Ok(ratio)
+    } else {
+        Err(invalid(option, value))
+    }
+}
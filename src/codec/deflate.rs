@@ -0,0 +1,544 @@
+// DEFLATE (RFC 1951) compressor and zlib (RFC 1950) wrapper, using LZ77 with hash chains and
+// a dynamic Huffman block per chunk of input
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use super::inflate::adler32;
+
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 128;
+const HASH_BITS: u32 = 15;
+// Number of LZ77 symbols gathered before a block is emitted
+const BLOCK_SYMBOLS: usize = 1 << 16;
+
+const END_OF_BLOCK: usize = 256;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+#[derive(Clone, Copy)]
+enum Symbol {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    // CM = 8 (deflate), CINFO = 7 (32K window), default compression level
+    let mut out = vec![0x78, 0x9C];
+    out.extend(deflate(data));
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::default();
+    let symbols = lz77(data);
+
+    if symbols.is_empty() {
+        // A single final block containing only an end-of-block code (fixed Huffman)
+        writer.bits(0b011, 3);
+        writer.bits(0, 7);
+    } else {
+        let blocks = symbols.chunks(BLOCK_SYMBOLS).count();
+        for (i, block) in symbols.chunks(BLOCK_SYMBOLS).enumerate() {
+            write_block(&mut writer, block, i + 1 == blocks);
+        }
+    }
+
+    writer.finish()
+}
+
+fn lz77(data: &[u8]) -> Vec<Symbol> {
+    let mut symbols = Vec::with_capacity(data.len() / 2);
+    let mut chains = HashChains::new(data);
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let (length, distance) = chains.longest_match(pos);
+
+        if length >= MIN_MATCH {
+            symbols.push(Symbol::Match {
+                length: u16::try_from(length).expect("match length fits in u16"),
+                distance: u16::try_from(distance).expect("match distance fits in u16"),
+            });
+            for p in pos..pos + length {
+                chains.insert(p);
+            }
+            pos += length;
+        } else {
+            symbols.push(Symbol::Literal(data[pos]));
+            chains.insert(pos);
+            pos += 1;
+        }
+    }
+
+    symbols
+}
+
+// Most recent position for each 3-byte hash, linked to earlier positions with the same hash
+struct HashChains<'a> {
+    data: &'a [u8],
+    head: Vec<usize>,
+    prev: Vec<usize>,
+}
+
+impl<'a> HashChains<'a> {
+    fn new(data: &'a [u8]) -> HashChains<'a> {
+        HashChains {
+            data,
+            head: vec![usize::MAX; 1 << HASH_BITS],
+            prev: vec![usize::MAX; WINDOW_SIZE],
+        }
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let d = self.data;
+        let v = u32::from(d[pos]) << 16 | u32::from(d[pos + 1]) << 8 | u32::from(d[pos + 2]);
+        (v.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert(&mut self, pos: usize) {
+        if pos + MIN_MATCH <= self.data.len() {
+            let h = self.hash(pos);
+            self.prev[pos % WINDOW_SIZE] = self.head[h];
+            self.head[h] = pos;
+        }
+    }
+
+    // Returns the (length, distance) of the longest earlier match for the bytes at `pos`
+    fn longest_match(&self, pos: usize) -> (usize, usize) {
+        if pos + MIN_MATCH > self.data.len() {
+            return (0, 0);
+        }
+
+        let max_length = MAX_MATCH.min(self.data.len() - pos);
+        let target = &self.data[pos..pos + max_length];
+        let mut best = (0, 0);
+        let mut candidate = self.head[self.hash(pos)];
+
+        for _ in 0..MAX_CHAIN {
+            if candidate == usize::MAX || pos - candidate >= WINDOW_SIZE {
+                break;
+            }
+
+            let length = self.data[candidate..]
+                .iter()
+                .zip(target)
+                .take_while(|(a, b)| a == b)
+                .count();
+            if length > best.0 {
+                best = (length, pos - candidate);
+                if length == max_length {
+                    break;
+                }
+            }
+
+            let next = self.prev[candidate % WINDOW_SIZE];
+            // Stop once the chain wraps into slots that have since been overwritten
+            if next == usize::MAX || next >= candidate {
+                break;
+            }
+            candidate = next;
+        }
+
+        best
+    }
+}
+
+fn length_code(length: u16) -> usize {
+    LENGTH_BASE
+        .iter()
+        .rposition(|&base| base <= length)
+        .expect("length >= 3")
+}
+
+fn distance_code(distance: u16) -> usize {
+    DIST_BASE
+        .iter()
+        .rposition(|&base| base <= distance)
+        .expect("distance >= 1")
+}
+
+fn write_block(writer: &mut BitWriter, symbols: &[Symbol], last: bool) {
+    let mut lit_freq = [0u32; 286];
+    let mut dist_freq = [0u32; 30];
+    for symbol in symbols {
+        match *symbol {
+            Symbol::Literal(byte) => lit_freq[usize::from(byte)] += 1,
+            Symbol::Match { length, distance } => {
+                lit_freq[257 + length_code(length)] += 1;
+                dist_freq[distance_code(distance)] += 1;
+            }
+        }
+    }
+    lit_freq[END_OF_BLOCK] = 1;
+
+    let lit_lengths = code_lengths(&lit_freq, 15);
+    let mut dist_lengths = code_lengths(&dist_freq, 15);
+    // At least one distance code must be present even if unused
+    if dist_lengths.iter().all(|&l| l == 0) {
+        dist_lengths[0] = 1;
+    }
+
+    let nlit = 257.max(lit_lengths.iter().rposition(|&l| l != 0).unwrap_or(0) + 1);
+    let ndist = 1.max(dist_lengths.iter().rposition(|&l| l != 0).unwrap_or(0) + 1);
+
+    // Run-length encode the concatenated code lengths with codes 16, 17 and 18
+    let mut all_lengths = lit_lengths[..nlit].to_vec();
+    all_lengths.extend_from_slice(&dist_lengths[..ndist]);
+    let runs = run_length_encode(&all_lengths);
+
+    let mut cl_freq = [0u32; 19];
+    for &(code, _) in &runs {
+        cl_freq[usize::from(code)] += 1;
+    }
+    let cl_lengths = code_lengths(&cl_freq, 7);
+    let ncode = 4.max(
+        CODE_LENGTH_ORDER
+            .iter()
+            .rposition(|&i| cl_lengths[i] != 0)
+            .unwrap_or(0)
+            + 1,
+    );
+
+    writer.bits(u32::from(last), 1);
+    writer.bits(2, 2);
+    writer.bits(u32::try_from(nlit - 257).expect("fits"), 5);
+    writer.bits(u32::try_from(ndist - 1).expect("fits"), 5);
+    writer.bits(u32::try_from(ncode - 4).expect("fits"), 4);
+    for &i in &CODE_LENGTH_ORDER[..ncode] {
+        writer.bits(u32::from(cl_lengths[i]), 3);
+    }
+
+    let cl_codes = canonical_codes(&cl_lengths);
+    for (code, extra) in runs {
+        let code = usize::from(code);
+        writer.huffman(cl_codes[code], cl_lengths[code]);
+        match code {
+            16 => writer.bits(u32::from(extra), 2),
+            17 => writer.bits(u32::from(extra), 3),
+            18 => writer.bits(u32::from(extra), 7),
+            _ => {}
+        }
+    }
+
+    let lit_codes = canonical_codes(&lit_lengths);
+    let dist_codes = canonical_codes(&dist_lengths);
+    for symbol in symbols {
+        match *symbol {
+            Symbol::Literal(byte) => {
+                let i = usize::from(byte);
+                writer.huffman(lit_codes[i], lit_lengths[i]);
+            }
+            Symbol::Match { length, distance } => {
+                let lc = length_code(length);
+                writer.huffman(lit_codes[257 + lc], lit_lengths[257 + lc]);
+                writer.bits(
+                    u32::from(length - LENGTH_BASE[lc]),
+                    u32::from(LENGTH_EXTRA[lc]),
+                );
+
+                let dc = distance_code(distance);
+                writer.huffman(dist_codes[dc], dist_lengths[dc]);
+                writer.bits(
+                    u32::from(distance - DIST_BASE[dc]),
+                    u32::from(DIST_EXTRA[dc]),
+                );
+            }
+        }
+    }
+    writer.huffman(lit_codes[END_OF_BLOCK], lit_lengths[END_OF_BLOCK]);
+}
+
+// Returns (code, extra bits value) pairs for the code length alphabet
+fn run_length_encode(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut runs = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let value = lengths[i];
+        let run = lengths[i..].iter().take_while(|&&l| l == value).count();
+
+        if value == 0 && run >= 3 {
+            let n = run.min(138);
+            let extra = |n: usize, base: usize| u8::try_from(n - base).expect("fits");
+            if n >= 11 {
+                runs.push((18, extra(n, 11)));
+            } else {
+                runs.push((17, extra(n, 3)));
+            }
+            i += n;
+        } else if value != 0 && run >= 4 {
+            runs.push((value, 0));
+            let n = (run - 1).min(6);
+            runs.push((16, u8::try_from(n - 3).expect("fits")));
+            i += n + 1;
+        } else {
+            runs.push((value, 0));
+            i += 1;
+        }
+    }
+    runs
+}
+
+// Builds Huffman code lengths for the given frequencies, limited to `max_bits`
+fn code_lengths(freqs: &[u32], max_bits: usize) -> Vec<u8> {
+    let mut lengths = vec![0u8; freqs.len()];
+    let used: Vec<usize> = (0..freqs.len()).filter(|&i| freqs[i] > 0).collect();
+
+    match used.len() {
+        0 => return lengths,
+        1 => {
+            // Pair the only symbol with a dummy so the code is complete, as some decoders require
+            lengths[used[0]] = 1;
+            lengths[usize::from(used[0] == 0)] = 1;
+            return lengths;
+        }
+        _ => {}
+    }
+
+    // Standard Huffman construction; node i < used.len() is a leaf
+    let mut parent = vec![0usize; used.len() * 2 - 1];
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = used
+        .iter()
+        .enumerate()
+        .map(|(node, &symbol)| Reverse((u64::from(freqs[symbol]), node)))
+        .collect();
+    let mut next = used.len();
+    while heap.len() > 1 {
+        let Reverse((wa, a)) = heap.pop().expect("heap has two nodes");
+        let Reverse((wb, b)) = heap.pop().expect("heap has two nodes");
+        parent[a] = next;
+        parent[b] = next;
+        heap.push(Reverse((wa + wb, next)));
+        next += 1;
+    }
+
+    let root = next - 1;
+    let mut depth = vec![0usize; next];
+    for node in (0..root).rev() {
+        depth[node] = depth[parent[node]] + 1;
+    }
+
+    // Limit the code lengths, keeping the Kraft sum exact
+    let mut counts = vec![0usize; max_bits + 1];
+    for &d in &depth[..used.len()] {
+        counts[d.min(max_bits)] += 1;
+    }
+    let kraft = |counts: &[usize]| -> usize {
+        (1..=max_bits)
+            .map(|len| counts[len] << (max_bits - len))
+            .sum()
+    };
+    let mut total = kraft(&counts);
+    while total > 1 << max_bits {
+        counts[max_bits] -= 1;
+        for len in (1..max_bits).rev() {
+            if counts[len] != 0 {
+                counts[len] -= 1;
+                counts[len + 1] += 2;
+                break;
+            }
+        }
+        total -= 1;
+    }
+
+    // Hand out the shortest lengths to the most frequent symbols
+    let mut by_freq = used;
+    by_freq.sort_by_key(|&symbol| Reverse(freqs[symbol]));
+    let mut symbols = by_freq.into_iter();
+    for (len, &count) in counts.iter().enumerate().skip(1) {
+        for symbol in symbols.by_ref().take(count) {
+            lengths[symbol] = u8::try_from(len).expect("length fits in u8");
+        }
+    }
+
+    lengths
+}
+
+fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+    let max = usize::from(lengths.iter().copied().max().unwrap_or(0));
+    let mut counts = vec![0u16; max + 1];
+    for &len in lengths {
+        counts[usize::from(len)] += 1;
+    }
+    counts[0] = 0;
+
+    let mut next_code = vec![0u16; max + 2];
+    let mut code = 0u16;
+    for len in 1..=max {
+        code = (code + counts[len - 1]) << 1;
+        next_code[len] = code;
+    }
+
+    lengths
+        .iter()
+        .map(|&len| {
+            if len == 0 {
+                0
+            } else {
+                let code = next_code[usize::from(len)];
+                next_code[usize::from(len)] += 1;
+                code
+            }
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    bit_buf: u64,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    fn bits(&mut self, value: u32, count: u32) {
+        self.bit_buf |= u64::from(value) << self.bit_count;
+        self.bit_count += count;
+        while self.bit_count >= 8 {
+            self.out.push(self.bit_buf.to_le_bytes()[0]);
+            self.bit_buf >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    // Huffman codes are packed starting from their most significant bit
+    fn huffman(&mut self, code: u16, len: u8) {
+        let reversed = code.reverse_bits() >> (16 - u32::from(len));
+        self.bits(u32::from(reversed), u32::from(len));
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.out.push(self.bit_buf.to_le_bytes()[0]);
+        }
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::inflate::{inflate, zlib_decompress};
+    use super::*;
+
+    // Deterministic bytes from a xorshift generator, drawn from the first `alphabet` values
+    fn noise(len: usize, alphabet: u32) -> Vec<u8> {
+        let mut state = 0x2545_F491u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                u8::try_from(state % alphabet).unwrap()
+            })
+            .collect()
+    }
+
+    fn round_trip(data: &[u8]) -> Vec<u8> {
+        let compressed = deflate(data);
+        let (out, consumed) = inflate(&compressed).unwrap();
+        assert_eq!(out, data);
+        assert_eq!(consumed, compressed.len());
+        compressed
+    }
+
+    // Type of the first block of a raw DEFLATE stream
+    fn first_block_type(compressed: &[u8]) -> u8 {
+        (compressed[0] >> 1) & 0b11
+    }
+
+    #[test]
+    fn empty_input_is_a_fixed_block() {
+        let compressed = round_trip(&[]);
+        assert_eq!(first_block_type(&compressed), 1);
+    }
+
+    #[test]
+    fn text_is_a_dynamic_block() {
+        let text = b"Hello, hello, hello! Stored, fixed and dynamic blocks.";
+        let compressed = round_trip(text);
+        assert_eq!(first_block_type(&compressed), 2);
+        assert!(compressed.len() < text.len());
+    }
+
+    #[test]
+    fn single_symbols() {
+        round_trip(b"x");
+        round_trip(&[0; 1000]);
+        round_trip(&[255; 259]);
+    }
+
+    #[test]
+    fn long_matches_and_distances() {
+        // Repeats of a block just under the window size, so matches reach back nearly 32K
+        let block = noise(WINDOW_SIZE - 10, 256);
+        let mut data = block.clone();
+        data.extend_from_slice(&block);
+        data.extend_from_slice(&block[..1000]);
+        let compressed = round_trip(&data);
+        assert!(compressed.len() < data.len() / 2);
+    }
+
+    #[test]
+    fn several_blocks() {
+        // Incompressible data gives a symbol per byte, so this spans four blocks
+        let data = noise(200_000, 256);
+        round_trip(&data);
+        round_trip(&noise(200_000, 7));
+    }
+
+    #[test]
+    fn stored_block_followed_by_dynamic_blocks() {
+        // A stored block ends on a byte boundary, so a compressed stream can follow it directly
+        let first = b"stored first, ";
+        let second = noise(5000, 16);
+        let mut stream = vec![0b000];
+        let len = u16::try_from(first.len()).unwrap();
+        stream.extend_from_slice(&len.to_le_bytes());
+        stream.extend_from_slice(&(!len).to_le_bytes());
+        stream.extend_from_slice(first);
+        stream.extend(deflate(&second));
+
+        let (out, _) = inflate(&stream).unwrap();
+        assert_eq!(out[..first.len()], *first);
+        assert_eq!(out[first.len()..], second);
+    }
+
+    #[test]
+    fn zlib_round_trip() {
+        let data = noise(10_000, 40);
+        assert_eq!(zlib_decompress(&zlib_compress(&data)).unwrap(), data);
+    }
+
+    #[test]
+    fn code_lengths_are_limited() {
+        // Fibonacci frequencies give the deepest possible Huffman tree
+        let mut freqs = vec![1u32, 1];
+        while freqs.len() < 30 {
+            freqs.push(freqs[freqs.len() - 1] + freqs[freqs.len() - 2]);
+        }
+        let lengths = code_lengths(&freqs, 15);
+        assert!(lengths.iter().all(|&len| (1..=15).contains(&len)));
+        let kraft: u32 = lengths.iter().map(|&len| 1 << (15 - len)).sum();
+        assert_eq!(kraft, 1 << 15);
+    }
+}
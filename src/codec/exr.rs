@@ -0,0 +1,571 @@
+// Single-part scanline OpenEXR reader and writer. The writer stores 32-bit float channels
+// holding an RGB image and optionally further layers of the same size; the reader also takes
+// half and unsigned int channels
+
+use super::{deflate, inflate, Error, HdrImage};
+
+pub const MAGIC: [u8; 4] = [0x76, 0x2F, 0x31, 0x01];
+const VERSION: [u8; 4] = [2, 0, 0, 0];
+const PIXEL_TYPE_UINT: i32 = 0;
+const PIXEL_TYPE_HALF: i32 = 1;
+const PIXEL_TYPE_FLOAT: i32 = 2;
+// Version flags for tiled, deep and multi-part files
+const UNSUPPORTED_FLAGS: u8 = 0x02 | 0x08 | 0x10;
+
+#[derive(Clone, Copy)]
+pub enum Compression {
+    None,
+    Zip,
+}
+
+impl Compression {
+    fn id(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Zip => 3,
+        }
+    }
+
+    fn lines_per_chunk(self) -> usize {
+        match self {
+            Compression::None => 1,
+            Compression::Zip => 16,
+        }
+    }
+}
+
+// Image stored under `name`, whose channels are called `name.X` for each X in `channels`, or
+// just X when the name is empty. The channels take the image's components in order
+pub struct Layer<'a> {
+    pub name: &'a str,
+    pub channels: &'a [&'a str],
+    pub image: &'a HdrImage,
+}
+
+pub fn encode(image: &HdrImage, compression: Compression) -> Vec<u8> {
+    encode_layers(
+        &[Layer {
+            name: "",
+            channels: &["R", "G", "B"],
+            image,
+        }],
+        compression,
+    )
+}
+
+// The layers must all be the same size
+pub fn encode_layers(layers: &[Layer], compression: Compression) -> Vec<u8> {
+    let image = layers[0].image;
+    assert!(
+        layers
+            .iter()
+            .all(|layer| layer.image.width == image.width && layer.image.height == image.height),
+        "Layers differ in size"
+    );
+
+    // Channels must be listed in alphabetical order, and their data comes in the same order
+    let mut channels: Vec<Channel> = layers
+        .iter()
+        .flat_map(|layer| {
+            layer
+                .channels
+                .iter()
+                .enumerate()
+                .map(|(component, channel)| {
+                    let name = if layer.name.is_empty() {
+                        (*channel).to_string()
+                    } else {
+                        format!("{}.{channel}", layer.name)
+                    };
+                    (name, layer.image, component)
+                })
+        })
+        .collect();
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&VERSION);
+    write_header(&mut out, &channels, image, compression);
+
+    let lines = compression.lines_per_chunk();
+    let chunks = image.height.div_ceil(lines);
+
+    // Reserve the offset table and fill it in as chunks are written
+    let table_start = out.len();
+    out.resize(table_start + chunks * 8, 0);
+
+    for chunk in 0..chunks {
+        let offset = u64::try_from(out.len()).expect("offset fits in u64");
+        out[table_start + chunk * 8..table_start + chunk * 8 + 8]
+            .copy_from_slice(&offset.to_le_bytes());
+
+        let y0 = chunk * lines;
+        let y1 = (y0 + lines).min(image.height);
+        let raw = chunk_data(&channels, image.width, y0, y1);
+        let data = match compression {
+            Compression::None => raw,
+            Compression::Zip => {
+                let compressed = zip(&raw);
+                // Chunks that do not shrink are stored uncompressed
+                if compressed.len() < raw.len() {
+                    compressed
+                } else {
+                    raw
+                }
+            }
+        };
+
+        out.extend_from_slice(&to_i32(y0).to_le_bytes());
+        out.extend_from_slice(&to_i32(data.len()).to_le_bytes());
+        out.extend_from_slice(&data);
+    }
+
+    out
+}
+
+fn to_i32(value: usize) -> i32 {
+    i32::try_from(value).expect("value fits in i32")
+}
+
+// Channels are given by name, the image holding them and their component in it
+type Channel<'a> = (String, &'a HdrImage, usize);
+
+fn write_header(
+    out: &mut Vec<u8>,
+    channels: &[Channel],
+    image: &HdrImage,
+    compression: Compression,
+) {
+    let mut list = Vec::new();
+    for (name, _, _) in channels {
+        list.extend_from_slice(name.as_bytes());
+        list.push(0);
+        list.extend_from_slice(&PIXEL_TYPE_FLOAT.to_le_bytes());
+        // pLinear and three reserved bytes, then x and y sampling
+        list.extend_from_slice(&[0, 0, 0, 0]);
+        list.extend_from_slice(&1i32.to_le_bytes());
+        list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    list.push(0);
+    attribute(out, "channels", "chlist", &list);
+
+    attribute(out, "compression", "compression", &[compression.id()]);
+
+    let mut window = Vec::new();
+    for v in [0, 0, to_i32(image.width) - 1, to_i32(image.height) - 1] {
+        window.extend_from_slice(&v.to_le_bytes());
+    }
+    attribute(out, "dataWindow", "box2i", &window);
+    attribute(out, "displayWindow", "box2i", &window);
+
+    // Increasing Y
+    attribute(out, "lineOrder", "lineOrder", &[0]);
+    attribute(out, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+
+    let mut center = Vec::new();
+    center.extend_from_slice(&0.0f32.to_le_bytes());
+    center.extend_from_slice(&0.0f32.to_le_bytes());
+    attribute(out, "screenWindowCenter", "v2f", &center);
+    attribute(out, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+
+    // End of header
+    out.push(0);
+}
+
+fn attribute(out: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    out.extend_from_slice(name.as_bytes());
+    out.push(0);
+    out.extend_from_slice(kind.as_bytes());
+    out.push(0);
+    out.extend_from_slice(&to_i32(value.len()).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+// Each scanline holds all of the samples of its first channel, then the second, and so on
+fn chunk_data(channels: &[Channel], width: usize, y0: usize, y1: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity((y1 - y0) * width * channels.len() * 4);
+    for y in y0..y1 {
+        for (_, image, component) in channels {
+            for pixel in image.row(y).chunks_exact(HdrImage::CHANNELS) {
+                data.extend_from_slice(&pixel[*component].to_le_bytes());
+            }
+        }
+    }
+    data
+}
+
+// ZIP compression splits even and odd bytes, delta encodes them, then deflates the result
+fn zip(raw: &[u8]) -> Vec<u8> {
+    let half = raw.len().div_ceil(2);
+    let mut reordered = vec![0u8; raw.len()];
+    for (i, &byte) in raw.iter().enumerate() {
+        let index = if i % 2 == 0 { i / 2 } else { half + i / 2 };
+        reordered[index] = byte;
+    }
+
+    let mut previous = reordered[0];
+    for byte in reordered.iter_mut().skip(1) {
+        let current = *byte;
+        *byte = current.wrapping_sub(previous).wrapping_add(128);
+        previous = current;
+    }
+
+    deflate::zlib_compress(&reordered)
+}
+
+// Channels of a decoded file by name, each holding its samples row by row from the top
+pub struct Channels {
+    pub width: usize,
+    pub height: usize,
+    samples: Vec<(String, Vec<f32>)>,
+}
+
+impl Channels {
+    // Image made of the channels of a layer named as `encode_layers` names them, or None if any
+    // is missing. A lone channel is repeated in all three components
+    pub fn layer(&self, name: &str, channels: &[&str]) -> Option<HdrImage> {
+        let samples: Vec<&[f32]> = channels
+            .iter()
+            .map(|channel| {
+                let full_name = if name.is_empty() {
+                    (*channel).to_string()
+                } else {
+                    format!("{name}.{channel}")
+                };
+                self.samples
+                    .iter()
+                    .find(|(n, _)| *n == full_name)
+                    .map(|(_, samples)| samples.as_slice())
+            })
+            .collect::<Option<_>>()?;
+
+        let mut image = HdrImage::new(self.width, self.height);
+        for (index, pixel) in image.data.chunks_exact_mut(HdrImage::CHANNELS).enumerate() {
+            for (component, value) in pixel.iter_mut().enumerate() {
+                *value = match samples.len() {
+                    1 => samples[0][index],
+                    n if component < n => samples[component][index],
+                    _ => 0.0,
+                };
+            }
+        }
+        Some(image)
+    }
+}
+
+struct ChannelInfo {
+    name: String,
+    pixel_type: i32,
+}
+
+impl ChannelInfo {
+    fn bytes(&self) -> usize {
+        if self.pixel_type == PIXEL_TYPE_HALF {
+            2
+        } else {
+            4
+        }
+    }
+
+    // Reads the sample at the start of `raw`
+    fn sample(&self, raw: &[u8]) -> f32 {
+        match self.pixel_type {
+            PIXEL_TYPE_HALF => half_to_f32(u16::from_le_bytes([raw[0], raw[1]])),
+            #[allow(clippy::cast_precision_loss)] // Rounding is fine
+            PIXEL_TYPE_UINT => u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f32,
+            _ => f32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]),
+        }
+    }
+}
+
+// The header attributes the reader needs
+struct Header {
+    channels: Vec<ChannelInfo>,
+    // First and last pixel, inclusive
+    window: (i32, i32, i32, i32),
+    lines_per_chunk: usize,
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Channels, Error> {
+    if !bytes.starts_with(&MAGIC) || bytes.len() < 8 {
+        return Err(Error::Malformed("not an OpenEXR file"));
+    }
+    if bytes[5] & UNSUPPORTED_FLAGS != 0 {
+        return Err(Error::Unsupported(
+            "tiled, deep or multi-part OpenEXR".to_string(),
+        ));
+    }
+
+    let mut reader = Reader { bytes, pos: 8 };
+    let header = read_header(&mut reader)?;
+    let (x0, y0, x1, y1) = header.window;
+    let size = |min: i32, max: i32| {
+        usize::try_from(i64::from(max) - i64::from(min) + 1)
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or(Error::Malformed("empty data window"))
+    };
+    let (width, height) = (size(x0, x1)?, size(y0, y1)?);
+    let lines = header.lines_per_chunk;
+
+    let line_bytes: usize = header.channels.iter().map(|c| width * c.bytes()).sum();
+    let mut samples = vec![vec![0.0; width * height]; header.channels.len()];
+    let chunks = height.div_ceil(lines);
+    // The chunks follow the offset table in order, which is all this reader relies on
+    reader.take(chunks * 8)?;
+    for _ in 0..chunks {
+        let y = usize::try_from(i64::from(reader.i32()?) - i64::from(y0))
+            .ok()
+            .filter(|&y| y < height)
+            .ok_or(Error::Malformed("chunk outside the data window"))?;
+        let size =
+            usize::try_from(reader.i32()?).map_err(|_| Error::Malformed("negative chunk size"))?;
+        let data = reader.take(size)?;
+        let count = lines.min(height - y);
+        let expected = count * line_bytes;
+        let raw = if size < expected {
+            unzip(data)?
+        } else {
+            data.to_vec()
+        };
+        if raw.len() != expected {
+            return Err(Error::Malformed("chunk has the wrong size"));
+        }
+
+        // Each scanline holds all of the samples of its first channel, then the second, and so on
+        let mut pos = 0;
+        for line in y..y + count {
+            for (channel, out) in header.channels.iter().zip(&mut samples) {
+                for value in &mut out[line * width..(line + 1) * width] {
+                    *value = channel.sample(&raw[pos..]);
+                    pos += channel.bytes();
+                }
+            }
+        }
+    }
+
+    Ok(Channels {
+        width,
+        height,
+        samples: header
+            .channels
+            .into_iter()
+            .map(|c| c.name)
+            .zip(samples)
+            .collect(),
+    })
+}
+
+fn read_header(reader: &mut Reader) -> Result<Header, Error> {
+    let mut channels = Vec::new();
+    let mut compression = None;
+    let mut window = None;
+    loop {
+        let name = reader.string()?;
+        if name.is_empty() {
+            break;
+        }
+        let _kind = reader.string()?;
+        let size = usize::try_from(reader.i32()?)
+            .map_err(|_| Error::Malformed("negative attribute size"))?;
+        let value = reader.take(size)?;
+        match name.as_str() {
+            "channels" => channels = parse_channels(value)?,
+            "compression" => compression = value.first().copied(),
+            "dataWindow" if size == 16 => {
+                let v: Vec<i32> = value
+                    .chunks_exact(4)
+                    .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect();
+                window = Some((v[0], v[1], v[2], v[3]));
+            }
+            _ => {}
+        }
+    }
+
+    if channels.is_empty() {
+        return Err(Error::Malformed("no channels"));
+    }
+    let lines_per_chunk = match compression {
+        Some(0 | 2) => 1,
+        Some(3) => 16,
+        Some(other) => return Err(Error::Unsupported(format!("OpenEXR compression {other}"))),
+        None => return Err(Error::Malformed("missing compression")),
+    };
+    Ok(Header {
+        channels,
+        window: window.ok_or(Error::Malformed("missing data window"))?,
+        lines_per_chunk,
+    })
+}
+
+fn parse_channels(value: &[u8]) -> Result<Vec<ChannelInfo>, Error> {
+    let mut reader = Reader {
+        bytes: value,
+        pos: 0,
+    };
+    let mut channels = Vec::new();
+    loop {
+        let name = reader.string()?;
+        if name.is_empty() {
+            return Ok(channels);
+        }
+        let pixel_type = reader.i32()?;
+        if !matches!(
+            pixel_type,
+            PIXEL_TYPE_UINT | PIXEL_TYPE_HALF | PIXEL_TYPE_FLOAT
+        ) {
+            return Err(Error::Malformed("unknown pixel type"));
+        }
+        // pLinear and reserved bytes
+        reader.take(4)?;
+        if reader.i32()? != 1 || reader.i32()? != 1 {
+            return Err(Error::Unsupported(
+                "subsampled OpenEXR channels".to_string(),
+            ));
+        }
+        channels.push(ChannelInfo { name, pixel_type });
+    }
+}
+
+// Reverses `zip`: inflates, undoes the delta encoding, then interleaves the two halves again
+fn unzip(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut reordered = inflate::zlib_decompress(data)?;
+    for i in 1..reordered.len() {
+        reordered[i] = reordered[i - 1]
+            .wrapping_add(reordered[i])
+            .wrapping_sub(128);
+    }
+
+    let half = reordered.len().div_ceil(2);
+    let mut raw = vec![0u8; reordered.len()];
+    for (i, byte) in raw.iter_mut().enumerate() {
+        let index = if i % 2 == 0 { i / 2 } else { half + i / 2 };
+        *byte = reordered[index];
+    }
+    Ok(raw)
+}
+
+fn half_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 == 0 { 1.0 } else { -1.0 };
+    let exponent = i32::from((bits >> 10) & 0x1F);
+    let mantissa = f32::from(bits & 0x3FF);
+    match exponent {
+        // Subnormal
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1F if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1F => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], Error> {
+        let taken = self
+            .bytes
+            .get(self.pos..self.pos + count)
+            .ok_or(Error::Malformed("OpenEXR file is truncated"))?;
+        self.pos += count;
+        Ok(taken)
+    }
+
+    fn i32(&mut self) -> Result<i32, Error> {
+        let b = self.take(4)?;
+        Ok(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    // Null terminated
+    fn string(&mut self) -> Result<String, Error> {
+        let length = self.bytes[self.pos..]
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or(Error::Malformed("OpenEXR file is truncated"))?;
+        let string = String::from_utf8_lossy(self.take(length)?).into_owned();
+        self.pos += 1;
+        Ok(string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // More rows than a ZIP chunk holds, so the last chunk is a partial one
+    fn image() -> HdrImage {
+        let mut image = HdrImage::new(7, 19);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let t = f32::from(u16::try_from(x * 19 + y).unwrap());
+                image.set(x, y, [t, -t * 0.25, 1.0 / (t + 1.0)]);
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let image = image();
+        for compression in [Compression::None, Compression::Zip] {
+            let channels = decode(&encode(&image, compression)).unwrap();
+            assert_eq!((channels.width, channels.height), (7, 19));
+            let decoded = channels.layer("", &["R", "G", "B"]).unwrap();
+            assert_eq!(decoded.data, image.data);
+        }
+    }
+
+    #[test]
+    fn layers_round_trip() {
+        let image = image();
+        let mut depth = HdrImage::new(7, 19);
+        depth.set(3, 4, [2.5; 3]);
+        let bytes = encode_layers(
+            &[
+                Layer {
+                    name: "",
+                    channels: &["R", "G", "B"],
+                    image: &image,
+                },
+                Layer {
+                    name: "depth",
+                    channels: &["Z"],
+                    image: &depth,
+                },
+            ],
+            Compression::Zip,
+        );
+
+        let channels = decode(&bytes).unwrap();
+        assert_eq!(
+            channels.layer("", &["R", "G", "B"]).unwrap().data,
+            image.data
+        );
+        assert_eq!(channels.layer("depth", &["Z"]).unwrap().data, depth.data);
+        assert!(channels.layer("normal", &["X", "Y", "Z"]).is_none());
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)] // Halves convert to floats exactly
+    fn half_floats() {
+        assert_eq!(half_to_f32(0x3C00), 1.0);
+        assert_eq!(half_to_f32(0xC000), -2.0);
+        assert_eq!(half_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(half_to_f32(0x7C00), f32::INFINITY);
+        assert!(half_to_f32(0x7E00).is_nan());
+    }
+
+    #[test]
+    fn not_an_exr() {
+        assert!(matches!(
+            decode(b"PF\n1 1\n"),
+            Err(Error::Malformed("not an OpenEXR file"))
+        ));
+        let bytes = encode(&image(), Compression::Zip);
+        assert!(decode(&bytes[..bytes.len() / 2]).is_err());
+    }
+}
@@ -0,0 +1,428 @@
+// DEFLATE (RFC 1951) decompressor and zlib (RFC 1950) wrapper
+
+use super::Error;
+
+const MAX_BITS: usize = 15;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// Order in which code length code lengths are stored in a dynamic block header
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    if data.len() < 6 {
+        return Err(Error::Malformed("zlib stream is truncated"));
+    }
+
+    let cmf = data[0];
+    let flg = data[1];
+    if cmf & 0x0F != 8 || (u16::from(cmf) << 8 | u16::from(flg)) % 31 != 0 {
+        return Err(Error::Malformed("invalid zlib header"));
+    }
+    if flg & 0x20 != 0 {
+        return Err(Error::Unsupported("zlib preset dictionary".to_string()));
+    }
+
+    let (out, consumed) = inflate(&data[2..])?;
+
+    let trailer = data
+        .get(2 + consumed..2 + consumed + 4)
+        .ok_or(Error::Malformed("zlib stream is missing its checksum"))?;
+    let expected = u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    if adler32(&out) != expected {
+        return Err(Error::Malformed("zlib checksum mismatch"));
+    }
+
+    Ok(out)
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+    // 5552 is the largest chunk that cannot overflow b before the modulo
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+// Inflates a raw DEFLATE stream, returning the output and the number of input bytes consumed
+pub fn inflate(data: &[u8]) -> Result<(Vec<u8>, usize), Error> {
+    let mut input = BitReader::new(data);
+    let mut out = Vec::new();
+
+    loop {
+        let last = input.bits(1)? == 1;
+        match input.bits(2)? {
+            0 => stored_block(&mut input, &mut out)?,
+            1 => {
+                let (lit, dist) = fixed_tables();
+                compressed_block(&mut input, &mut out, &lit, &dist)?;
+            }
+            2 => {
+                let (lit, dist) = dynamic_tables(&mut input)?;
+                compressed_block(&mut input, &mut out, &lit, &dist)?;
+            }
+            _ => return Err(Error::Malformed("invalid deflate block type")),
+        }
+
+        if last {
+            break;
+        }
+    }
+
+    Ok((out, input.bytes_consumed()))
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit_buf: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data,
+            pos: 0,
+            bit_buf: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn bits(&mut self, need: u32) -> Result<u32, Error> {
+        while self.bit_count < need {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or(Error::Malformed("deflate stream is truncated"))?;
+            self.pos += 1;
+            self.bit_buf |= u32::from(byte) << self.bit_count;
+            self.bit_count += 8;
+        }
+
+        let value = self.bit_buf & ((1 << need) - 1);
+        self.bit_buf >>= need;
+        self.bit_count -= need;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.bit_buf = 0;
+        self.bit_count = 0;
+    }
+
+    fn bytes_consumed(&self) -> usize {
+        self.pos - (self.bit_count / 8) as usize
+    }
+}
+
+// Canonical Huffman decoding table, stored as code counts per length and symbols ordered by code
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, Error> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[usize::from(len)] += 1;
+        }
+        counts[0] = 0;
+
+        // Reject over-subscribed code sets; incomplete sets are allowed by the spec
+        let mut left: i32 = 1;
+        for &count in &counts[1..] {
+            left = (left << 1) - i32::from(count);
+            if left < 0 {
+                return Err(Error::Malformed("over-subscribed huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                let slot = &mut offsets[usize::from(len)];
+                symbols[usize::from(*slot)] =
+                    u16::try_from(symbol).expect("huffman alphabets fit in u16");
+                *slot += 1;
+            }
+        }
+
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, input: &mut BitReader) -> Result<u16, Error> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+
+        for &count in &self.counts[1..] {
+            #[allow(clippy::cast_possible_wrap)] // Single bit
+            let bit = input.bits(1)? as i32;
+            code |= bit;
+            let count = i32::from(count);
+            if code - count < first {
+                #[allow(clippy::cast_sign_loss)] // Non-negative by construction
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+
+        Err(Error::Malformed("invalid huffman code"))
+    }
+}
+
+fn stored_block(input: &mut BitReader, out: &mut Vec<u8>) -> Result<(), Error> {
+    input.align_to_byte();
+    let start = input.pos;
+    let header = input
+        .data
+        .get(start..start + 4)
+        .ok_or(Error::Malformed("deflate stream is truncated"))?;
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    let nlen = u16::from_le_bytes([header[2], header[3]]);
+    if len != !nlen {
+        return Err(Error::Malformed("stored block length mismatch"));
+    }
+
+    let body_start = start + 4;
+    let body = input
+        .data
+        .get(body_start..body_start + usize::from(len))
+        .ok_or(Error::Malformed("deflate stream is truncated"))?;
+    out.extend_from_slice(body);
+    input.pos = body_start + usize::from(len);
+
+    Ok(())
+}
+
+fn fixed_tables() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+
+    (
+        Huffman::new(&lengths).expect("fixed literal table is valid"),
+        Huffman::new(&[5; 30]).expect("fixed distance table is valid"),
+    )
+}
+
+fn dynamic_tables(input: &mut BitReader) -> Result<(Huffman, Huffman), Error> {
+    let nlen = input.bits(5)? as usize + 257;
+    let ndist = input.bits(5)? as usize + 1;
+    let ncode = input.bits(4)? as usize + 4;
+    if nlen > 286 || ndist > 30 {
+        return Err(Error::Malformed("too many deflate codes"));
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..ncode] {
+        code_lengths[index] = u8::try_from(input.bits(3)?).expect("3 bits fit in u8");
+    }
+    let code_table = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![0u8; nlen + ndist];
+    let mut index = 0;
+    while index < nlen + ndist {
+        let symbol = code_table.decode(input)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (u8::try_from(symbol).expect("code lengths fit in u8"), 1),
+            16 => {
+                if index == 0 {
+                    return Err(Error::Malformed("repeat with no previous length"));
+                }
+                (lengths[index - 1], 3 + input.bits(2)? as usize)
+            }
+            17 => (0, 3 + input.bits(3)? as usize),
+            _ => (0, 11 + input.bits(7)? as usize),
+        };
+
+        if index + repeat > nlen + ndist {
+            return Err(Error::Malformed("too many code lengths"));
+        }
+        lengths[index..index + repeat].fill(value);
+        index += repeat;
+    }
+
+    if lengths[256] == 0 {
+        return Err(Error::Malformed("missing end-of-block code"));
+    }
+
+    Ok((
+        Huffman::new(&lengths[..nlen])?,
+        Huffman::new(&lengths[nlen..])?,
+    ))
+}
+
+fn compressed_block(
+    input: &mut BitReader,
+    out: &mut Vec<u8>,
+    lit: &Huffman,
+    dist: &Huffman,
+) -> Result<(), Error> {
+    loop {
+        let symbol = usize::from(lit.decode(input)?);
+        match symbol {
+            0..=255 => out.push(u8::try_from(symbol).expect("literal fits in u8")),
+            256 => return Ok(()),
+            _ => {
+                let symbol = symbol - 257;
+                if symbol >= LENGTH_BASE.len() {
+                    return Err(Error::Malformed("invalid length code"));
+                }
+                let len = usize::from(LENGTH_BASE[symbol])
+                    + input.bits(u32::from(LENGTH_EXTRA[symbol]))? as usize;
+
+                let symbol = usize::from(dist.decode(input)?);
+                if symbol >= DIST_BASE.len() {
+                    return Err(Error::Malformed("invalid distance code"));
+                }
+                let distance = usize::from(DIST_BASE[symbol])
+                    + input.bits(u32::from(DIST_EXTRA[symbol]))? as usize;
+                if distance > out.len() {
+                    return Err(Error::Malformed("distance too far back"));
+                }
+
+                // Copies may overlap their own output, so go byte by byte
+                let start = out.len() - distance;
+                for i in 0..len {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &[u8] = b"Hello, hello, hello! Stored, fixed and dynamic blocks.";
+
+    // TEXT as zlib writes it with compression level 0, which stores it in a single block
+    fn stored() -> Vec<u8> {
+        let mut data = vec![0x78, 0x01, 0x01, 0x36, 0x00, 0xc9, 0xff];
+        data.extend_from_slice(TEXT);
+        data.extend_from_slice(&[0x00, 0x5a, 0x12, 0xe7]);
+        data
+    }
+
+    // TEXT as zlib writes it with the fixed Huffman codes
+    const FIXED: [u8; 52] = [
+        0x78, 0x01, 0xf3, 0x48, 0xcd, 0xc9, 0xc9, 0xd7, 0x51, 0xc8, 0x40, 0xa2, 0x14, 0x15, 0x82,
+        0x4b, 0xf2, 0x8b, 0x52, 0x53, 0x74, 0x14, 0xd2, 0x32, 0x2b, 0x52, 0x53, 0x14, 0x12, 0xf3,
+        0x52, 0x14, 0x52, 0x2a, 0xf3, 0x12, 0x73, 0x33, 0x93, 0x15, 0x92, 0x72, 0xf2, 0x93, 0xb3,
+        0x8b, 0xf5, 0x00, 0x00, 0x5a, 0x12, 0xe7,
+    ];
+
+    const LONG_TEXT: &[u8] =
+        b"It was the best of times, it was the worst of times, it was the age \
+        of wisdom, it was the age of foolishness, it was the epoch of belief, it was the epoch of \
+        incredulity, it was the season of Light, it was the season of Darkness.";
+
+    // LONG_TEXT as zlib writes it with compression level 9, which picks a dynamic block
+    const DYNAMIC: [u8; 107] = [
+        0x78, 0xda, 0x75, 0xcd, 0xc1, 0x09, 0x80, 0x30, 0x10, 0x44, 0xd1, 0x56, 0xb6, 0x00, 0xb1,
+        0x0a, 0x2f, 0x82, 0x4d, 0x24, 0x3a, 0x31, 0x8b, 0x31, 0x2b, 0xd9, 0x95, 0x60, 0xf7, 0x92,
+        0x93, 0x08, 0x7a, 0x7e, 0x7f, 0x98, 0xd1, 0xa8, 0x3a, 0x25, 0x8b, 0x20, 0x0f, 0x35, 0x92,
+        0x40, 0xc6, 0x3b, 0xb4, 0x23, 0x7e, 0xa4, 0x4a, 0xf9, 0x23, 0xb7, 0xa2, 0x41, 0x65, 0x5d,
+        0x64, 0xff, 0x92, 0x20, 0x92, 0x58, 0x63, 0x86, 0xbe, 0x87, 0x38, 0x64, 0x8e, 0x2d, 0xf0,
+        0x48, 0x8c, 0xf0, 0x6d, 0x9c, 0xe7, 0x82, 0xe5, 0x4c, 0x6c, 0xd7, 0x2b, 0x50, 0x38, 0x95,
+        0xdc, 0x8a, 0x89, 0xd7, 0x68, 0x3f, 0x36, 0xb8, 0xb2, 0xb5, 0xdf, 0xfe, 0x06, 0xef, 0xfe,
+        0x50, 0x54,
+    ];
+
+    #[test]
+    fn stored_block() {
+        assert_eq!(zlib_decompress(&stored()).unwrap(), TEXT);
+    }
+
+    #[test]
+    fn fixed_block() {
+        assert_eq!(zlib_decompress(&FIXED).unwrap(), TEXT);
+    }
+
+    #[test]
+    fn dynamic_block() {
+        assert_eq!(zlib_decompress(&DYNAMIC).unwrap(), LONG_TEXT);
+    }
+
+    #[test]
+    fn consumed_bytes_exclude_the_checksum() {
+        let (out, consumed) = inflate(&FIXED[2..]).unwrap();
+        assert_eq!(out, TEXT);
+        assert_eq!(consumed, FIXED.len() - 6);
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let mut data = FIXED;
+        data[FIXED.len() - 1] ^= 1;
+        assert!(matches!(
+            zlib_decompress(&data),
+            Err(Error::Malformed("zlib checksum mismatch"))
+        ));
+    }
+
+    #[test]
+    fn truncated_stream() {
+        assert!(matches!(
+            zlib_decompress(&DYNAMIC[..DYNAMIC.len() / 2]),
+            Err(Error::Malformed("deflate stream is truncated"))
+        ));
+        assert!(matches!(
+            zlib_decompress(&stored()[..20]),
+            Err(Error::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_header() {
+        let mut data = FIXED;
+        data[1] = 0x02;
+        assert!(matches!(
+            zlib_decompress(&data),
+            Err(Error::Malformed("invalid zlib header"))
+        ));
+    }
+
+    #[test]
+    fn adler32_of_known_text() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+}
@@ -0,0 +1,656 @@
+// Baseline (sequential, Huffman coded, 8-bit) JPEG decoder
+
+use super::{check_size, Bitmap, Error};
+
+pub const SIGNATURE: [u8; 2] = [0xFF, 0xD8];
+
+// Maps a coefficient's position in the zig-zag stream to its row-major block index
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+struct Huffman {
+    max_code: [i32; 18],
+    val_offset: [i32; 17],
+    values: Vec<u8>,
+}
+
+impl Huffman {
+    fn new(counts: &[u8; 16], values: Vec<u8>) -> Huffman {
+        let mut max_code = [-1; 18];
+        let mut val_offset = [0; 17];
+        let mut code = 0;
+        let mut k = 0;
+
+        for len in 1..=16 {
+            let count = i32::from(counts[len - 1]);
+            val_offset[len] = k - code;
+            code += count;
+            k += count;
+            if count > 0 {
+                max_code[len] = code - 1;
+            }
+            code <<= 1;
+        }
+        // Sentinel so decoding always terminates
+        max_code[17] = i32::MAX;
+
+        Huffman {
+            max_code,
+            val_offset,
+            values,
+        }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u8, Error> {
+        let mut code = 0;
+        for len in 1..=16 {
+            code = (code << 1) | reader.bit();
+            if code <= self.max_code[len] {
+                return usize::try_from(code + self.val_offset[len])
+                    .ok()
+                    .and_then(|i| self.values.get(i).copied())
+                    .ok_or(Error::Malformed("invalid huffman code"));
+            }
+        }
+        Err(Error::Malformed("invalid huffman code"))
+    }
+}
+
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    quant_table: usize,
+    dc_table: usize,
+    ac_table: usize,
+    dc_pred: i32,
+    // Decoded samples, padded out to a whole number of MCUs
+    plane: Vec<u8>,
+    stride: usize,
+}
+
+struct Frame {
+    width: usize,
+    height: usize,
+    h_max: usize,
+    v_max: usize,
+    mcus_x: usize,
+    mcus_y: usize,
+    components: Vec<Component>,
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    quant: [[u16; 64]; 4],
+    dc_tables: [Option<Huffman>; 4],
+    ac_tables: [Option<Huffman>; 4],
+    restart_interval: usize,
+    frame: Option<Frame>,
+    adobe_transform: Option<u8>,
+    idct_table: [[f32; 8]; 8],
+}
+
+pub fn decode(data: &[u8]) -> Result<Bitmap, Error> {
+    let mut decoder = Decoder {
+        data,
+        pos: SIGNATURE.len(),
+        quant: [[0; 64]; 4],
+        dc_tables: [None, None, None, None],
+        ac_tables: [None, None, None, None],
+        restart_interval: 0,
+        frame: None,
+        adobe_transform: None,
+        idct_table: idct_table(),
+    };
+
+    loop {
+        match decoder.next_marker()? {
+            0xC0 | 0xC1 => decoder.read_frame()?,
+            marker @ (0xC2..=0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF) => {
+                return Err(Error::Unsupported(format!(
+                    "jpeg coding process SOF{} (only baseline is supported)",
+                    marker - 0xC0
+                )));
+            }
+            0xC4 => decoder.read_huffman_tables()?,
+            0xDB => decoder.read_quant_tables()?,
+            0xDD => decoder.read_restart_interval()?,
+            0xDA => decoder.read_scan()?,
+            0xEE => decoder.read_adobe()?,
+            0xD9 => break,
+            _ => {
+                let segment = decoder.segment()?;
+                decoder.pos += segment.len();
+            }
+        }
+    }
+
+    let frame = decoder
+        .frame
+        .ok_or(Error::Malformed("jpeg has no frame header"))?;
+    Ok(to_rgb(&frame, decoder.adobe_transform))
+}
+
+impl<'a> Decoder<'a> {
+    fn byte(&mut self) -> Result<u8, Error> {
+        let b = *self
+            .data
+            .get(self.pos)
+            .ok_or(Error::Malformed("jpeg is truncated"))?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn next_marker(&mut self) -> Result<u8, Error> {
+        if self.byte()? != 0xFF {
+            return Err(Error::Malformed("expected a jpeg marker"));
+        }
+        // Any number of 0xFF fill bytes may precede a marker
+        let mut marker = self.byte()?;
+        while marker == 0xFF {
+            marker = self.byte()?;
+        }
+        Ok(marker)
+    }
+
+    // Returns the body of the current marker segment without advancing past it
+    fn segment(&mut self) -> Result<&'a [u8], Error> {
+        let hi = usize::from(self.byte()?);
+        let lo = usize::from(self.byte()?);
+        let len = (hi << 8 | lo)
+            .checked_sub(2)
+            .ok_or(Error::Malformed("invalid jpeg segment length"))?;
+        self.data
+            .get(self.pos..self.pos + len)
+            .ok_or(Error::Malformed("jpeg segment is truncated"))
+    }
+
+    fn read_quant_tables(&mut self) -> Result<(), Error> {
+        let segment = self.segment()?;
+        let len = segment.len();
+        let mut i = 0;
+        let mut tables = Vec::new();
+        while i < len {
+            let precision = segment[i] >> 4;
+            let id = usize::from(segment[i] & 0x0F);
+            i += 1;
+            if id > 3 {
+                return Err(Error::Malformed("invalid quantization table id"));
+            }
+
+            let mut table = [0u16; 64];
+            for value in &mut table {
+                if precision == 0 {
+                    *value = u16::from(*segment.get(i).ok_or(Error::Malformed("dqt truncated"))?);
+                    i += 1;
+                } else {
+                    let bytes = segment
+                        .get(i..i + 2)
+                        .ok_or(Error::Malformed("dqt truncated"))?;
+                    *value = u16::from_be_bytes([bytes[0], bytes[1]]);
+                    i += 2;
+                }
+            }
+            tables.push((id, table));
+        }
+
+        for (id, table) in tables {
+            self.quant[id] = table;
+        }
+        self.pos += len;
+        Ok(())
+    }
+
+    fn read_huffman_tables(&mut self) -> Result<(), Error> {
+        let segment = self.segment()?;
+        let len = segment.len();
+        let mut i = 0;
+        let mut tables = Vec::new();
+        while i < len {
+            let class = segment[i] >> 4;
+            let id = usize::from(segment[i] & 0x0F);
+            if class > 1 || id > 3 {
+                return Err(Error::Malformed("invalid huffman table id"));
+            }
+
+            let mut counts = [0u8; 16];
+            counts.copy_from_slice(
+                segment
+                    .get(i + 1..i + 17)
+                    .ok_or(Error::Malformed("dht truncated"))?,
+            );
+            i += 17;
+
+            let total: usize = counts.iter().map(|&c| usize::from(c)).sum();
+            let values = segment
+                .get(i..i + total)
+                .ok_or(Error::Malformed("dht truncated"))?
+                .to_vec();
+            i += total;
+
+            tables.push((class, id, Huffman::new(&counts, values)));
+        }
+
+        for (class, id, table) in tables {
+            if class == 0 {
+                self.dc_tables[id] = Some(table);
+            } else {
+                self.ac_tables[id] = Some(table);
+            }
+        }
+        self.pos += len;
+        Ok(())
+    }
+
+    fn read_restart_interval(&mut self) -> Result<(), Error> {
+        let segment = self.segment()?;
+        if segment.len() < 2 {
+            return Err(Error::Malformed("dri truncated"));
+        }
+        self.restart_interval = usize::from(u16::from_be_bytes([segment[0], segment[1]]));
+        self.pos += segment.len();
+        Ok(())
+    }
+
+    fn read_adobe(&mut self) -> Result<(), Error> {
+        let segment = self.segment()?;
+        if segment.len() >= 12 && segment.starts_with(b"Adobe") {
+            self.adobe_transform = Some(segment[11]);
+        }
+        self.pos += segment.len();
+        Ok(())
+    }
+
+    fn read_frame(&mut self) -> Result<(), Error> {
+        let segment = self.segment()?;
+        let len = segment.len();
+        if len < 6 {
+            return Err(Error::Malformed("sof truncated"));
+        }
+        if segment[0] != 8 {
+            return Err(Error::Unsupported(format!(
+                "{}-bit jpeg samples",
+                segment[0]
+            )));
+        }
+
+        let height = usize::from(u16::from_be_bytes([segment[1], segment[2]]));
+        let width = usize::from(u16::from_be_bytes([segment[3], segment[4]]));
+        let count = usize::from(segment[5]);
+        if width == 0 || height == 0 {
+            return Err(Error::Unsupported("jpeg with deferred height".to_string()));
+        }
+        check_size(width, height)?;
+        if count != 1 && count != 3 {
+            return Err(Error::Unsupported(format!("jpeg with {count} components")));
+        }
+
+        let mut components = Vec::with_capacity(count);
+        for c in 0..count {
+            let spec = segment
+                .get(6 + c * 3..9 + c * 3)
+                .ok_or(Error::Malformed("sof truncated"))?;
+            let h = usize::from(spec[1] >> 4);
+            let v = usize::from(spec[1] & 0x0F);
+            if !(1..=4).contains(&h) || !(1..=4).contains(&v) || spec[2] > 3 {
+                return Err(Error::Malformed("invalid jpeg component"));
+            }
+            components.push(Component {
+                id: spec[0],
+                h,
+                v,
+                quant_table: usize::from(spec[2]),
+                dc_table: 0,
+                ac_table: 0,
+                dc_pred: 0,
+                plane: Vec::new(),
+                stride: 0,
+            });
+        }
+
+        let h_max = components.iter().map(|c| c.h).max().unwrap_or(1);
+        let v_max = components.iter().map(|c| c.v).max().unwrap_or(1);
+        let mcus_x = width.div_ceil(8 * h_max);
+        let mcus_y = height.div_ceil(8 * v_max);
+        for c in &mut components {
+            c.stride = mcus_x * c.h * 8;
+            c.plane = vec![0; c.stride * mcus_y * c.v * 8];
+        }
+
+        self.frame = Some(Frame {
+            width,
+            height,
+            h_max,
+            v_max,
+            mcus_x,
+            mcus_y,
+            components,
+        });
+        self.pos += len;
+        Ok(())
+    }
+
+    fn read_scan(&mut self) -> Result<(), Error> {
+        let segment = self.segment()?;
+        let len = segment.len();
+        let count = usize::from(*segment.first().ok_or(Error::Malformed("sos truncated"))?);
+        if count == 0 || len < 1 + count * 2 + 3 {
+            return Err(Error::Malformed("sos truncated"));
+        }
+
+        let mut frame = self
+            .frame
+            .take()
+            .ok_or(Error::Malformed("jpeg scan before frame header"))?;
+
+        let mut scan = Vec::with_capacity(count);
+        for i in 0..count {
+            let id = segment[1 + i * 2];
+            let tables = segment[2 + i * 2];
+            let index = frame
+                .components
+                .iter()
+                .position(|c| c.id == id)
+                .ok_or(Error::Malformed("scan references unknown component"))?;
+            let component = &mut frame.components[index];
+            component.dc_table = usize::from(tables >> 4) & 3;
+            component.ac_table = usize::from(tables & 0x0F) & 3;
+            component.dc_pred = 0;
+            scan.push(index);
+        }
+
+        self.pos += len;
+        let mut reader = BitReader::new(self.data, self.pos);
+        let result = self.decode_scan(&mut frame, &scan, &mut reader);
+        self.pos = reader.marker_pos();
+        self.frame = Some(frame);
+        result
+    }
+
+    fn decode_scan(
+        &self,
+        frame: &mut Frame,
+        scan: &[usize],
+        reader: &mut BitReader,
+    ) -> Result<(), Error> {
+        // A single-component scan is not interleaved and covers just that component's blocks
+        let (units_x, units_y) = if scan.len() == 1 {
+            let c = &frame.components[scan[0]];
+            (
+                (frame.width * c.h).div_ceil(8 * frame.h_max),
+                (frame.height * c.v).div_ceil(8 * frame.v_max),
+            )
+        } else {
+            (frame.mcus_x, frame.mcus_y)
+        };
+
+        let mut block = [0i32; 64];
+        let mut samples = [0u8; 64];
+
+        for unit in 0..units_x * units_y {
+            if self.restart_interval > 0 && unit > 0 && unit % self.restart_interval == 0 {
+                reader.restart()?;
+                for &index in scan {
+                    frame.components[index].dc_pred = 0;
+                }
+            }
+
+            let (ux, uy) = (unit % units_x, unit / units_x);
+            for &index in scan {
+                let component = &mut frame.components[index];
+                let (bh, bv) = if scan.len() == 1 {
+                    (1, 1)
+                } else {
+                    (component.h, component.v)
+                };
+
+                for by in 0..bv {
+                    for bx in 0..bh {
+                        self.decode_block(component, reader, &mut block)?;
+                        idct(&self.idct_table, &block, &mut samples);
+
+                        let x0 = (ux * bh + bx) * 8;
+                        let y0 = (uy * bv + by) * 8;
+                        for (row, chunk) in samples.chunks_exact(8).enumerate() {
+                            let start = (y0 + row) * component.stride + x0;
+                            component.plane[start..start + 8].copy_from_slice(chunk);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn decode_block(
+        &self,
+        component: &mut Component,
+        reader: &mut BitReader,
+        block: &mut [i32; 64],
+    ) -> Result<(), Error> {
+        let dc_table = self.dc_tables[component.dc_table]
+            .as_ref()
+            .ok_or(Error::Malformed("scan references missing huffman table"))?;
+        let ac_table = self.ac_tables[component.ac_table]
+            .as_ref()
+            .ok_or(Error::Malformed("scan references missing huffman table"))?;
+        let quant = &self.quant[component.quant_table];
+
+        block.fill(0);
+
+        let size = dc_table.decode(reader)?;
+        component.dc_pred += reader.receive_extend(size);
+        block[0] = component.dc_pred * i32::from(quant[0]);
+
+        let mut k = 1;
+        while k < 64 {
+            let rs = ac_table.decode(reader)?;
+            let run = usize::from(rs >> 4);
+            let size = rs & 0x0F;
+
+            if size == 0 {
+                if run != 15 {
+                    // End of block
+                    break;
+                }
+                k += 16;
+                continue;
+            }
+
+            k += run;
+            if k > 63 {
+                return Err(Error::Malformed("jpeg coefficient index out of range"));
+            }
+            block[ZIGZAG[k]] = reader.receive_extend(size) * i32::from(quant[k]);
+            k += 1;
+        }
+
+        Ok(())
+    }
+}
+
+// Reads entropy-coded data, removing 0xFF00 byte stuffing and stopping at the next marker
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bits: u32,
+    count: u32,
+    at_marker: bool,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> BitReader<'a> {
+        BitReader {
+            data,
+            pos,
+            bits: 0,
+            count: 0,
+            at_marker: false,
+        }
+    }
+
+    fn fill(&mut self) {
+        while self.count <= 24 {
+            let mut byte = 0;
+            if !self.at_marker && self.pos < self.data.len() {
+                byte = self.data[self.pos];
+                if byte == 0xFF {
+                    if self.data.get(self.pos + 1) == Some(&0) {
+                        self.pos += 2;
+                    } else {
+                        self.at_marker = true;
+                        byte = 0;
+                    }
+                } else {
+                    self.pos += 1;
+                }
+            }
+            self.bits |= u32::from(byte) << (24 - self.count);
+            self.count += 8;
+        }
+    }
+
+    fn bit(&mut self) -> i32 {
+        self.bits(1)
+    }
+
+    fn bits(&mut self, n: u8) -> i32 {
+        if n == 0 {
+            return 0;
+        }
+        self.fill();
+        let value = self.bits >> (32 - u32::from(n));
+        self.bits <<= n;
+        self.count -= u32::from(n);
+        i32::try_from(value).expect("at most 16 bits")
+    }
+
+    // Reads an n-bit magnitude category value and sign extends it
+    fn receive_extend(&mut self, n: u8) -> i32 {
+        let value = self.bits(n.min(16));
+        if n > 0 && value < 1 << (n - 1) {
+            value - (1 << n) + 1
+        } else {
+            value
+        }
+    }
+
+    // Consumes an RSTn marker and resets the bit buffer
+    fn restart(&mut self) -> Result<(), Error> {
+        self.bits = 0;
+        self.count = 0;
+        self.at_marker = false;
+        match self.data.get(self.pos..self.pos + 2) {
+            Some([0xFF, 0xD0..=0xD7]) => {
+                self.pos += 2;
+                Ok(())
+            }
+            _ => Err(Error::Malformed("missing jpeg restart marker")),
+        }
+    }
+
+    // Position of the marker that ended the scan
+    fn marker_pos(&self) -> usize {
+        let mut pos = self.pos;
+        while pos + 1 < self.data.len() {
+            if self.data[pos] == 0xFF && self.data[pos + 1] != 0 && self.data[pos + 1] != 0xFF {
+                if !(0xD0..=0xD7).contains(&self.data[pos + 1]) {
+                    return pos;
+                }
+                pos += 2;
+            } else {
+                pos += 1;
+            }
+        }
+        self.data.len()
+    }
+}
+
+fn idct_table() -> [[f32; 8]; 8] {
+    let mut table = [[0.0; 8]; 8];
+    for (x, row) in table.iter_mut().enumerate() {
+        for (u, value) in row.iter_mut().enumerate() {
+            let scale = if u == 0 {
+                std::f32::consts::FRAC_1_SQRT_2
+            } else {
+                1.0
+            };
+            #[allow(clippy::cast_precision_loss)] // Small indices
+            let angle = (2 * x + 1) as f32 * u as f32 * std::f32::consts::PI / 16.0;
+            *value = scale * angle.cos() / 2.0;
+        }
+    }
+    table
+}
+
+// Separable 2D inverse DCT, level shifted back to the 0..255 range
+fn idct(table: &[[f32; 8]; 8], block: &[i32; 64], out: &mut [u8; 64]) {
+    let mut temp = [0.0f32; 64];
+    for y in 0..8 {
+        for x in 0..8 {
+            let mut sum = 0.0;
+            for u in 0..8 {
+                #[allow(clippy::cast_precision_loss)] // Dequantized coefficients are small
+                let coefficient = block[y * 8 + u] as f32;
+                sum += table[x][u] * coefficient;
+            }
+            temp[y * 8 + x] = sum;
+        }
+    }
+
+    for x in 0..8 {
+        for y in 0..8 {
+            let mut sum = 0.0;
+            for v in 0..8 {
+                sum += table[y][v] * temp[v * 8 + x];
+            }
+            out[y * 8 + x] = clamp_to_byte(sum + 128.0);
+        }
+    }
+}
+
+fn clamp_to_byte(value: f32) -> u8 {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // Clamped first
+    let byte = value.round().clamp(0.0, 255.0) as u8;
+    byte
+}
+
+fn to_rgb(frame: &Frame, adobe_transform: Option<u8>) -> Bitmap {
+    let mut bitmap = Bitmap::new(frame.width, frame.height);
+
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            // Nearest-neighbour upsampling of subsampled components
+            let sample =
+                |c: &Component| c.plane[(y * c.v / frame.v_max) * c.stride + x * c.h / frame.h_max];
+
+            let rgb = if frame.components.len() == 1 {
+                let gray = sample(&frame.components[0]);
+                [gray, gray, gray]
+            } else if adobe_transform == Some(0) {
+                [
+                    sample(&frame.components[0]),
+                    sample(&frame.components[1]),
+                    sample(&frame.components[2]),
+                ]
+            } else {
+                let luma = f32::from(sample(&frame.components[0]));
+                let cb = f32::from(sample(&frame.components[1])) - 128.0;
+                let cr = f32::from(sample(&frame.components[2])) - 128.0;
+                [
+                    clamp_to_byte(luma + 1.402 * cr),
+                    clamp_to_byte(luma - 0.344_136 * cb - 0.714_136 * cr),
+                    clamp_to_byte(luma + 1.772 * cb),
+                ]
+            };
+            bitmap.set(x, y, rgb);
+        }
+    }
+
+    bitmap
+}
@@ -0,0 +1,159 @@
+pub mod deflate;
+pub mod exr;
+pub mod inflate;
+pub mod jpeg;
+pub mod pfm;
+pub mod png;
+pub mod ppm;
+pub mod rgbe;
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// An 8-bit RGB image, stored row-major from the top-left corner
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+}
+
+impl Bitmap {
+    pub const BYTES_PER_PIXEL: usize = 3;
+
+    pub fn new(width: usize, height: usize) -> Bitmap {
+        Bitmap {
+            width,
+            height,
+            data: vec![0; width * height * Bitmap::BYTES_PER_PIXEL],
+        }
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, rgb: [u8; 3]) {
+        let offset = (y * self.width + x) * Bitmap::BYTES_PER_PIXEL;
+        self.data[offset..offset + Bitmap::BYTES_PER_PIXEL].copy_from_slice(&rgb);
+    }
+}
+
+// Decoders refuse images with more pixels than this, a 16384 by 16384 texture, so that a corrupt
+// or hostile header cannot make them allocate without bound
+const MAX_PIXELS: usize = 1 << 28;
+
+// Checks the size a decoder read from a header before it allocates anything for it
+fn check_size(width: usize, height: usize) -> Result<(), Error> {
+    match width.checked_mul(height) {
+        Some(pixels) if pixels <= MAX_PIXELS => Ok(()),
+        _ => Err(Error::Unsupported(format!(
+            "{width}x{height} image is too large"
+        ))),
+    }
+}
+
+// A linear, unclamped floating point RGB image, stored row-major from the top-left corner
+pub struct HdrImage {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<f32>,
+}
+
+impl HdrImage {
+    pub const CHANNELS: usize = 3;
+
+    pub fn new(width: usize, height: usize) -> HdrImage {
+        HdrImage {
+            width,
+            height,
+            data: vec![0.0; width * height * HdrImage::CHANNELS],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> [f32; 3] {
+        let offset = (y * self.width + x) * HdrImage::CHANNELS;
+        [
+            self.data[offset],
+            self.data[offset + 1],
+            self.data[offset + 2],
+        ]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, rgb: [f32; 3]) {
+        let offset = (y * self.width + x) * HdrImage::CHANNELS;
+        self.data[offset..offset + HdrImage::CHANNELS].copy_from_slice(&rgb);
+    }
+
+    pub fn row(&self, y: usize) -> &[f32] {
+        let stride = self.width * HdrImage::CHANNELS;
+        &self.data[y * stride..(y + 1) * stride]
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Malformed(&'static str),
+    Unsupported(String),
+    InFile(PathBuf, Box<Error>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Malformed(reason) => write!(f, "malformed image: {reason}"),
+            Error::Unsupported(feature) => write!(f, "unsupported image: {feature}"),
+            Error::InFile(path, e) => write!(f, "{}: {e}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+// Loads a JPEG, PNG or PPM file, detecting the format from its leading bytes
+pub fn load(path: &Path) -> Result<Bitmap, Error> {
+    fs::read(path)
+        .map_err(Error::from)
+        .and_then(|bytes| decode(&bytes))
+        .map_err(|e| Error::InFile(path.to_path_buf(), Box::new(e)))
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Bitmap, Error> {
+    if bytes.starts_with(&jpeg::SIGNATURE) {
+        jpeg::decode(bytes)
+    } else if bytes.starts_with(&png::SIGNATURE) {
+        png::decode(bytes)
+    } else if ppm::is_ppm(bytes) {
+        ppm::decode(bytes)
+    } else {
+        Err(Error::Unsupported("unrecognized file format".to_string()))
+    }
+}
+
+// Loads the linear radiance of a PFM, Radiance RGBE or OpenEXR file, detecting the format from its
+// leading bytes. An OpenEXR file must have R, G and B channels outside any layer
+pub fn load_hdr(path: &Path) -> Result<HdrImage, Error> {
+    fs::read(path)
+        .map_err(Error::from)
+        .and_then(|bytes| decode_hdr(&bytes))
+        .map_err(|e| Error::InFile(path.to_path_buf(), Box::new(e)))
+}
+
+pub fn decode_hdr(bytes: &[u8]) -> Result<HdrImage, Error> {
+    if bytes.starts_with(&exr::MAGIC) {
+        exr::decode(bytes)?
+            .layer("", &["R", "G", "B"])
+            .ok_or(Error::Malformed("OpenEXR file has no R, G and B channels"))
+    } else if pfm::is_pfm(bytes) {
+        pfm::decode(bytes)
+    } else if rgbe::is_rgbe(bytes) {
+        rgbe::decode(bytes)
+    } else {
+        Err(Error::Unsupported("unrecognized file format".to_string()))
+    }
+}
@@ -0,0 +1,130 @@
+// Portable float map reader for color (PF) and gray (Pf) maps, and writer for linear RGB images
+
+use super::{Error, HdrImage};
+
+pub fn is_pfm(bytes: &[u8]) -> bool {
+    matches!(bytes, [b'P', b'F' | b'f', b'\n' | b'\r' | b' ' | b'\t', ..])
+}
+
+pub fn decode(bytes: &[u8]) -> Result<HdrImage, Error> {
+    let channels = if bytes[1] == b'F' { 3 } else { 1 };
+    // The header is three whitespace separated tokens after the magic: width, height and a scale
+    // whose sign gives the byte order, followed by exactly one whitespace byte
+    let mut pos = 2;
+    let mut tokens = Vec::new();
+    while tokens.len() < 3 {
+        while bytes.get(pos).is_some_and(u8::is_ascii_whitespace) {
+            pos += 1;
+        }
+        let start = pos;
+        while bytes.get(pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+            pos += 1;
+        }
+        if start == pos {
+            return Err(Error::Malformed("pfm header is truncated"));
+        }
+        tokens.push(std::str::from_utf8(&bytes[start..pos]).unwrap_or(""));
+    }
+    pos += 1;
+
+    let width: usize = tokens[0]
+        .parse()
+        .map_err(|_| Error::Malformed("pfm width is not a number"))?;
+    let height: usize = tokens[1]
+        .parse()
+        .map_err(|_| Error::Malformed("pfm height is not a number"))?;
+    let scale: f32 = tokens[2]
+        .parse()
+        .map_err(|_| Error::Malformed("pfm scale is not a number"))?;
+    if width == 0 || height == 0 {
+        return Err(Error::Malformed("pfm has zero size"));
+    }
+
+    let data = bytes
+        .get(pos..pos + width * height * channels * 4)
+        .ok_or(Error::Malformed("pfm pixel data is truncated"))?;
+    let mut values = data.chunks_exact(4).map(|b| {
+        let b = [b[0], b[1], b[2], b[3]];
+        if scale < 0.0 {
+            f32::from_le_bytes(b)
+        } else {
+            f32::from_be_bytes(b)
+        }
+    });
+
+    let mut image = HdrImage::new(width, height);
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let mut rgb = [0.0; 3];
+            for value in rgb.iter_mut().take(channels) {
+                *value = values.next().expect("length checked above");
+            }
+            if channels == 1 {
+                rgb = [rgb[0]; 3];
+            }
+            image.set(x, y, rgb);
+        }
+    }
+
+    Ok(image)
+}
+
+pub fn encode(image: &HdrImage) -> Vec<u8> {
+    // A negative scale marks the samples as little-endian
+    let mut out = format!("PF\n{} {}\n-1.0\n", image.width, image.height).into_bytes();
+    out.reserve(image.data.len() * 4);
+
+    // Scanlines are stored bottom-to-top
+    for y in (0..image.height).rev() {
+        for value in image.row(y) {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_round_trip() {
+        let mut image = HdrImage::new(3, 2);
+        image.set(0, 0, [1.0, 0.5, 0.25]);
+        image.set(2, 0, [-1.0, 1e-20, 65536.5]);
+        image.set(1, 1, [f32::INFINITY, 0.0, 123.456]);
+
+        let bytes = encode(&image);
+        assert!(is_pfm(&bytes));
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!((decoded.width, decoded.height), (3, 2));
+        assert_eq!(decoded.data, image.data);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)] // The stored values are exact
+    fn big_endian_gray() {
+        // Two rows, stored bottom row first
+        let mut bytes = b"Pf\n1 2\n1.0\n".to_vec();
+        bytes.extend_from_slice(&2.0f32.to_be_bytes());
+        bytes.extend_from_slice(&0.5f32.to_be_bytes());
+
+        let image = decode(&bytes).unwrap();
+        assert_eq!(image.get(0, 0), [0.5; 3]);
+        assert_eq!(image.get(0, 1), [2.0; 3]);
+    }
+
+    #[test]
+    fn truncated_data() {
+        let bytes = encode(&HdrImage::new(4, 4));
+        assert!(matches!(
+            decode(&bytes[..bytes.len() - 1]),
+            Err(Error::Malformed("pfm pixel data is truncated"))
+        ));
+        assert!(matches!(
+            decode(b"PF\n4 4"),
+            Err(Error::Malformed("pfm header is truncated"))
+        ));
+    }
+}
@@ -0,0 +1,576 @@
+use super::{check_size, deflate, inflate, Bitmap, Error};
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+// Adam7 pass origins and strides as (x0, y0, dx, dy)
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * usize::from(self.bit_depth)
+    }
+
+    fn row_bytes(&self, width: usize) -> usize {
+        (width * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    // Size of the Adam7 passes as (x0, y0, dx, dy, width, height), leaving out empty ones, or of
+    // the whole image as a single pass
+    fn passes(&self) -> Vec<(usize, usize, usize, usize, usize, usize)> {
+        if !self.interlaced {
+            return vec![(0, 0, 1, 1, self.width, self.height)];
+        }
+        ADAM7
+            .into_iter()
+            .map(|(x0, y0, dx, dy)| {
+                let width = (self.width + dx - 1 - x0) / dx;
+                let height = (self.height + dy - 1 - y0) / dy;
+                (x0, y0, dx, dy, width, height)
+            })
+            .filter(|&(.., width, height)| width > 0 && height > 0)
+            .collect()
+    }
+
+    // Bytes of filtered image data the header calls for, a filter byte leading each row
+    fn data_size(&self) -> usize {
+        self.passes()
+            .into_iter()
+            .map(|(.., width, height)| (self.row_bytes(width) + 1) * height)
+            .sum()
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Bitmap, Error> {
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut compressed = Vec::new();
+
+    let mut pos = SIGNATURE.len();
+    loop {
+        let chunk_header = bytes
+            .get(pos..pos + 8)
+            .ok_or(Error::Malformed("png is truncated"))?;
+        let len = u32::from_be_bytes([
+            chunk_header[0],
+            chunk_header[1],
+            chunk_header[2],
+            chunk_header[3],
+        ]) as usize;
+        let kind = &chunk_header[4..8];
+        let data = bytes
+            .get(pos + 8..pos + 8 + len)
+            .ok_or(Error::Malformed("png chunk is truncated"))?;
+        let crc = bytes
+            .get(pos + 8 + len..pos + 12 + len)
+            .ok_or(Error::Malformed("png chunk is truncated"))?;
+        if crc32(&bytes[pos + 4..pos + 8 + len])
+            != u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]])
+        {
+            return Err(Error::Malformed("png chunk checksum mismatch"));
+        }
+        pos += 12 + len;
+
+        match kind {
+            b"IHDR" => header = Some(parse_header(data)?),
+            b"PLTE" => palette = data,
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+
+    let header = header.ok_or(Error::Malformed("png is missing its IHDR chunk"))?;
+    if header.color_type == 3 && palette.is_empty() {
+        return Err(Error::Malformed("palette png is missing its PLTE chunk"));
+    }
+
+    let raw = inflate::zlib_decompress(&compressed)?;
+    if raw.len() < header.data_size() {
+        return Err(Error::Malformed("png image data is truncated"));
+    }
+
+    let mut bitmap = Bitmap::new(header.width, header.height);
+    let mut offset = 0;
+    for (x0, y0, dx, dy, width, height) in header.passes() {
+        let size = (header.row_bytes(width) + 1) * height;
+        decode_pass(
+            &header,
+            palette,
+            &raw[offset..offset + size],
+            width,
+            height,
+            |x, y, rgb| bitmap.set(x0 + x * dx, y0 + y * dy, rgb),
+        )?;
+        offset += size;
+    }
+
+    Ok(bitmap)
+}
+
+fn parse_header(data: &[u8]) -> Result<Header, Error> {
+    if data.len() != 13 {
+        return Err(Error::Malformed("invalid IHDR chunk"));
+    }
+
+    let width = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    let height = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+    let header = Header {
+        width,
+        height,
+        bit_depth: data[8],
+        color_type: data[9],
+        interlaced: data[12] == 1,
+    };
+
+    if width == 0 || height == 0 {
+        return Err(Error::Malformed("png has zero size"));
+    }
+    check_size(width, height)?;
+    if data[10] != 0 || data[11] != 0 || data[12] > 1 {
+        return Err(Error::Unsupported(
+            "png compression, filter or interlace method".to_string(),
+        ));
+    }
+
+    let valid_depth = match header.color_type {
+        0 => matches!(header.bit_depth, 1 | 2 | 4 | 8 | 16),
+        3 => matches!(header.bit_depth, 1 | 2 | 4 | 8),
+        2 | 4 | 6 => matches!(header.bit_depth, 8 | 16),
+        _ => false,
+    };
+    if !valid_depth {
+        return Err(Error::Unsupported(format!(
+            "png color type {} with bit depth {}",
+            header.color_type, header.bit_depth
+        )));
+    }
+
+    Ok(header)
+}
+
+fn decode_pass(
+    header: &Header,
+    palette: &[u8],
+    data: &[u8],
+    width: usize,
+    height: usize,
+    mut put: impl FnMut(usize, usize, [u8; 3]),
+) -> Result<(), Error> {
+    let row_bytes = header.row_bytes(width);
+    let bpp = header.bits_per_pixel().div_ceil(8);
+
+    if data.len() < (row_bytes + 1) * height {
+        return Err(Error::Malformed("png image data is truncated"));
+    }
+
+    let mut previous = vec![0u8; row_bytes];
+    let mut current = vec![0u8; row_bytes];
+
+    for y in 0..height {
+        let line = &data[y * (row_bytes + 1)..(y + 1) * (row_bytes + 1)];
+        current.copy_from_slice(&line[1..]);
+        unfilter(line[0], &mut current, &previous, bpp)?;
+
+        for x in 0..width {
+            put(x, y, pixel(header, palette, &current, x)?);
+        }
+
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    Ok(())
+}
+
+fn unfilter(filter: u8, row: &mut [u8], previous: &[u8], bpp: usize) -> Result<(), Error> {
+    match filter {
+        0 => {}
+        1 => {
+            for i in bpp..row.len() {
+                row[i] = row[i].wrapping_add(row[i - bpp]);
+            }
+        }
+        2 => {
+            for (r, p) in row.iter_mut().zip(previous) {
+                *r = r.wrapping_add(*p);
+            }
+        }
+        3 => {
+            for i in 0..row.len() {
+                let left = if i >= bpp { row[i - bpp] } else { 0 };
+                row[i] = row[i].wrapping_add(u8::midpoint(left, previous[i]));
+            }
+        }
+        4 => {
+            for i in 0..row.len() {
+                let left = if i >= bpp { row[i - bpp] } else { 0 };
+                let upper_left = if i >= bpp { previous[i - bpp] } else { 0 };
+                row[i] = row[i].wrapping_add(paeth(left, previous[i], upper_left));
+            }
+        }
+        _ => return Err(Error::Malformed("invalid png filter type")),
+    }
+
+    Ok(())
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = i16::from(a) + i16::from(b) - i16::from(c);
+    let pa = (p - i16::from(a)).abs();
+    let pb = (p - i16::from(b)).abs();
+    let pc = (p - i16::from(c)).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// Reads channel `index` of pixel `x`, reduced to 8 bits
+fn sample(header: &Header, row: &[u8], x: usize, index: usize) -> u8 {
+    let depth = usize::from(header.bit_depth);
+    match depth {
+        8 => row[x * header.channels() + index],
+        16 => row[(x * header.channels() + index) * 2],
+        _ => {
+            // Sub-byte depths only occur with a single channel, packed from the high bits
+            let bit = x * depth;
+            let shift = 8 - depth - bit % 8;
+            let mask = (1u8 << depth) - 1;
+            let value = (row[bit / 8] >> shift) & mask;
+            if header.color_type == 3 {
+                value
+            } else {
+                u8::try_from(u16::from(value) * 255 / u16::from(mask)).expect("scaled to u8")
+            }
+        }
+    }
+}
+
+fn pixel(header: &Header, palette: &[u8], row: &[u8], x: usize) -> Result<[u8; 3], Error> {
+    Ok(match header.color_type {
+        0 | 4 => {
+            let gray = sample(header, row, x, 0);
+            [gray, gray, gray]
+        }
+        3 => {
+            let index = usize::from(sample(header, row, x, 0)) * 3;
+            let entry = palette
+                .get(index..index + 3)
+                .ok_or(Error::Malformed("png palette index out of range"))?;
+            [entry[0], entry[1], entry[2]]
+        }
+        _ => [
+            sample(header, row, x, 0),
+            sample(header, row, x, 1),
+            sample(header, row, x, 2),
+        ],
+    })
+}
+
+// Encodes an 8-bit RGB PNG, picking each row's filter with the minimum sum of absolute
+// differences heuristic
+pub fn encode(bitmap: &Bitmap) -> Vec<u8> {
+    let row_bytes = bitmap.width * Bitmap::BYTES_PER_PIXEL;
+    let mut filtered = Vec::with_capacity((row_bytes + 1) * bitmap.height);
+    let mut candidate = vec![0u8; row_bytes];
+    let mut best = vec![0u8; row_bytes];
+    let zero_row = vec![0u8; row_bytes];
+
+    for y in 0..bitmap.height {
+        let row = &bitmap.data[y * row_bytes..(y + 1) * row_bytes];
+        let previous = if y == 0 {
+            &zero_row[..]
+        } else {
+            &bitmap.data[(y - 1) * row_bytes..y * row_bytes]
+        };
+
+        let mut best_filter = 0;
+        let mut best_cost = u64::MAX;
+        for filter in 0..5 {
+            apply_filter(
+                filter,
+                row,
+                previous,
+                Bitmap::BYTES_PER_PIXEL,
+                &mut candidate,
+            );
+            let cost: u64 = candidate
+                .iter()
+                .map(|&b| u64::from(b.cast_signed().unsigned_abs()))
+                .sum();
+            if cost < best_cost {
+                best_cost = cost;
+                best_filter = filter;
+                std::mem::swap(&mut best, &mut candidate);
+            }
+        }
+
+        filtered.push(best_filter);
+        filtered.extend_from_slice(&best);
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(
+        &u32::try_from(bitmap.width)
+            .expect("width fits")
+            .to_be_bytes(),
+    );
+    header.extend_from_slice(
+        &u32::try_from(bitmap.height)
+            .expect("height fits")
+            .to_be_bytes(),
+    );
+    // 8-bit depth, truecolor, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut out = SIGNATURE.to_vec();
+    write_chunk(&mut out, *b"IHDR", &header);
+    write_chunk(&mut out, *b"IDAT", &deflate::zlib_compress(&filtered));
+    write_chunk(&mut out, *b"IEND", &[]);
+    out
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: [u8; 4], data: &[u8]) {
+    out.extend_from_slice(&u32::try_from(data.len()).expect("chunk fits").to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(&kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn apply_filter(filter: u8, row: &[u8], previous: &[u8], bpp: usize, out: &mut [u8]) {
+    for i in 0..row.len() {
+        let left = if i >= bpp { row[i - bpp] } else { 0 };
+        let up = previous[i];
+        let upper_left = if i >= bpp { previous[i - bpp] } else { 0 };
+        let prediction = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => u8::midpoint(left, up),
+            _ => paeth(left, up, upper_left),
+        };
+        out[i] = row[i].wrapping_sub(prediction);
+    }
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A PNG of the given IHDR fields and filtered image data, with an optional palette
+    fn png(width: u32, height: u32, depth_and_type: [u8; 2], interlace: u8, raw: &[u8]) -> Vec<u8> {
+        png_with_palette(width, height, depth_and_type, interlace, raw, &[])
+    }
+
+    fn png_with_palette(
+        width: u32,
+        height: u32,
+        depth_and_type: [u8; 2],
+        interlace: u8,
+        raw: &[u8],
+        palette: &[u8],
+    ) -> Vec<u8> {
+        let mut ihdr = Vec::new();
+        ihdr.extend_from_slice(&width.to_be_bytes());
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        ihdr.extend_from_slice(&[depth_and_type[0], depth_and_type[1], 0, 0, interlace]);
+
+        let mut out = SIGNATURE.to_vec();
+        write_chunk(&mut out, *b"IHDR", &ihdr);
+        if !palette.is_empty() {
+            write_chunk(&mut out, *b"PLTE", palette);
+        }
+        write_chunk(&mut out, *b"IDAT", &deflate::zlib_compress(raw));
+        write_chunk(&mut out, *b"IEND", &[]);
+        out
+    }
+
+    fn color(x: usize, y: usize) -> [u8; 3] {
+        let x = u8::try_from(x).unwrap();
+        let y = u8::try_from(y).unwrap();
+        [x * 20, y * 20, x ^ y]
+    }
+
+    #[test]
+    fn gray() {
+        let bitmap = decode(&png(2, 2, [8, 0], 0, &[0, 10, 20, 0, 30, 40])).unwrap();
+        assert_eq!(
+            bitmap.data,
+            [10, 10, 10, 20, 20, 20, 30, 30, 30, 40, 40, 40]
+        );
+    }
+
+    #[test]
+    fn filters() {
+        // The rows hold 1 to 5 in every byte, written with the None, Sub, Up, Average
+        // and Paeth filters in turn
+        let raw = [
+            0, 1, 1, 1, 1, 1, 1, //
+            1, 2, 2, 2, 0, 0, 0, //
+            2, 1, 1, 1, 1, 1, 1, //
+            3, 3, 3, 3, 1, 1, 1, //
+            4, 1, 1, 1, 0, 0, 0,
+        ];
+        let bitmap = decode(&png(2, 5, [8, 2], 0, &raw)).unwrap();
+        for (y, row) in bitmap.data.chunks_exact(6).enumerate() {
+            let expected = [1, 2, 3, 4, 5][y];
+            assert!(row.iter().all(|&b| b == expected), "row {y}: {row:?}");
+        }
+    }
+
+    #[test]
+    fn palette_and_low_depths() {
+        let palette = [255, 0, 0, 0, 255, 0];
+        let bitmap = decode(&png_with_palette(
+            3,
+            1,
+            [1, 3],
+            0,
+            &[0, 0b0100_0000],
+            &palette,
+        ))
+        .unwrap();
+        assert_eq!(bitmap.data, [255, 0, 0, 0, 255, 0, 255, 0, 0]);
+
+        let bitmap = decode(&png(4, 1, [2, 0], 0, &[0, 0b0001_1011])).unwrap();
+        assert_eq!(
+            bitmap.data,
+            [0, 0, 0, 85, 85, 85, 170, 170, 170, 255, 255, 255]
+        );
+
+        let error = decode(&png_with_palette(1, 1, [8, 3], 0, &[0, 2], &palette));
+        assert!(matches!(
+            error,
+            Err(Error::Malformed("png palette index out of range"))
+        ));
+    }
+
+    #[test]
+    fn sixteen_bit_keeps_the_high_byte() {
+        let raw = [0, 0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC];
+        let bitmap = decode(&png(1, 1, [16, 2], 0, &raw)).unwrap();
+        assert_eq!(bitmap.data, [0x12, 0x56, 0x9A]);
+    }
+
+    #[test]
+    fn interlaced_matches_progressive() {
+        // Odd sizes leave some Adam7 passes narrower than others
+        let (width, height) = (11, 7);
+
+        let mut progressive = Vec::new();
+        for y in 0..height {
+            progressive.push(0);
+            for x in 0..width {
+                progressive.extend_from_slice(&color(x, y));
+            }
+        }
+
+        let mut interlaced = Vec::new();
+        for (x0, y0, dx, dy) in ADAM7 {
+            if x0 >= width || y0 >= height {
+                continue;
+            }
+            for y in (y0..height).step_by(dy) {
+                interlaced.push(0);
+                for x in (x0..width).step_by(dx) {
+                    interlaced.extend_from_slice(&color(x, y));
+                }
+            }
+        }
+
+        let (w, h) = (
+            u32::try_from(width).unwrap(),
+            u32::try_from(height).unwrap(),
+        );
+        let expected = decode(&png(w, h, [8, 2], 0, &progressive)).unwrap();
+        let bitmap = decode(&png(w, h, [8, 2], 1, &interlaced)).unwrap();
+        assert_eq!(bitmap.data, expected.data);
+        assert_eq!(bitmap.data[..3], color(0, 0));
+        assert_eq!(
+            bitmap.data[bitmap.data.len() - 3..],
+            color(width - 1, height - 1)
+        );
+    }
+
+    #[test]
+    fn huge_header_is_rejected() {
+        let error = decode(&png(1 << 30, 1 << 30, [8, 2], 0, &[0, 0, 0, 0]));
+        assert!(matches!(error, Err(Error::Unsupported(_))));
+    }
+
+    #[test]
+    fn truncated_image_data() {
+        let error = decode(&png(4, 4, [8, 2], 0, &[0; 20]));
+        assert!(matches!(
+            error,
+            Err(Error::Malformed("png image data is truncated"))
+        ));
+    }
+
+    #[test]
+    fn chunk_checksum_mismatch() {
+        let mut bytes = png(1, 1, [8, 0], 0, &[0, 0]);
+        bytes[SIGNATURE.len() + 10] ^= 1;
+        assert!(matches!(
+            decode(&bytes),
+            Err(Error::Malformed("png chunk checksum mismatch"))
+        ));
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let mut bitmap = Bitmap::new(13, 9);
+        for y in 0..bitmap.height {
+            for x in 0..bitmap.width {
+                bitmap.set(x, y, color(x, y));
+            }
+        }
+        let decoded = decode(&encode(&bitmap)).unwrap();
+        assert_eq!((decoded.width, decoded.height), (13, 9));
+        assert_eq!(decoded.data, bitmap.data);
+
+        let flat = Bitmap::new(1, 1);
+        assert_eq!(decode(&encode(&flat)).unwrap().data, flat.data);
+    }
+}
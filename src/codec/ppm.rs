@@ -0,0 +1,169 @@
+// Netpbm reader for ASCII (P2/P3) and binary (P5/P6) gray and color maps, and writer for
+// P3/P6 color maps
+
+use std::fmt::Write;
+
+use super::{check_size, Bitmap, Error};
+
+pub fn is_ppm(bytes: &[u8]) -> bool {
+    matches!(bytes, [b'P', b'2' | b'3' | b'5' | b'6', ..])
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Bitmap, Error> {
+    let mut reader = Reader { bytes, pos: 2 };
+    let binary = matches!(bytes[1], b'5' | b'6');
+    let channels = if matches!(bytes[1], b'3' | b'6') {
+        3
+    } else {
+        1
+    };
+
+    let width = reader.number()?;
+    let height = reader.number()?;
+    let max_value = reader.number()?;
+    if width == 0 || height == 0 {
+        return Err(Error::Malformed("ppm has zero size"));
+    }
+    check_size(width, height)?;
+    if max_value == 0 || max_value > 65535 {
+        return Err(Error::Malformed("ppm max value out of range"));
+    }
+
+    // Binary data starts after exactly one whitespace byte following the header
+    reader.pos += 1;
+
+    let mut bitmap = Bitmap::new(width, height);
+    let mut rgb = [0u8; 3];
+    for y in 0..height {
+        for x in 0..width {
+            for value in rgb.iter_mut().take(channels) {
+                let raw = if binary {
+                    reader.binary_sample(max_value > 255)?
+                } else {
+                    reader.number()?
+                };
+                *value = u8::try_from(raw.min(max_value) * 255 / max_value).expect("scaled to u8");
+            }
+            if channels == 1 {
+                rgb = [rgb[0]; 3];
+            }
+            bitmap.set(x, y, rgb);
+        }
+    }
+
+    Ok(bitmap)
+}
+
+pub fn encode_binary(bitmap: &Bitmap) -> Vec<u8> {
+    let mut out = format!("P6\n{} {}\n255\n", bitmap.width, bitmap.height).into_bytes();
+    out.extend_from_slice(&bitmap.data);
+    out
+}
+
+pub fn encode_ascii(bitmap: &Bitmap) -> Vec<u8> {
+    let mut out = format!("P3\n{} {}\n255\n", bitmap.width, bitmap.height);
+    for pixel in bitmap.data.chunks_exact(Bitmap::BYTES_PER_PIXEL) {
+        writeln!(out, "{} {} {}", pixel[0], pixel[1], pixel[2]).expect("writing to a string");
+    }
+    out.into_bytes()
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    // Reads a decimal number, skipping whitespace and '#' comments
+    fn number(&mut self) -> Result<usize, Error> {
+        loop {
+            match self.bytes.get(self.pos) {
+                Some(b'#') => {
+                    while !matches!(self.bytes.get(self.pos), Some(b'\n') | None) {
+                        self.pos += 1;
+                    }
+                }
+                Some(c) if c.is_ascii_whitespace() => self.pos += 1,
+                _ => break,
+            }
+        }
+
+        let start = self.pos;
+        while self.bytes.get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+
+        std::str::from_utf8(&self.bytes[start..self.pos])
+            .ok()
+            .and_then(|digits| digits.parse().ok())
+            .ok_or(Error::Malformed("expected a number in ppm data"))
+    }
+
+    fn binary_sample(&mut self, wide: bool) -> Result<usize, Error> {
+        let size = if wide { 2 } else { 1 };
+        let sample = self
+            .bytes
+            .get(self.pos..self.pos + size)
+            .ok_or(Error::Malformed("ppm pixel data is truncated"))?;
+        self.pos += size;
+
+        Ok(if wide {
+            usize::from(u16::from_be_bytes([sample[0], sample[1]]))
+        } else {
+            usize::from(sample[0])
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_gray_with_comments() {
+        let bitmap = decode(b"P2\n# a comment\n2 1 # another\n15\n0 15\n").unwrap();
+        assert_eq!((bitmap.width, bitmap.height), (2, 1));
+        assert_eq!(bitmap.data, [0, 0, 0, 255, 255, 255]);
+    }
+
+    #[test]
+    fn binary_sixteen_bit() {
+        let mut bytes = b"P6 1 1 65535\n".to_vec();
+        bytes.extend_from_slice(&[0xFF, 0xFF, 0x80, 0x00, 0x00, 0x00]);
+        assert_eq!(decode(&bytes).unwrap().data, [255, 127, 0]);
+    }
+
+    #[test]
+    fn malformed_headers() {
+        assert!(matches!(
+            decode(b"P6 0 1 255\n"),
+            Err(Error::Malformed("ppm has zero size"))
+        ));
+        assert!(matches!(
+            decode(b"P6 1 1 0\n"),
+            Err(Error::Malformed("ppm max value out of range"))
+        ));
+        assert!(matches!(
+            decode(b"P6 100000 100000 255\n"),
+            Err(Error::Unsupported(_))
+        ));
+        assert!(matches!(
+            decode(b"P6 2 2 255\n\x01\x02\x03"),
+            Err(Error::Malformed("ppm pixel data is truncated"))
+        ));
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let mut bitmap = Bitmap::new(3, 2);
+        for (i, byte) in bitmap.data.iter_mut().enumerate() {
+            *byte = u8::try_from(i * 14).unwrap();
+        }
+        for bytes in [encode_binary(&bitmap), encode_ascii(&bitmap)] {
+            assert!(is_ppm(&bytes));
+            let decoded = decode(&bytes).unwrap();
+            assert_eq!((decoded.width, decoded.height), (3, 2));
+            assert_eq!(decoded.data, bitmap.data);
+        }
+    }
+}
@@ -0,0 +1,318 @@
+// Radiance RGBE (.hdr) reader and writer, using the run-length encoded scanline format
+
+use super::{Error, HdrImage};
+
+pub fn is_rgbe(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"#?")
+}
+
+// Reads flat and run-length encoded scanlines stored top to bottom, left to right, which is what
+// nearly every writer produces
+pub fn decode(bytes: &[u8]) -> Result<HdrImage, Error> {
+    // Header lines end at an empty line, which the resolution line follows
+    let mut pos = 0;
+    let mut line = || -> Result<String, Error> {
+        let start = pos;
+        let end = bytes[start..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map(|offset| start + offset)
+            .ok_or(Error::Malformed("rgbe header is truncated"))?;
+        pos = end + 1;
+        Ok(String::from_utf8_lossy(&bytes[start..end])
+            .trim()
+            .to_string())
+    };
+    loop {
+        let header = line()?;
+        if header.is_empty() {
+            break;
+        }
+        if let Some(format) = header.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(Error::Unsupported(format!("rgbe format {format}")));
+            }
+        }
+    }
+    let resolution = line()?;
+    let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (height.parse().ok(), width.parse().ok()),
+        _ => return Err(Error::Unsupported(format!("rgbe orientation {resolution}"))),
+    };
+    let (Some(height), Some(width)) = (height, width) else {
+        return Err(Error::Malformed("rgbe resolution is not a number"));
+    };
+    if width == 0 || height == 0 {
+        return Err(Error::Malformed("rgbe has zero size"));
+    }
+
+    let mut image = HdrImage::new(width, height);
+    let mut scanline = vec![0u8; width * 4];
+    let mut data = &bytes[pos..];
+    for y in 0..height {
+        data = decode_scanline(data, &mut scanline)?;
+        for (x, pixel) in scanline.chunks_exact(4).enumerate() {
+            image.set(x, y, from_rgbe([pixel[0], pixel[1], pixel[2], pixel[3]]));
+        }
+    }
+
+    Ok(image)
+}
+
+// Fills `scanline` with RGBE quadruples and returns the data after it
+fn decode_scanline<'a>(data: &'a [u8], scanline: &mut [u8]) -> Result<&'a [u8], Error> {
+    let width = scanline.len() / 4;
+    let run_length_encoded = (MIN_RLE_WIDTH..=MAX_RLE_WIDTH).contains(&width)
+        && data.len() >= 4
+        && data[0] == 2
+        && data[1] == 2
+        && data[2] & 0x80 == 0;
+    if !run_length_encoded {
+        let flat = data
+            .get(..scanline.len())
+            .ok_or(Error::Malformed("rgbe pixel data is truncated"))?;
+        scanline.copy_from_slice(flat);
+        return Ok(&data[scanline.len()..]);
+    }
+
+    if usize::from(u16::from_be_bytes([data[2], data[3]])) != width {
+        return Err(Error::Malformed(
+            "rgbe scanline width does not match the image",
+        ));
+    }
+    let mut pos = 4;
+    for component in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = *data
+                .get(pos)
+                .ok_or(Error::Malformed("rgbe pixel data is truncated"))?;
+            pos += 1;
+            if count > 128 {
+                let run = usize::from(count - 128);
+                let value = *data
+                    .get(pos)
+                    .ok_or(Error::Malformed("rgbe pixel data is truncated"))?;
+                pos += 1;
+                if x + run > width {
+                    return Err(Error::Malformed("rgbe run overflows the scanline"));
+                }
+                for pixel in x..x + run {
+                    scanline[pixel * 4 + component] = value;
+                }
+                x += run;
+            } else {
+                let count = usize::from(count);
+                if count == 0 || x + count > width {
+                    return Err(Error::Malformed("rgbe run overflows the scanline"));
+                }
+                let values = data
+                    .get(pos..pos + count)
+                    .ok_or(Error::Malformed("rgbe pixel data is truncated"))?;
+                for (pixel, &value) in (x..x + count).zip(values) {
+                    scanline[pixel * 4 + component] = value;
+                }
+                pos += count;
+                x += count;
+            }
+        }
+    }
+    Ok(&data[pos..])
+}
+
+pub fn from_rgbe([r, g, b, e]: [u8; 4]) -> [f32; 3] {
+    if e == 0 {
+        return [0.0; 3];
+    }
+    // Each mantissa is taken at the middle of its quantization step
+    let scale = 2f32.powi(i32::from(e) - 136);
+    [r, g, b].map(|c| (f32::from(c) + 0.5) * scale)
+}
+
+// Scanlines outside this width range cannot use the run-length encoded format
+const MIN_RLE_WIDTH: usize = 8;
+const MAX_RLE_WIDTH: usize = 0x7FFF;
+const MIN_RUN: usize = 4;
+const MAX_RUN: usize = 127;
+
+pub fn encode(image: &HdrImage) -> Vec<u8> {
+    let mut out = format!(
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        image.height, image.width
+    )
+    .into_bytes();
+
+    let mut scanline = Vec::with_capacity(image.width * 4);
+    for y in 0..image.height {
+        scanline.clear();
+        for pixel in image.row(y).chunks_exact(HdrImage::CHANNELS) {
+            scanline.extend_from_slice(&to_rgbe(pixel[0], pixel[1], pixel[2]));
+        }
+
+        if (MIN_RLE_WIDTH..=MAX_RLE_WIDTH).contains(&image.width) {
+            let width = u16::try_from(image.width).expect("checked above");
+            out.extend_from_slice(&[2, 2]);
+            out.extend_from_slice(&width.to_be_bytes());
+            // Each component is run-length encoded separately
+            for component in 0..4 {
+                let values: Vec<u8> = scanline
+                    .iter()
+                    .skip(component)
+                    .step_by(4)
+                    .copied()
+                    .collect();
+                encode_component(&values, &mut out);
+            }
+        } else {
+            out.extend_from_slice(&scanline);
+        }
+    }
+
+    out
+}
+
+// Shared exponent encoding: three 8-bit mantissas scaled by 2^(e - 128)
+pub fn to_rgbe(r: f32, g: f32, b: f32) -> [u8; 4] {
+    let (r, g, b) = (r.max(0.0), g.max(0.0), b.max(0.0));
+    let v = r.max(g).max(b);
+    if v < 1e-32 || !v.is_finite() {
+        return [0, 0, 0, 0];
+    }
+
+    let (mantissa, exponent) = frexp(v);
+    let scale = mantissa * 256.0 / v;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // Values are < 256
+    let quantize = |c: f32| (c * scale) as u8;
+    let exponent = u8::try_from((exponent + 128).clamp(0, 255)).expect("clamped to a byte");
+
+    [quantize(r), quantize(g), quantize(b), exponent]
+}
+
+// Splits a positive value into a mantissa in [0.5, 1) and a power of two exponent
+fn frexp(v: f32) -> (f32, i32) {
+    #[allow(clippy::cast_possible_truncation)] // log2 of a finite f32 is small
+    let mut exponent = v.log2().floor() as i32 + 1;
+    let mut mantissa = v / 2f32.powi(exponent);
+    // Correct for rounding in log2 near exact powers of two
+    if mantissa >= 1.0 {
+        mantissa /= 2.0;
+        exponent += 1;
+    } else if mantissa < 0.5 {
+        mantissa *= 2.0;
+        exponent -= 1;
+    }
+    (mantissa, exponent)
+}
+
+// Run lengths are stored as 128 + count followed by the value, literals as count then values
+fn encode_component(values: &[u8], out: &mut Vec<u8>) {
+    let mut pos = 0;
+    while pos < values.len() {
+        // Find the next run long enough to be worth encoding
+        let mut run_start = pos;
+        let mut run_length = 0;
+        while run_start < values.len() {
+            run_length = values[run_start..]
+                .iter()
+                .take(MAX_RUN)
+                .take_while(|&&v| v == values[run_start])
+                .count();
+            if run_length >= MIN_RUN {
+                break;
+            }
+            run_start += run_length;
+        }
+        if run_length < MIN_RUN {
+            run_start = values.len();
+        }
+
+        // Emit the literals preceding the run
+        while pos < run_start {
+            let count = (run_start - pos).min(MAX_RUN);
+            out.push(u8::try_from(count).expect("count <= 127"));
+            out.extend_from_slice(&values[pos..pos + count]);
+            pos += count;
+        }
+
+        if run_start < values.len() {
+            out.push(u8::try_from(128 + run_length).expect("run <= 127"));
+            out.push(values[run_start]);
+            pos = run_start + run_length;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An image with flat runs, which the run-length encoding compresses, beside varying pixels
+    fn image(width: usize, height: usize) -> HdrImage {
+        let mut image = HdrImage::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let t = f32::from(u16::try_from(x * 7 + y * 3).unwrap());
+                let rgb = if x < width / 2 {
+                    [1.0, 0.5, 0.0]
+                } else {
+                    [t * 0.37, (t * 0.11).sin().abs(), 1000.0 / (t + 1.0)]
+                };
+                image.set(x, y, rgb);
+            }
+        }
+        image
+    }
+
+    fn assert_close(decoded: &HdrImage, image: &HdrImage) {
+        assert_eq!((decoded.width, decoded.height), (image.width, image.height));
+        for (a, b) in decoded.data.chunks_exact(3).zip(image.data.chunks_exact(3)) {
+            // The mantissas are 8 bits of the largest component
+            let tolerance = b.iter().copied().fold(0.0, f32::max) / 256.0;
+            for (a, b) in a.iter().zip(b) {
+                assert!((a - b).abs() <= tolerance, "{a} != {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn run_length_encoded_round_trip() {
+        let image = image(300, 3);
+        let bytes = encode(&image);
+        assert!(is_rgbe(&bytes));
+        assert!(bytes.len() < 300 * 3 * 4);
+        assert_close(&decode(&bytes).unwrap(), &image);
+    }
+
+    #[test]
+    fn flat_round_trip() {
+        // Scanlines narrower than 8 pixels are stored without run-length encoding
+        let image = image(5, 4);
+        assert_close(&decode(&encode(&image)).unwrap(), &image);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)] // The decoded values are exact
+    fn shared_exponent() {
+        assert_eq!(to_rgbe(0.0, 0.0, 0.0), [0, 0, 0, 0]);
+        assert_eq!(to_rgbe(-1.0, f32::NAN, 0.0), [0, 0, 0, 0]);
+        assert_eq!(to_rgbe(1.0, 0.5, 0.25), [128, 64, 32, 129]);
+        assert_eq!(from_rgbe([0, 0, 0, 0]), [0.0; 3]);
+        assert_eq!(
+            from_rgbe([128, 64, 32, 129]),
+            [128.5 / 128.0, 64.5 / 128.0, 32.5 / 128.0]
+        );
+    }
+
+    #[test]
+    fn truncated_data() {
+        let bytes = encode(&image(20, 2));
+        assert!(matches!(
+            decode(&bytes[..bytes.len() - 3]),
+            Err(Error::Malformed("rgbe pixel data is truncated"))
+        ));
+        assert!(matches!(
+            decode(b"#?RADIANCE\n\n+Y 2 +X 2\n"),
+            Err(Error::Unsupported(_))
+        ));
+    }
+}
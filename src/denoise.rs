@@ -0,0 +1,128 @@
+use rayon::prelude::*;
+
+use crate::codec::HdrImage;
+
+// Passes of the filter, each reaching twice as far as the last, so the last spans 124 pixels
+const PASSES: u32 = 5;
+// Weights of the taps of the B3 spline, spaced further apart in each pass
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+// How quickly a tap's weight falls as its normal and albedo differ from the pixel's
+const NORMAL_SIGMA: f32 = 0.3;
+const ALBEDO_SIGMA: f32 = 0.1;
+// Albedo below this is not divided out, so black and emitting surfaces keep their color
+const MIN_ALBEDO: f32 = 0.01;
+
+// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010). The image is blurred in passes of
+// widening reach, with neighbours weighted down where their normal, albedo or color differs from
+// the pixel's, so edges and texture survive while noise on smooth surfaces is averaged out. The
+// albedo is divided out beforehand and multiplied back in after, so the filter only smooths the
+// lighting. `strength` is how different two colors may be, once compressed into [0, 1), before
+// they are kept apart; it halves with each pass
+pub fn denoise(image: &HdrImage, albedo: &HdrImage, normal: &HdrImage, strength: f32) -> HdrImage {
+    let pixels = |image: &HdrImage| -> Vec<[f32; 3]> {
+        image
+            .data
+            .chunks_exact(HdrImage::CHANNELS)
+            .map(|p| [p[0], p[1], p[2]])
+            .collect()
+    };
+    let normals = pixels(normal);
+    let albedos = pixels(albedo);
+    let divisors: Vec<[f32; 3]> = albedos
+        .iter()
+        .map(|a| a.map(|c| if c > MIN_ALBEDO { c } else { 1.0 }))
+        .collect();
+
+    let mut lighting: Vec<[f32; 3]> = pixels(image)
+        .iter()
+        .zip(&divisors)
+        // A NaN or infinite sample would spread across the whole filter
+        .map(|(c, d)| [0, 1, 2].map(|i| if c[i].is_finite() { c[i] / d[i] } else { 0.0 }))
+        .collect();
+
+    let (width, height) = (image.width, image.height);
+    for pass in 0..PASSES {
+        let step = 1 << pass;
+        let sigma = strength / f32::from(1u16 << pass);
+        let compressed: Vec<[f32; 3]> = lighting.iter().map(|c| c.map(|v| v / (1.0 + v))).collect();
+        let guides = Guides {
+            width,
+            height,
+            normals: &normals,
+            albedos: &albedos,
+            compressed: &compressed,
+            color_scale: 1.0 / (sigma * sigma).max(f32::MIN_POSITIVE),
+        };
+        lighting = (0..height)
+            .into_par_iter()
+            .flat_map_iter(|y| {
+                let (guides, lighting) = (&guides, &lighting);
+                (0..width).map(move |x| guides.filter(lighting, x, y, step))
+            })
+            .collect();
+    }
+
+    let mut out = HdrImage::new(width, height);
+    for (index, (c, d)) in lighting.iter().zip(&divisors).enumerate() {
+        out.set(
+            index % width,
+            index / width,
+            [c[0] * d[0], c[1] * d[1], c[2] * d[2]],
+        );
+    }
+    out
+}
+
+// What the taps of one pass are compared by
+struct Guides<'a> {
+    width: usize,
+    height: usize,
+    normals: &'a [[f32; 3]],
+    albedos: &'a [[f32; 3]],
+    compressed: &'a [[f32; 3]],
+    // One over the square of the color sigma
+    color_scale: f32,
+}
+
+impl Guides<'_> {
+    // Weighted average of the 5 by 5 taps `step` pixels apart around (x, y)
+    fn filter(&self, lighting: &[[f32; 3]], x: usize, y: usize, step: usize) -> [f32; 3] {
+        let center = y * self.width + x;
+        let mut sum = [0.0; 3];
+        let mut total = 0.0;
+        for (dy, ky) in KERNEL.iter().enumerate() {
+            let Some(ty) = (y + dy * step).checked_sub(2 * step) else {
+                continue;
+            };
+            if ty >= self.height {
+                continue;
+            }
+            for (dx, kx) in KERNEL.iter().enumerate() {
+                let Some(tx) = (x + dx * step).checked_sub(2 * step) else {
+                    continue;
+                };
+                if tx >= self.width {
+                    continue;
+                }
+                let tap = ty * self.width + tx;
+                let distance = distance_squared(&self.compressed[center], &self.compressed[tap])
+                    * self.color_scale
+                    + distance_squared(&self.normals[center], &self.normals[tap])
+                        / (NORMAL_SIGMA * NORMAL_SIGMA)
+                    + distance_squared(&self.albedos[center], &self.albedos[tap])
+                        / (ALBEDO_SIGMA * ALBEDO_SIGMA);
+                let weight = kx * ky * (-distance).exp();
+                for (s, v) in sum.iter_mut().zip(&lighting[tap]) {
+                    *s += weight * v;
+                }
+                total += weight;
+            }
+        }
+        // The center tap always has a positive weight
+        sum.map(|s| s / total)
+    }
+}
+
+fn distance_squared(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    (0..3).map(|i| (a[i] - b[i]) * (a[i] - b[i])).sum()
+}
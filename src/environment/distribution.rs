@@ -0,0 +1,102 @@
+// Piecewise constant distribution over [0, 1), each of its equal pieces chosen in proportion to
+// its weight
+pub struct Distribution {
+    weights: Vec<f64>,
+    // Running sums of the weights, normalized to end at 1
+    cdf: Vec<f64>,
+    // Mean weight, which the density of each piece is relative to
+    mean: f64,
+}
+
+impl Distribution {
+    // Negative and non-finite weights count as zero. If every weight is zero, the pieces are
+    // chosen uniformly
+    pub fn new(weights: Vec<f64>) -> Distribution {
+        let mut weights: Vec<f64> = weights
+            .into_iter()
+            .map(|w| if w.is_finite() { w.max(0.0) } else { 0.0 })
+            .collect();
+        let mut total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            weights.fill(1.0);
+            #[allow(clippy::cast_precision_loss)] // Piece counts are far below 2^52
+            let count = weights.len() as f64;
+            total = count;
+        }
+
+        let mut cdf = Vec::with_capacity(weights.len() + 1);
+        cdf.push(0.0);
+        let mut sum = 0.0;
+        for weight in &weights {
+            sum += weight;
+            cdf.push(sum / total);
+        }
+        #[allow(clippy::cast_precision_loss)] // Piece counts are far below 2^52
+        let mean = total / weights.len() as f64;
+        Distribution { weights, cdf, mean }
+    }
+
+    pub fn len(&self) -> usize {
+        self.weights.len()
+    }
+
+    // Point of [0, 1) that `u` maps to, and the piece it lies in
+    pub fn sample(&self, u: f64) -> (f64, usize) {
+        // The last piece whose running sum starts at or below u, skipping empty pieces
+        let index = self
+            .cdf
+            .partition_point(|&c| c <= u)
+            .saturating_sub(1)
+            .min(self.len() - 1);
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let offset = if width > 0.0 {
+            ((u - self.cdf[index]) / width).clamp(0.0, 1.0 - f64::EPSILON)
+        } else {
+            0.5
+        };
+        #[allow(clippy::cast_precision_loss)] // As above
+        let position = (index as f64 + offset) / self.len() as f64;
+        (position, index)
+    }
+
+    // Density of the points in piece `index`
+    pub fn pdf(&self, index: usize) -> f64 {
+        self.weights[index] / self.mean
+    }
+}
+
+// Piecewise constant distribution over the unit square, in rows of equal pieces: a row is chosen
+// first in proportion to its total weight, then a piece within it
+pub struct Distribution2D {
+    rows: Vec<Distribution>,
+    marginal: Distribution,
+}
+
+impl Distribution2D {
+    // `weights` holds `width` weights for each row in turn
+    pub fn new(weights: &[f64], width: usize) -> Distribution2D {
+        let rows: Vec<Distribution> = weights
+            .chunks_exact(width)
+            .map(|row| Distribution::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution::new(
+            weights
+                .chunks_exact(width)
+                .map(|row| row.iter().sum())
+                .collect(),
+        );
+        Distribution2D { rows, marginal }
+    }
+
+    // Point (x, y) of the unit square that `u` maps to, along with the column and row it lies in
+    pub fn sample(&self, (u0, u1): (f64, f64)) -> ((f64, f64), (usize, usize)) {
+        let (y, row) = self.marginal.sample(u1);
+        let (x, column) = self.rows[row].sample(u0);
+        ((x, y), (column, row))
+    }
+
+    // Density of the points in the given column and row
+    pub fn pdf(&self, column: usize, row: usize) -> f64 {
+        self.marginal.pdf(row) * self.rows[row].pdf(column)
+    }
+}
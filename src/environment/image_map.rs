@@ -0,0 +1,96 @@
+use std::f64::consts::PI;
+use std::path::Path;
+
+use super::distribution::Distribution2D;
+use super::{equirect_direction, equirect_uv, rotate_y, Environment};
+use crate::codec::{self, HdrImage};
+use crate::vec3::{Color, Vec3};
+
+// Light from an equirectangular (latitude-longitude) HDR image, such as a photographed sky or
+// studio. Directions are drawn in proportion to the luminance of the pixels, so small bright
+// features like the sun are found by shadow rays rather than left to chance
+pub struct ImageMap {
+    image: HdrImage,
+    intensity: f64,
+    // Radians about the vertical axis
+    rotation: f64,
+    // Over the pixels, row by row from the top
+    distribution: Distribution2D,
+}
+
+impl ImageMap {
+    // `intensity` scales the radiance, and `rotation` turns the image about the vertical axis by
+    // that many degrees
+    pub fn new(image: HdrImage, intensity: f64, rotation: f64) -> ImageMap {
+        // Rows near the poles cover less of the sphere, so they are weighted down by sin(theta)
+        let weights: Vec<f64> = (0..image.height)
+            .flat_map(|y| {
+                #[allow(clippy::cast_precision_loss)] // Image dimensions are far below 2^52
+                let sin_theta = (PI * (y as f64 + 0.5) / image.height as f64).sin();
+                let image = &image;
+                (0..image.width).map(move |x| {
+                    let [r, g, b] = image.get(x, y);
+                    let color = Color::new(f64::from(r), f64::from(g), f64::from(b));
+                    color.luminance() * sin_theta
+                })
+            })
+            .collect();
+        let distribution = Distribution2D::new(&weights, image.width);
+
+        ImageMap {
+            image,
+            intensity,
+            rotation: rotation.to_radians(),
+            distribution,
+        }
+    }
+
+    // Reads a Radiance .hdr, PFM or OpenEXR image
+    pub fn load(path: &Path, intensity: f64, rotation: f64) -> Result<ImageMap, codec::Error> {
+        Ok(ImageMap::new(codec::load_hdr(path)?, intensity, rotation))
+    }
+
+    // Column and row of the pixel seen in `direction`, in the image's own orientation, along with
+    // sin(theta) of the direction
+    fn pixel(&self, direction: &Vec3) -> (usize, usize, f64) {
+        let direction = rotate_y(&direction.unit_vector(), -self.rotation);
+        let (u, v) = equirect_uv(&direction);
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )] // Coordinates within [0, 1] of the image size
+        let (column, row) = (
+            ((u * self.image.width as f64) as usize).min(self.image.width - 1),
+            (((1.0 - v) * self.image.height as f64) as usize).min(self.image.height - 1),
+        );
+        let sin_theta = (1.0 - direction.y() * direction.y()).max(0.0).sqrt();
+        (column, row, sin_theta)
+    }
+}
+
+impl Environment for ImageMap {
+    fn radiance(&self, direction: &Vec3) -> Color {
+        let (column, row, _) = self.pixel(direction);
+        let [r, g, b] = self.image.get(column, row);
+        Color::new(f64::from(r), f64::from(g), f64::from(b)) * self.intensity
+    }
+
+    fn sampled(&self) -> bool {
+        true
+    }
+
+    // The density over the image, divided by the solid angle a unit of it covers
+    fn pdf_value(&self, direction: &Vec3) -> f64 {
+        let (column, row, sin_theta) = self.pixel(direction);
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(column, row) / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn random(&self, u: (f64, f64)) -> Vec3 {
+        let ((x, y), _) = self.distribution.sample(u);
+        rotate_y(&equirect_direction(x, 1.0 - y), self.rotation)
+    }
+}
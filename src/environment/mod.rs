@@ -0,0 +1,58 @@
+mod distribution;
+pub mod image_map;
+pub mod sky;
+pub mod uniform;
+
+use std::f64::consts::PI;
+
+use crate::vec3::{Color, Vec3};
+
+// What surrounds the scene: the radiance arriving along rays that hit nothing
+pub trait Environment: Sync + Send {
+    // Radiance arriving from `direction`, which need not be a unit vector
+    fn radiance(&self, direction: &Vec3) -> Color;
+
+    // Whether direct lighting samples the environment like a light, which only pays off when
+    // some directions are much brighter than others. Only environments that are sampled implement
+    // `pdf_value` and `random`
+    fn sampled(&self) -> bool {
+        false
+    }
+
+    // Density, per unit solid angle, of `random` choosing `direction`
+    fn pdf_value(&self, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    // Unit direction chosen by the point `u` of the unit square
+    fn random(&self, _u: (f64, f64)) -> Vec3 {
+        Vec3::new(0.0, 1.0, 0.0)
+    }
+}
+
+// Coordinates of a unit direction in an equirectangular image, laid out like a sphere's texture
+// coordinates: u goes once around the vertical axis and v from straight down (0) to straight up (1)
+fn equirect_uv(direction: &Vec3) -> (f64, f64) {
+    crate::hittable::sphere::get_sphere_uv(direction)
+}
+
+// Inverse of `equirect_uv`
+fn equirect_direction(u: f64, v: f64) -> Vec3 {
+    let theta = v * PI;
+    let phi = u * 2.0 * PI - PI;
+    Vec3::new(
+        theta.sin() * phi.cos(),
+        -theta.cos(),
+        -theta.sin() * phi.sin(),
+    )
+}
+
+// Turns `direction` by `angle` radians about the vertical axis, counterclockwise seen from above
+fn rotate_y(direction: &Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    Vec3::new(
+        cos * direction.x() + sin * direction.z(),
+        direction.y(),
+        -sin * direction.x() + cos * direction.z(),
+    )
+}
@@ -0,0 +1,206 @@
+use std::f64::consts::PI;
+
+use super::Environment;
+use crate::onb::Onb;
+use crate::vec3::{Color, Vec3};
+
+// Luminance, in cd/m², that a radiance of 1 stands for. A white diffuse surface under a high sun
+// comes out around 1
+const UNIT_LUMINANCE: f64 = 40_000.0;
+// Luminance of the sun seen from above the atmosphere, in cd/m²
+const SUN_LUMINANCE: f64 = 1.9e9;
+// Angular radius of the sun, in radians
+const SUN_RADIUS: f64 = 0.004_65;
+// Share of the samples spent on the sun disk, the rest going over the whole sphere
+const SUN_SAMPLES: f64 = 0.5;
+// Wavelengths in micrometres that the red, green and blue sunlight is attenuated at
+const WAVELENGTHS: [f64; 3] = [0.65, 0.57, 0.475];
+
+// Clear sky after Preetham, Shirley and Smits, "A Practical Analytic Model for Daylight" (1999),
+// with the sun as a small bright disk in it. Below the horizon the sky carries on as it looks at
+// the horizon, for scenes without a ground
+pub struct Sky {
+    // Unit vector towards the sun
+    sun: Vec3,
+    // Coefficients A to E of the Perez distribution for the chromaticities x and y and the
+    // luminance Y
+    perez: [[f64; 5]; 3],
+    // Zenith chromaticities and luminance, divided by the Perez distribution at the zenith so
+    // that multiplying by it elsewhere gives the sky there
+    zenith: [f64; 3],
+    sun_radiance: Color,
+    cos_sun_radius: f64,
+    intensity: f64,
+}
+
+impl Sky {
+    // `elevation` is the sun's angle above the horizon and `azimuth` its angle from +z towards +x,
+    // both in degrees. `turbidity` measures the haze, from 2 for a very clear sky to 10 for a hazy
+    // one, and `intensity` scales the sky and sun together
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64, intensity: f64) -> Sky {
+        let elevation = elevation.clamp(0.0, 90.0).to_radians();
+        let azimuth = azimuth.to_radians();
+        let t = turbidity;
+        let sun = Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            elevation.cos() * azimuth.cos(),
+        );
+        let theta_sun = PI / 2.0 - elevation;
+
+        let perez = [
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+        ];
+
+        let zenith_x = zenith_chromaticity(
+            t,
+            theta_sun,
+            [
+                [0.00166, -0.00375, 0.00209, 0.0],
+                [-0.02903, 0.06377, -0.03202, 0.00394],
+                [0.11693, -0.21196, 0.06052, 0.25886],
+            ],
+        );
+        let zenith_y = zenith_chromaticity(
+            t,
+            theta_sun,
+            [
+                [0.00275, -0.00610, 0.00317, 0.0],
+                [-0.04214, 0.08970, -0.04153, 0.00516],
+                [0.15346, -0.26756, 0.06670, 0.26688],
+            ],
+        );
+        // In kcd/m²
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_luminance =
+            ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192) * 1000.0 / UNIT_LUMINANCE;
+
+        let zenith = [zenith_x, zenith_y, zenith_luminance.max(0.0)];
+        let zenith = [0, 1, 2].map(|i| zenith[i] / perez_distribution(&perez[i], 0.0, theta_sun));
+
+        Sky {
+            sun,
+            perez,
+            zenith,
+            sun_radiance: sun_transmittance(t, theta_sun) * (SUN_LUMINANCE / UNIT_LUMINANCE),
+            cos_sun_radius: SUN_RADIUS.cos(),
+            intensity,
+        }
+    }
+
+    fn sky_radiance(&self, direction: &Vec3) -> Color {
+        let theta = direction.y().clamp(0.0, 1.0).acos();
+        let gamma = direction.dot(&self.sun).clamp(-1.0, 1.0).acos();
+        let [x, y, luminance] =
+            [0, 1, 2].map(|i| self.zenith[i] * perez_distribution(&self.perez[i], theta, gamma));
+        xyy_to_rgb(x, y, luminance)
+    }
+
+    fn sun_cone_pdf(&self) -> f64 {
+        1.0 / (2.0 * PI * (1.0 - self.cos_sun_radius))
+    }
+}
+
+impl Environment for Sky {
+    fn radiance(&self, direction: &Vec3) -> Color {
+        let direction = direction.unit_vector();
+        let mut radiance = self.sky_radiance(&direction);
+        if direction.dot(&self.sun) >= self.cos_sun_radius && direction.y() >= 0.0 {
+            radiance += &self.sun_radiance;
+        }
+        radiance * self.intensity
+    }
+
+    fn sampled(&self) -> bool {
+        true
+    }
+
+    fn pdf_value(&self, direction: &Vec3) -> f64 {
+        let mut pdf = (1.0 - SUN_SAMPLES) / (4.0 * PI);
+        if direction.unit_vector().dot(&self.sun) >= self.cos_sun_radius {
+            pdf += SUN_SAMPLES * self.sun_cone_pdf();
+        }
+        pdf
+    }
+
+    fn random(&self, (u0, u1): (f64, f64)) -> Vec3 {
+        if u0 >= SUN_SAMPLES {
+            return Vec3::uniform_sphere(((u0 - SUN_SAMPLES) / (1.0 - SUN_SAMPLES), u1));
+        }
+
+        // Uniform over the cone of directions towards the sun disk
+        let z = 1.0 - u1 * (1.0 - self.cos_sun_radius);
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * u0 / SUN_SAMPLES;
+        Onb::build_from_w(&self.sun).local(&Vec3::new(r * phi.cos(), r * phi.sin(), z))
+    }
+}
+
+// Perez et al.'s sky distribution for a direction `theta` from the zenith and `gamma` from the sun:
+// horizon darkening or brightening, its gradient, the glow around the sun and its width, and light
+// scattered back
+fn perez_distribution(coefficients: &[f64; 5], theta: f64, gamma: f64) -> f64 {
+    let [darkening, gradient, circumsolar, width, backscatter] = *coefficients;
+    let cos_theta = theta.cos().max(1e-3);
+    (1.0 + darkening * (gradient / cos_theta).exp())
+        * (1.0 + circumsolar * (width * gamma).exp() + backscatter * gamma.cos().powi(2))
+}
+
+// Chromaticity at the zenith, a cubic in the sun's zenith angle for each power of the turbidity
+fn zenith_chromaticity(turbidity: f64, theta_sun: f64, rows: [[f64; 4]; 3]) -> f64 {
+    let thetas = [theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.0];
+    let turbidities = [turbidity * turbidity, turbidity, 1.0];
+    rows.iter()
+        .zip(turbidities)
+        .map(|(row, t)| t * row.iter().zip(thetas).map(|(c, th)| c * th).sum::<f64>())
+        .sum()
+}
+
+// Share of the sunlight reaching the ground through the air and the haze, in each channel
+fn sun_transmittance(turbidity: f64, theta_sun: f64) -> Color {
+    // Air mass relative to the zenith, which stays finite at the horizon
+    let degrees = theta_sun.to_degrees();
+    let air_mass = 1.0 / (theta_sun.cos() + 0.15 * (93.885 - degrees).powf(-1.253));
+    // Ångström's haze coefficient, with the ratio of small to large particles fixed
+    let beta = 0.046_08 * turbidity - 0.045_86;
+    let [r, g, b] = WAVELENGTHS.map(|lambda| {
+        let rayleigh = (-0.008_735 * lambda.powf(-4.08) * air_mass).exp();
+        let aerosol = (-beta * lambda.powf(-1.3) * air_mass).exp();
+        rayleigh * aerosol
+    });
+    Color::new(r, g, b)
+}
+
+// Linear Rec. 709 color of a CIE xyY color, with colors outside the gamut clipped
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    if y <= 0.0 {
+        return Color::default();
+    }
+    let big_x = x * luminance / y;
+    let big_z = (1.0 - x - y) * luminance / y;
+    Color::new(
+        (3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z).max(0.0),
+        (-0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z).max(0.0),
+        (0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z).max(0.0),
+    )
+}
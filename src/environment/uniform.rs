@@ -0,0 +1,19 @@
+use super::Environment;
+use crate::vec3::{Color, Vec3};
+
+// The same radiance from every direction, as given by a scene's background color
+pub struct Uniform {
+    color: Color,
+}
+
+impl Uniform {
+    pub fn new(color: Color) -> Uniform {
+        Uniform { color }
+    }
+}
+
+impl Environment for Uniform {
+    fn radiance(&self, _direction: &Vec3) -> Color {
+        self.color
+    }
+}
@@ -0,0 +1,217 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::codec::HdrImage;
+use crate::tiles::Tile;
+use crate::vec3::Color;
+
+const MAGIC: &[u8; 8] = b"RTCHKPT1";
+// Sum, sample count, luminance mean, squared deviations and convergence flag
+const PIXEL_BYTES: usize = 3 * 8 + 8 + 8 + 8 + 1;
+
+// Accumulation buffer holding the running sums of every pixel, row by row from the top, so that
+// samples can be added in passes and the image read out at any point
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Pixel>,
+}
+
+// Sum of the samples taken in a pixel and how many there were, along with the running statistics
+// of their luminance that adaptive sampling judges convergence by
+#[derive(Clone, Default)]
+pub struct Pixel {
+    pub sum: Color,
+    pub samples: u64,
+    // Running mean and sum of squared deviations of the luminance (Welford's method)
+    mean: f64,
+    squared_deviations: f64,
+    pub converged: bool,
+}
+
+impl Pixel {
+    pub fn add(&mut self, color: &Color) {
+        self.sum += color;
+        self.samples += 1;
+
+        #[allow(clippy::cast_precision_loss)] // Sample counts are far below 2^52
+        let count = self.samples as f64;
+        let luminance = color.luminance();
+        let delta = luminance - self.mean;
+        self.mean += delta / count;
+        self.squared_deviations += delta * (luminance - self.mean);
+    }
+
+    pub fn color(&self) -> Color {
+        #[allow(clippy::cast_precision_loss)] // Sample counts are far below 2^52
+        let samples = self.samples as f64;
+        self.sum / samples.max(1.0)
+    }
+
+    // Whether the standard error of the mean luminance is within `threshold` of the mean
+    pub fn within_noise_threshold(&self, threshold: f64) -> bool {
+        if self.samples < 2 {
+            return false;
+        }
+        #[allow(clippy::cast_precision_loss)] // Sample counts are far below 2^52
+        let count = self.samples as f64;
+        let standard_error = (self.squared_deviations / (count - 1.0) / count).sqrt();
+        standard_error <= threshold * self.mean
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Malformed(&'static str),
+    // The checkpoint was rendered from a different scene or with different settings
+    Mismatch,
+    InFile(PathBuf, Box<Error>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Malformed(reason) => write!(f, "malformed checkpoint: {reason}"),
+            Error::Mismatch => write!(
+                f,
+                "checkpoint was rendered from a different scene or with different settings"
+            ),
+            Error::InFile(path, e) => write!(f, "{}: {e}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Film {
+        Film {
+            width,
+            height,
+            pixels: vec![Pixel::default(); width * height],
+        }
+    }
+
+    pub fn to_image(&self, rgb: impl Fn(&Pixel) -> [f32; 3]) -> HdrImage {
+        let mut image = HdrImage::new(self.width, self.height);
+        for (index, pixel) in self.pixels.iter().enumerate() {
+            image.set(index % self.width, index / self.width, rgb(pixel));
+        }
+        image
+    }
+
+    // Copy of the pixels in a tile, row by row
+    pub fn tile(&self, tile: &Tile) -> Vec<Pixel> {
+        (tile.y0..tile.y1)
+            .flat_map(|y| &self.pixels[y * self.width + tile.x0..y * self.width + tile.x1])
+            .cloned()
+            .collect()
+    }
+
+    pub fn set_tile(&mut self, tile: &Tile, pixels: &[Pixel]) {
+        for (y, row) in (tile.y0..tile.y1).zip(pixels.chunks(tile.width())) {
+            self.pixels[y * self.width + tile.x0..y * self.width + tile.x1].clone_from_slice(row);
+        }
+    }
+
+    // Writes the film to a checkpoint that `load` can resume from. `scene_hash` identifies what
+    // was rendered. The file is written beside the old one and then moved over it, so a crash
+    // while saving leaves the previous checkpoint intact
+    pub fn save(&self, path: &Path, scene_hash: u64) -> Result<(), Error> {
+        let mut out = Vec::with_capacity(MAGIC.len() + 24 + self.pixels.len() * PIXEL_BYTES);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&scene_hash.to_le_bytes());
+        out.extend_from_slice(&(self.width as u64).to_le_bytes());
+        out.extend_from_slice(&(self.height as u64).to_le_bytes());
+        for pixel in &self.pixels {
+            for value in [pixel.sum.x(), pixel.sum.y(), pixel.sum.z()] {
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+            out.extend_from_slice(&pixel.samples.to_le_bytes());
+            out.extend_from_slice(&pixel.mean.to_le_bytes());
+            out.extend_from_slice(&pixel.squared_deviations.to_le_bytes());
+            out.push(u8::from(pixel.converged));
+        }
+
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        let in_file = |e: io::Error| Error::InFile(path.to_path_buf(), Box::new(Error::Io(e)));
+        fs::write(&temporary, out).map_err(in_file)?;
+        fs::rename(&temporary, path).map_err(in_file)
+    }
+
+    // Reads a checkpoint written by `save`, which must match the scene and image size
+    pub fn load(path: &Path, scene_hash: u64, width: usize, height: usize) -> Result<Film, Error> {
+        let in_file = |e: Error| Error::InFile(path.to_path_buf(), Box::new(e));
+        let bytes = fs::read(path).map_err(|e| in_file(Error::Io(e)))?;
+        Film::decode(&bytes, scene_hash, width, height).map_err(in_file)
+    }
+
+    fn decode(bytes: &[u8], scene_hash: u64, width: usize, height: usize) -> Result<Film, Error> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(Error::Malformed("not a checkpoint"));
+        }
+        if reader.u64()? != scene_hash
+            || reader.u64()? != width as u64
+            || reader.u64()? != height as u64
+        {
+            return Err(Error::Mismatch);
+        }
+        if reader.bytes.len() != width * height * PIXEL_BYTES {
+            return Err(Error::Malformed("wrong number of pixels"));
+        }
+
+        let mut film = Film::new(width, height);
+        for pixel in &mut film.pixels {
+            pixel.sum = Color::new(reader.f64()?, reader.f64()?, reader.f64()?);
+            pixel.samples = reader.u64()?;
+            pixel.mean = reader.f64()?;
+            pixel.squared_deviations = reader.f64()?;
+            pixel.converged = reader.take(1)?[0] != 0;
+        }
+        Ok(film)
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], Error> {
+        if self.bytes.len() < count {
+            return Err(Error::Malformed("unexpected end of file"));
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().expect("Eight bytes")))
+    }
+
+    fn f64(&mut self) -> Result<f64, Error> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+}
+
+// 64-bit FNV-1a, which unlike the standard library's hasher is stable between builds, so that
+// checkpoints can be resumed by a rebuilt renderer
+pub fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
@@ -3,7 +3,7 @@ use std::sync::Arc;
 use super::{
     htlist::HittableList, xyrect::XYRect, xzrect::XZRect, yzrect::YZRect, HitRecord, Hittable,
 };
-use crate::{aabb::Aabb, material::Material, ray::Ray, vec3::Point3};
+use crate::{aabb::Aabb, material::Material, ray::Ray, utils::Rng, vec3::Point3};
 
 pub struct B0x {
     box_min: Point3,
@@ -73,8 +73,8 @@ impl B0x {
 }
 
 impl Hittable for B0x {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        self.sides.hit(r, t_min, t_max)
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut Rng) -> Option<HitRecord> {
+        self.sides.hit(r, t_min, t_max, rng)
     }
 
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
@@ -1,75 +1,277 @@
-use std::{cmp::Ordering, sync::Arc};
+use std::{fmt, sync::Arc, time::Duration, time::Instant};
 
-use super::{htlist::HittableList, HitRecord, Hittable};
-use crate::{aabb::Aabb, utils};
+use super::{HitRecord, Hittable};
+use crate::{aabb::Aabb, ray::Ray, utils::Rng, vec3::Point3};
 
+// Number of buckets primitive centroids are sorted into when evaluating split planes
+const BINS: usize = 12;
+// Nodes with more primitives than this are always split
+const MAX_LEAF_SIZE: usize = 4;
+// Estimated cost of visiting a node, relative to intersecting one primitive
+const TRAVERSAL_COST: f64 = 0.125;
+// Nodes this deep become leaves regardless of size, which bounds the traversal stack
+pub const MAX_DEPTH: usize = 64;
+
+// Bounding volume hierarchy built with the surface area heuristic (SAH): each node is split
+// where the expected cost of tracing a ray through both halves is lowest, or becomes a leaf
+// holding several primitives when splitting would not pay off
 pub struct BVHNode {
-    left: Arc<dyn Hittable>,
-    right: Arc<dyn Hittable>,
+    pub(super) bbox: Aabb,
+    pub(super) contents: Contents,
+}
+
+pub(super) enum Contents {
+    // Objects with their index in the list the tree was built from
+    Leaf(Vec<(usize, Arc<dyn Hittable>)>),
+    // Children are ordered along the split axis
+    Split(usize, Box<BVHNode>, Box<BVHNode>),
+}
+
+#[derive(Default)]
+pub struct Stats {
+    pub primitives: usize,
+    pub nodes: usize,
+    pub leaves: usize,
+    pub max_depth: usize,
+    pub max_leaf_size: usize,
+    // Expected cost of a ray through the tree, in units of primitive intersections
+    pub sah_cost: f64,
+    pub build_time: Duration,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} primitives, {} nodes, {} leaves (max size {}), depth {}, SAH cost {:.2}, built in {:.1} ms",
+            self.primitives,
+            self.nodes,
+            self.leaves,
+            self.max_leaf_size,
+            self.max_depth,
+            self.sah_cost,
+            self.build_time.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+struct Primitive {
+    index: usize,
+    object: Arc<dyn Hittable>,
     bbox: Aabb,
+    centroid: Point3,
 }
 
 impl BVHNode {
-    pub fn new_from_list(list: &HittableList, time0: f64, time1: f64) -> BVHNode {
-        BVHNode::new_from_array(&list.objects, 0, list.objects.len(), time0, time1)
-    }
+    pub fn build(objects: &[Arc<dyn Hittable>], time0: f64, time1: f64) -> (BVHNode, Stats) {
+        assert!(!objects.is_empty(), "No objects in bvh_node constructor");
+        let start = Instant::now();
+
+        let mut primitives: Vec<Primitive> = objects
+            .iter()
+            .enumerate()
+            .map(|(index, object)| {
+                let bbox = object
+                    .bounding_box(time0, time1)
+                    .expect("No bounding box in bvh_node constructor");
+                let centroid = 0.5 * (bbox.min() + bbox.max());
+                Primitive {
+                    index,
+                    object: object.clone(),
+                    bbox,
+                    centroid,
+                }
+            })
+            .collect();
 
-    pub fn new_from_array(
-        objects: &[Arc<dyn Hittable>],
-        start: usize,
-        end: usize,
-        time0: f64,
-        time1: f64,
-    ) -> BVHNode {
-        let axis = utils::random_int(0, 2);
-        let comparator = match axis {
-            0 => box_x_compare,
-            1 => box_y_compare,
-            2 => box_z_compare,
-            _ => panic!("Unexpected axis {}", axis),
+        let mut stats = Stats {
+            primitives: objects.len(),
+            ..Stats::default()
         };
+        let root = BVHNode::build_node(&mut primitives, 1, &mut stats);
+        stats.sah_cost = root.cost() / root.bbox.surface_area().max(f64::MIN_POSITIVE);
+        stats.build_time = start.elapsed();
+
+        (root, stats)
+    }
+
+    fn build_node(primitives: &mut [Primitive], depth: usize, stats: &mut Stats) -> BVHNode {
+        stats.nodes += 1;
+        stats.max_depth = stats.max_depth.max(depth);
 
-        let span = end - start;
-
-        let left: Arc<dyn Hittable>;
-        let right: Arc<dyn Hittable>;
-
-        if span == 1 {
-            left = objects[start].clone();
-            right = objects[start].clone();
-        } else if span == 2 {
-            if comparator(&objects[start], &objects[start + 1]) == Ordering::Less {
-                left = objects[start].clone();
-                right = objects[start + 1].clone();
-            } else {
-                left = objects[start + 1].clone();
-                right = objects[start].clone();
+        let bbox = primitives[1..]
+            .iter()
+            .fold(primitives[0].bbox.clone(), |b, p| {
+                Aabb::surrounding_box(&b, &p.bbox)
+            });
+
+        let count = primitives.len();
+        // Where to divide the primitives and along which axis
+        let split = if count == 1 || depth == MAX_DEPTH {
+            None
+        } else {
+            match best_split(primitives, &bbox) {
+                Some(split) if count > MAX_LEAF_SIZE || split.cost < leaf_cost(count) => {
+                    let mid = partition(primitives, |p| {
+                        bin_index(p.centroid[split.axis], split.min, split.extent) <= split.bin
+                    });
+                    Some((mid, split.axis))
+                }
+                // Every centroid is in the same place, so any split is as good as another
+                None if count > MAX_LEAF_SIZE => Some((count / 2, 0)),
+                _ => None,
             }
+        };
+
+        let contents = if let Some((mid, axis)) = split {
+            let (left, right) = primitives.split_at_mut(mid);
+            Contents::Split(
+                axis,
+                Box::new(BVHNode::build_node(left, depth + 1, stats)),
+                Box::new(BVHNode::build_node(right, depth + 1, stats)),
+            )
         } else {
-            // Shallow clone and sort array
-            let mut objects = objects.to_vec();
-            objects.sort_by(comparator);
+            stats.leaves += 1;
+            stats.max_leaf_size = stats.max_leaf_size.max(count);
+            Contents::Leaf(
+                primitives
+                    .iter()
+                    .map(|p| (p.index, p.object.clone()))
+                    .collect(),
+            )
+        };
+
+        BVHNode { bbox, contents }
+    }
 
-            let mid = start + span / 2;
-            left = Arc::new(BVHNode::new_from_array(&objects, start, mid, time0, time1));
-            right = Arc::new(BVHNode::new_from_array(&objects, mid, end, time0, time1));
+    // Sum of each node's cost weighted by its surface area, the probability of a ray hitting it
+    fn cost(&self) -> f64 {
+        let area = self.bbox.surface_area();
+        match &self.contents {
+            Contents::Leaf(objects) => area * leaf_cost(objects.len()),
+            Contents::Split(_, left, right) => area * TRAVERSAL_COST + left.cost() + right.cost(),
         }
+    }
+}
 
-        let box_left = left
-            .bounding_box(time0, time1)
-            .expect("No bounding box in bvh_node constructor");
-        let box_right = right
-            .bounding_box(time0, time1)
-            .expect("No bounding box in bvh_node constructor");
+#[allow(clippy::cast_precision_loss)] // Primitive counts are far below 2^52
+fn leaf_cost(count: usize) -> f64 {
+    count as f64
+}
 
-        let bbox = Aabb::surrounding_box(&box_left, &box_right);
+// A plane between centroid bins: primitives in bins up to and including `bin` go left
+struct Split {
+    cost: f64,
+    axis: usize,
+    bin: usize,
+    min: f64,
+    extent: f64,
+}
+
+// Finds the cheapest split, or None if all centroids coincide
+fn best_split(primitives: &[Primitive], bbox: &Aabb) -> Option<Split> {
+    let first = primitives[0].centroid;
+    let (min, max) = primitives.iter().fold((first, first), |(min, max), p| {
+        let c = &p.centroid;
+        (
+            Point3::new(min.x().min(c.x()), min.y().min(c.y()), min.z().min(c.z())),
+            Point3::new(max.x().max(c.x()), max.y().max(c.y()), max.z().max(c.z())),
+        )
+    });
+    let parent_area = bbox.surface_area().max(f64::MIN_POSITIVE);
+
+    let mut best: Option<Split> = None;
+    for axis in 0..3 {
+        let extent = max[axis] - min[axis];
+        if extent <= 0.0 {
+            continue;
+        }
 
-        BVHNode { left, right, bbox }
+        let mut counts = [0; BINS];
+        let mut boxes: [Option<Aabb>; BINS] = Default::default();
+        for p in primitives {
+            let bin = bin_index(p.centroid[axis], min[axis], extent);
+            counts[bin] += 1;
+            boxes[bin] = Some(match &boxes[bin] {
+                Some(b) => Aabb::surrounding_box(b, &p.bbox),
+                None => p.bbox.clone(),
+            });
+        }
+
+        // Sweep from the right to find the area and count above each plane
+        let mut right_areas = [0.0; BINS];
+        let mut right_counts = [0; BINS];
+        let mut accumulated: Option<Aabb> = None;
+        let mut count = 0;
+        for bin in (1..BINS).rev() {
+            accumulated = merge(accumulated, boxes[bin].as_ref());
+            count += counts[bin];
+            right_areas[bin] = accumulated.as_ref().map_or(0.0, Aabb::surface_area);
+            right_counts[bin] = count;
+        }
+
+        // Then from the left, evaluating the plane after each bin
+        let mut accumulated: Option<Aabb> = None;
+        let mut count = 0;
+        for bin in 0..BINS - 1 {
+            accumulated = merge(accumulated, boxes[bin].as_ref());
+            count += counts[bin];
+            if count == 0 || right_counts[bin + 1] == 0 {
+                continue;
+            }
+
+            let left_area = accumulated.as_ref().map_or(0.0, Aabb::surface_area);
+            let cost = TRAVERSAL_COST
+                + (left_area * leaf_cost(count)
+                    + right_areas[bin + 1] * leaf_cost(right_counts[bin + 1]))
+                    / parent_area;
+            if best.as_ref().is_none_or(|b| cost < b.cost) {
+                best = Some(Split {
+                    cost,
+                    axis,
+                    bin,
+                    min: min[axis],
+                    extent,
+                });
+            }
+        }
+    }
+
+    best
+}
+
+fn bin_index(value: f64, min: f64, extent: f64) -> usize {
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )] // The value is within [0, BINS]
+    let bin = ((value - min) / extent * BINS as f64) as usize;
+    bin.min(BINS - 1)
+}
+
+fn merge(a: Option<Aabb>, b: Option<&Aabb>) -> Option<Aabb> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(Aabb::surrounding_box(&a, b)),
+        (a, None) => a,
+        (None, Some(b)) => Some(b.clone()),
     }
 }
 
+// Moves the primitives matching the predicate to the front and returns how many there are
+fn partition(primitives: &mut [Primitive], predicate: impl Fn(&Primitive) -> bool) -> usize {
+    let mut mid = 0;
+    for i in 0..primitives.len() {
+        if predicate(&primitives[i]) {
+            primitives.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
 impl Hittable for BVHNode {
-    fn hit(&self, r: &crate::ray::Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut Rng) -> Option<HitRecord> {
         if !self.bbox.hit(r, t_min, t_max) {
             return None;
         }
@@ -77,10 +279,23 @@ impl Hittable for BVHNode {
         let mut rec = None;
         let mut max = t_max;
 
-        for object in [&self.left, &self.right] {
-            if let Some(hr) = object.hit(r, t_min, max) {
-                max = hr.t;
-                rec = Some(hr);
+        match &self.contents {
+            Contents::Leaf(objects) => {
+                for (index, object) in objects {
+                    if let Some(mut hr) = object.hit(r, t_min, max, rng) {
+                        max = hr.t;
+                        hr.object = *index;
+                        rec = Some(hr);
+                    }
+                }
+            }
+            Contents::Split(_, left, right) => {
+                for node in [left, right] {
+                    if let Some(hr) = node.hit(r, t_min, max, rng) {
+                        max = hr.t;
+                        rec = Some(hr);
+                    }
+                }
             }
         }
 
@@ -91,35 +306,3 @@ impl Hittable for BVHNode {
         Some(self.bbox.clone())
     }
 }
-
-fn box_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>, axis: usize) -> Ordering {
-    let box_a = a
-        .bounding_box(0.0, 0.0)
-        .expect("No bounding box in bvh_node constructor");
-    let box_b = b
-        .bounding_box(0.0, 0.0)
-        .expect("No bounding box in bvh_node constructor");
-
-    let axis_a = box_a.min()[axis];
-    let axis_b = box_b.min()[axis];
-
-    if axis_a < axis_b {
-        Ordering::Less
-    } else if axis_a > axis_b {
-        Ordering::Greater
-    } else {
-        Ordering::Equal
-    }
-}
-
-fn box_x_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>) -> Ordering {
-    box_compare(a, b, 0)
-}
-
-fn box_y_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>) -> Ordering {
-    box_compare(a, b, 1)
-}
-
-fn box_z_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>) -> Ordering {
-    box_compare(a, b, 2)
-}
@@ -1,10 +1,10 @@
 use std::sync::Arc;
 
 use crate::{
-    material::{isotropic::Isotropic, Material},
-    texture::Texture,
-    utils,
-    vec3::{Color, Vec3},
+    material::Material,
+    ray::Ray,
+    utils::{self, Rng},
+    vec3::Vec3,
 };
 
 use super::{HitRecord, Hittable};
@@ -16,32 +16,24 @@ pub struct ConstantMedium {
 }
 
 impl ConstantMedium {
-    pub fn new(boundary: Arc<dyn Hittable>, density: f64, color: Color) -> ConstantMedium {
-        ConstantMedium {
-            boundary,
-            neg_inv_density: -1.0 / density,
-            phase_func: Arc::new(Isotropic::new(color)),
-        }
-    }
-
-    #[allow(dead_code)]
-    pub fn new_texture(
+    // `phase_func` scatters light inside the medium, normally an `Isotropic` material
+    pub fn new(
         boundary: Arc<dyn Hittable>,
         density: f64,
-        texture: Box<dyn Texture>,
+        phase_func: Arc<dyn Material>,
     ) -> ConstantMedium {
         ConstantMedium {
             boundary,
             neg_inv_density: -1.0 / density,
-            phase_func: Arc::new(Isotropic::new_texture(texture)),
+            phase_func,
         }
     }
 }
 
 impl Hittable for ConstantMedium {
-    fn hit(&self, r: &crate::ray::Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        if let Some(mut rec1) = self.boundary.hit(r, f64::NEG_INFINITY, f64::INFINITY) {
-            if let Some(mut rec2) = self.boundary.hit(r, rec1.t + 0.0001, f64::INFINITY) {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut Rng) -> Option<HitRecord> {
+        if let Some(mut rec1) = self.boundary.hit(r, f64::NEG_INFINITY, f64::INFINITY, rng) {
+            if let Some(mut rec2) = self.boundary.hit(r, rec1.t + 0.0001, f64::INFINITY, rng) {
                 if rec1.t < t_min {
                     rec1.t = t_min;
                 }
@@ -57,7 +49,7 @@ impl Hittable for ConstantMedium {
 
                 let ray_length = r.direction().length();
                 let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
-                let hit_distance = self.neg_inv_density * utils::random_float().ln();
+                let hit_distance = self.neg_inv_density * utils::random_float(rng).ln();
 
                 if hit_distance > distance_inside_boundary {
                     return None;
@@ -73,6 +65,8 @@ impl Hittable for ConstantMedium {
                     u: 0.0,
                     v: 0.0,
                     front_face: true,
+                    object: 0,
+                    vertex_color: None,
                 });
             }
         }
@@ -0,0 +1,40 @@
+use super::{HitRecord, Hittable};
+use crate::{
+    aabb::Aabb,
+    ray::Ray,
+    utils::Rng,
+    vec3::{Point3, Vec3},
+};
+
+// Swaps which side of an object counts as its front, so that a one-sided light such as a ceiling
+// rectangle can face the other way. Glass turned inside out refracts as if it were the surrounding
+// medium
+pub struct FlipFace {
+    hittable: Box<dyn Hittable>,
+}
+
+impl FlipFace {
+    pub fn new(hittable: Box<dyn Hittable>) -> FlipFace {
+        FlipFace { hittable }
+    }
+}
+
+impl Hittable for FlipFace {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut Rng) -> Option<HitRecord> {
+        let mut rec = self.hittable.hit(r, t_min, t_max, rng)?;
+        rec.front_face = !rec.front_face;
+        Some(rec)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        self.hittable.bounding_box(time0, time1)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.hittable.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point3, u: (f64, f64)) -> Vec3 {
+        self.hittable.random(origin, u)
+    }
+}
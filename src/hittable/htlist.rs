@@ -3,6 +3,8 @@ use std::sync::Arc;
 use super::{HitRecord, Hittable};
 use crate::aabb::Aabb;
 use crate::ray::Ray;
+use crate::utils::Rng;
+use crate::vec3::{Point3, Vec3};
 
 #[derive(Default)]
 pub struct HittableList {
@@ -28,13 +30,17 @@ impl HittableList {
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut Rng) -> Option<HitRecord> {
         let mut hit_anything = None;
         let mut closest_so_far = t_max;
 
-        for object in &self.objects {
-            if let Some(hit) = object.hit(r, t_min, closest_so_far) {
+        for (index, object) in self.objects.iter().enumerate() {
+            if let Some(mut hit) = object.hit(r, t_min, closest_so_far, rng) {
                 closest_so_far = hit.t;
+                // A list of one, such as a world holding a single BVH, leaves the index alone
+                if self.objects.len() > 1 {
+                    hit.object = index;
+                }
                 hit_anything = Some(hit);
             }
         }
@@ -62,4 +68,28 @@ impl Hittable for HittableList {
 
         bbox
     }
+
+    // Each object is picked with equal probability, so the density is the average of theirs
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let sum: f64 = self
+            .objects
+            .iter()
+            .map(|object| object.pdf_value(origin, direction))
+            .sum();
+        #[allow(clippy::cast_precision_loss)] // Object counts are far below 2^52
+        let count = self.objects.len() as f64;
+        sum / count
+    }
+
+    // The first coordinate picks the object and is then stretched back over [0, 1), so the object
+    // still receives a well-spread point
+    fn random(&self, origin: &Point3, (u0, u1): (f64, f64)) -> Vec3 {
+        #[allow(clippy::cast_precision_loss)] // Object counts are far below 2^52
+        let count = self.objects.len() as f64;
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // Within [0, len]
+        let index = ((u0 * count) as usize).min(self.objects.len() - 1);
+        #[allow(clippy::cast_precision_loss)] // As above
+        let remapped = (u0 * count - index as f64).clamp(0.0, 1.0 - f64::EPSILON);
+        self.objects[index].random(origin, (remapped, u1))
+    }
 }
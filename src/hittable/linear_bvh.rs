@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use super::bvh_node::{self, BVHNode, Contents, Stats};
+use super::{htlist::HittableList, HitRecord, Hittable};
+use crate::aabb::Aabb;
+use crate::ray::Ray;
+use crate::utils::Rng;
+
+// A BVH flattened into one array in depth-first order, so a node's first child directly follows
+// it and only the index of the second child is stored. Traversal uses an explicit stack instead
+// of recursion, and visits the child nearer to the ray origin first so that the closest hit found
+// so far can cull the farther one.
+pub struct LinearBVH {
+    nodes: Vec<LinearNode>,
+    // Objects with their index in the list the tree was built from
+    primitives: Vec<(usize, Arc<dyn Hittable>)>,
+}
+
+struct LinearNode {
+    bbox: Aabb,
+    // First primitive of a leaf, or the second child of an interior node
+    offset: usize,
+    // Number of primitives in a leaf, zero for interior nodes
+    count: usize,
+    axis: usize,
+}
+
+impl LinearBVH {
+    // Builds the tree with the SAH builder, returning it with its statistics
+    pub fn new_from_list(list: &HittableList, time0: f64, time1: f64) -> (LinearBVH, Stats) {
+        let (root, stats) = BVHNode::build(&list.objects, time0, time1);
+
+        let mut bvh = LinearBVH {
+            nodes: Vec::with_capacity(stats.nodes),
+            primitives: Vec::with_capacity(stats.primitives),
+        };
+        bvh.flatten(root);
+        (bvh, stats)
+    }
+
+    fn flatten(&mut self, node: BVHNode) {
+        let index = self.nodes.len();
+        self.nodes.push(LinearNode {
+            bbox: node.bbox,
+            offset: self.primitives.len(),
+            count: 0,
+            axis: 0,
+        });
+
+        match node.contents {
+            Contents::Leaf(objects) => {
+                self.nodes[index].count = objects.len();
+                self.primitives.extend(objects);
+            }
+            Contents::Split(axis, left, right) => {
+                self.nodes[index].axis = axis;
+                self.flatten(*left);
+                self.nodes[index].offset = self.nodes.len();
+                self.flatten(*right);
+            }
+        }
+    }
+}
+
+impl Hittable for LinearBVH {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut Rng) -> Option<HitRecord> {
+        let direction_negative = [
+            r.direction().x() < 0.0,
+            r.direction().y() < 0.0,
+            r.direction().z() < 0.0,
+        ];
+
+        let mut rec = None;
+        let mut closest = t_max;
+
+        let mut stack = [0; bvh_node::MAX_DEPTH];
+        let mut stack_size = 0;
+        let mut current = 0;
+
+        loop {
+            let node = &self.nodes[current];
+            if node.bbox.hit(r, t_min, closest) {
+                if node.count > 0 {
+                    for (index, object) in &self.primitives[node.offset..node.offset + node.count] {
+                        if let Some(mut hr) = object.hit(r, t_min, closest, rng) {
+                            closest = hr.t;
+                            hr.object = *index;
+                            rec = Some(hr);
+                        }
+                    }
+                } else {
+                    // Children are ordered along the split axis, so a ray travelling in the
+                    // negative direction reaches the second child first
+                    let (near, far) = if direction_negative[node.axis] {
+                        (node.offset, current + 1)
+                    } else {
+                        (current + 1, node.offset)
+                    };
+                    stack[stack_size] = far;
+                    stack_size += 1;
+                    current = near;
+                    continue;
+                }
+            }
+
+            if stack_size == 0 {
+                break;
+            }
+            stack_size -= 1;
+            current = stack[stack_size];
+        }
+
+        rec
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(self.nodes[0].bbox.clone())
+    }
+}
@@ -3,11 +3,14 @@ use std::sync::Arc;
 pub mod b0x;
 pub mod bvh_node;
 pub mod constant_medium;
+pub mod flip_face;
 pub mod htlist;
+pub mod linear_bvh;
 pub mod moving_sphere;
 pub mod rotate_y;
 pub mod sphere;
 pub mod translate;
+pub mod triangle;
 pub mod xyrect;
 pub mod xzrect;
 pub mod yzrect;
@@ -15,7 +18,8 @@ pub mod yzrect;
 use crate::aabb::Aabb;
 use crate::material::Material;
 use crate::ray::Ray;
-use crate::vec3::{Point3, Vec3};
+use crate::utils::Rng;
+use crate::vec3::{Color, Point3, Vec3};
 
 pub struct HitRecord {
     pub p: Point3,
@@ -25,6 +29,13 @@ pub struct HitRecord {
     pub u: f64,
     pub v: f64,
     pub front_face: bool,
+    // Index of the object among those the scene was built from, in the order they were added. Each
+    // list or BVH of several objects records the index of the one hit, overwriting the index a
+    // nested one recorded, so a mesh or group counts as a single object
+    pub object: usize,
+    // Blended from the corner colors of a mesh triangle that has them, kept apart from (u, v) so
+    // that a mesh can have both
+    pub vertex_color: Option<Color>,
 }
 
 impl HitRecord {
@@ -39,6 +50,18 @@ impl HitRecord {
 }
 
 pub trait Hittable: Sync + Send {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut Rng) -> Option<HitRecord>;
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb>;
+
+    // Density, per unit solid angle, of `random` choosing `direction` from `origin`. Only
+    // objects that can be sampled as lights implement this and `random`, and since lights are
+    // surfaces the density never depends on random numbers
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    // Direction from `origin` to a point on the object, chosen by the point `u` of the unit square
+    fn random(&self, _origin: &Point3, _u: (f64, f64)) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
 }
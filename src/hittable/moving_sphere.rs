@@ -5,6 +5,7 @@ use super::{HitRecord, Hittable};
 use crate::aabb::Aabb;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::utils::Rng;
 use crate::vec3::{Point3, Vec3};
 
 pub struct MovingSphere {
@@ -43,7 +44,7 @@ impl MovingSphere {
 }
 
 impl Hittable for MovingSphere {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut Rng) -> Option<HitRecord> {
         let oc = r.origin() - &self.center(r.time());
         let a = r.direction().length_squared();
         let half_b = oc.dot(r.direction());
@@ -77,6 +78,8 @@ impl Hittable for MovingSphere {
             front_face: false,
             u: coords.0,
             v: coords.1,
+            object: 0,
+            vertex_color: None,
         };
 
         rec.set_face_normal(r, &outward_normal);
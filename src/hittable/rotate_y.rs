@@ -1,7 +1,7 @@
 use crate::{
     aabb::Aabb,
     ray::Ray,
-    utils,
+    utils::{self, Rng},
     vec3::{Point3, Vec3},
 };
 
@@ -60,7 +60,7 @@ impl RotateY {
 }
 
 impl Hittable for RotateY {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut Rng) -> Option<HitRecord> {
         let mut origin = *r.origin();
         let mut direction = *r.direction();
 
@@ -72,7 +72,7 @@ impl Hittable for RotateY {
 
         let rotated = Ray::new(origin, direction, r.time());
 
-        if let Some(mut rec) = self.hittable.hit(&rotated, t_min, t_max) {
+        if let Some(mut rec) = self.hittable.hit(&rotated, t_min, t_max, rng) {
             let mut p = rec.p;
             let mut normal = rec.normal;
 
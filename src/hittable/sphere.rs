@@ -1,9 +1,12 @@
+use std::f64::consts::PI;
 use std::sync::Arc;
 
 use super::{HitRecord, Hittable};
 use crate::aabb::Aabb;
 use crate::material::Material;
+use crate::onb::Onb;
 use crate::ray::Ray;
+use crate::utils::Rng;
 use crate::vec3::{Point3, Vec3};
 
 pub struct Sphere {
@@ -20,10 +23,9 @@ impl Sphere {
             material,
         }
     }
-}
 
-impl Hittable for Sphere {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    // Nearest ray parameter in range where the ray meets the sphere
+    fn root(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<f64> {
         let oc = r.origin() - &self.center;
         let a = r.direction().length_squared();
         let half_b = oc.dot(r.direction());
@@ -45,6 +47,13 @@ impl Hittable for Sphere {
             }
         }
 
+        Some(root)
+    }
+}
+
+impl Hittable for Sphere {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut Rng) -> Option<HitRecord> {
+        let root = self.root(r, t_min, t_max)?;
         let point = r.at(root);
         let outward_normal = (point - self.center) / self.radius;
         let coords = get_sphere_uv(&outward_normal);
@@ -57,6 +66,8 @@ impl Hittable for Sphere {
             front_face: false,
             u: coords.0,
             v: coords.1,
+            object: 0,
+            vertex_color: None,
         };
 
         rec.set_face_normal(r, &outward_normal);
@@ -70,6 +81,51 @@ impl Hittable for Sphere {
             self.center + Vec3::new_single(self.radius),
         ))
     }
+
+    // Directions are sampled uniformly within the cone the sphere subtends
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        if self
+            .root(&Ray::new(*origin, *direction, 0.0), 0.001, f64::INFINITY)
+            .is_none()
+        {
+            return 0.0;
+        }
+
+        let distance_squared = (self.center - *origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        // From inside, every direction reaches the sphere
+        if distance_squared <= radius_squared {
+            return 1.0 / (4.0 * PI);
+        }
+
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: &Point3, u: (f64, f64)) -> Vec3 {
+        let direction = self.center - *origin;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return Vec3::uniform_sphere(u);
+        }
+
+        let uvw = Onb::build_from_w(&direction);
+        uvw.local(&direction_to_sphere(self.radius, distance_squared, u))
+    }
+}
+
+// Direction around +z within the cone subtended by a sphere at the given distance, spread
+// uniformly over the cone as `(r1, r2)` covers the unit square
+fn direction_to_sphere(radius: f64, distance_squared: f64, (r1, r2): (f64, f64)) -> Vec3 {
+    let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).sqrt() - 1.0);
+
+    let phi = 2.0 * PI * r1;
+    let x = phi.cos() * (1.0 - z * z).sqrt();
+    let y = phi.sin() * (1.0 - z * z).sqrt();
+
+    Vec3::new(x, y, z)
 }
 
 pub fn get_sphere_uv(p: &Point3) -> (f64, f64) {
@@ -1,5 +1,5 @@
 use super::{HitRecord, Hittable};
-use crate::{aabb::Aabb, ray::Ray, vec3::Vec3};
+use crate::{aabb::Aabb, ray::Ray, utils::Rng, vec3::Vec3};
 
 pub struct Translate {
     hittable: Box<dyn Hittable>,
@@ -16,9 +16,9 @@ impl Translate {
 }
 
 impl Hittable for Translate {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut Rng) -> Option<HitRecord> {
         let moved = Ray::new(r.origin() - &self.offset, *r.direction(), r.time());
-        if let Some(mut rec) = self.hittable.hit(&moved, t_min, t_max) {
+        if let Some(mut rec) = self.hittable.hit(&moved, t_min, t_max, rng) {
             let normal = rec.normal;
             rec.p += &self.offset;
             rec.set_face_normal(&moved, &normal);
@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use super::{HitRecord, Hittable};
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::utils::Rng;
+use crate::vec3::{Color, Point3, Vec3};
+
+pub struct Triangle {
+    vertices: [Point3; 3],
+    // Per-vertex shading normals, falling back to the face normal when absent
+    normals: Option<[Vec3; 3]>,
+    // Per-vertex texture coordinates, falling back to the barycentric coordinates when absent
+    uvs: Option<[(f64, f64); 3]>,
+    // Per-vertex colors, blended by the barycentric coordinates for the `vertex_color` texture
+    colors: Option<[Color; 3]>,
+    material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(v0: Point3, v1: Point3, v2: Point3, material: Arc<dyn Material>) -> Triangle {
+        Triangle::new_with_attributes([v0, v1, v2], None, None, None, material)
+    }
+
+    pub fn new_with_attributes(
+        vertices: [Point3; 3],
+        normals: Option<[Vec3; 3]>,
+        uvs: Option<[(f64, f64); 3]>,
+        colors: Option<[Color; 3]>,
+        material: Arc<dyn Material>,
+    ) -> Triangle {
+        Triangle {
+            vertices,
+            normals,
+            uvs,
+            colors,
+            material,
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    // Möller-Trumbore intersection
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut Rng) -> Option<HitRecord> {
+        let [v0, v1, v2] = &self.vertices;
+        let edge1 = v1 - v0;
+        let edge2 = v2 - v0;
+
+        let pvec = r.direction().cross(&edge2);
+        let det = edge1.dot(&pvec);
+        if det == 0.0 {
+            // Ray is parallel to the triangle. Nearly parallel rays are left to the barycentric
+            // tests below, as any fixed threshold would also reject small triangles
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        let tvec = r.origin() - v0;
+        let b1 = tvec.dot(&pvec) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+
+        let qvec = tvec.cross(&edge1);
+        let b2 = r.direction().dot(&qvec) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(&qvec) * inv_det;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let b0 = 1.0 - b1 - b2;
+        let (u, v) = match &self.uvs {
+            Some([uv0, uv1, uv2]) => (
+                b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0,
+                b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1,
+            ),
+            None => (b1, b2),
+        };
+
+        let mut rec = HitRecord {
+            p: r.at(t),
+            normal: Vec3::default(),
+            material: self.material.clone(),
+            t,
+            u,
+            v,
+            front_face: false,
+            object: 0,
+            vertex_color: self
+                .colors
+                .as_ref()
+                .map(|[c0, c1, c2]| b0 * c0 + b1 * c1 + b2 * c2),
+        };
+
+        // The side that was hit is decided by the geometric normal, the shading normal is only
+        // flipped to match it
+        rec.set_face_normal(r, &edge1.cross(&edge2).unit_vector());
+        if let Some([n0, n1, n2]) = &self.normals {
+            let shading = (b0 * n0 + b1 * n1 + b2 * n2).unit_vector();
+            rec.normal = if rec.front_face { shading } else { -shading };
+        }
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        let [v0, v1, v2] = &self.vertices;
+        // Pad each dimension a small amount so axis-aligned triangles still have a non-zero width
+        let pad = Vec3::new_single(0.0001);
+        let min = Point3::new(
+            v0.x().min(v1.x()).min(v2.x()),
+            v0.y().min(v1.y()).min(v2.y()),
+            v0.z().min(v1.z()).min(v2.z()),
+        );
+        let max = Point3::new(
+            v0.x().max(v1.x()).max(v2.x()),
+            v0.y().max(v1.y()).max(v2.y()),
+            v0.z().max(v1.z()).max(v2.z()),
+        );
+        Some(Aabb::new(min - pad, max + pad))
+    }
+}
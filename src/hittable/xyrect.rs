@@ -4,6 +4,8 @@ use super::{HitRecord, Hittable};
 use crate::{
     aabb::Aabb,
     material::Material,
+    ray::Ray,
+    utils::Rng,
     vec3::{Point3, Vec3},
 };
 
@@ -27,10 +29,9 @@ impl XYRect {
             k,
         }
     }
-}
 
-impl Hittable for XYRect {
-    fn hit(&self, r: &crate::ray::Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    // Ray parameter and in-plane coordinates of where the ray crosses the rectangle
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
         let t = (self.k - r.origin().z()) / r.direction().z();
         if t < t_min || t > t_max {
             return None;
@@ -42,6 +43,14 @@ impl Hittable for XYRect {
             return None;
         }
 
+        Some((t, x, y))
+    }
+}
+
+impl Hittable for XYRect {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut Rng) -> Option<HitRecord> {
+        let (t, x, y) = self.intersect(r, t_min, t_max)?;
+
         let mut rec = HitRecord {
             u: (x - self.x0) / (self.x1 - self.x0),
             v: (y - self.y0) / (self.y1 - self.y0),
@@ -50,6 +59,8 @@ impl Hittable for XYRect {
             p: r.at(t),
             normal: Vec3::default(),
             front_face: false,
+            object: 0,
+            vertex_color: None,
         };
 
         rec.set_face_normal(r, &Vec3::new(0.0, 0.0, 1.0));
@@ -64,4 +75,27 @@ impl Hittable for XYRect {
             Point3::new(self.x1, self.y1, self.k + 0.0001),
         ))
     }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let Some((t, _, _)) =
+            self.intersect(&Ray::new(*origin, *direction, 0.0), 0.001, f64::INFINITY)
+        else {
+            return 0.0;
+        };
+
+        let area = (self.x1 - self.x0) * (self.y1 - self.y0);
+        let distance_squared = t * t * direction.length_squared();
+        let cosine = (direction.z() / direction.length()).abs();
+
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, origin: &Point3, (u0, u1): (f64, f64)) -> Vec3 {
+        let point = Point3::new(
+            self.x0 + u0 * (self.x1 - self.x0),
+            self.y0 + u1 * (self.y1 - self.y0),
+            self.k,
+        );
+        point - *origin
+    }
 }
@@ -4,6 +4,8 @@ use super::{HitRecord, Hittable};
 use crate::{
     aabb::Aabb,
     material::Material,
+    ray::Ray,
+    utils::Rng,
     vec3::{Point3, Vec3},
 };
 
@@ -27,10 +29,9 @@ impl YZRect {
             k,
         }
     }
-}
 
-impl Hittable for YZRect {
-    fn hit(&self, r: &crate::ray::Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    // Ray parameter and in-plane coordinates of where the ray crosses the rectangle
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
         let t = (self.k - r.origin().x()) / r.direction().x();
         if t < t_min || t > t_max {
             return None;
@@ -42,6 +43,14 @@ impl Hittable for YZRect {
             return None;
         }
 
+        Some((t, y, z))
+    }
+}
+
+impl Hittable for YZRect {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _rng: &mut Rng) -> Option<HitRecord> {
+        let (t, y, z) = self.intersect(r, t_min, t_max)?;
+
         let mut rec = HitRecord {
             u: (y - self.y0) / (self.y1 - self.y0),
             v: (z - self.z0) / (self.z1 - self.z0),
@@ -50,6 +59,8 @@ impl Hittable for YZRect {
             p: r.at(t),
             normal: Vec3::default(),
             front_face: false,
+            object: 0,
+            vertex_color: None,
         };
 
         rec.set_face_normal(r, &Vec3::new(1.0, 0.0, 0.0));
@@ -64,4 +75,27 @@ impl Hittable for YZRect {
             Point3::new(self.k + 0.0001, self.y1, self.z1),
         ))
     }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let Some((t, _, _)) =
+            self.intersect(&Ray::new(*origin, *direction, 0.0), 0.001, f64::INFINITY)
+        else {
+            return 0.0;
+        };
+
+        let area = (self.y1 - self.y0) * (self.z1 - self.z0);
+        let distance_squared = t * t * direction.length_squared();
+        let cosine = (direction.x() / direction.length()).abs();
+
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, origin: &Point3, (u0, u1): (f64, f64)) -> Vec3 {
+        let point = Point3::new(
+            self.k,
+            self.y0 + u0 * (self.y1 - self.y0),
+            self.z0 + u1 * (self.z1 - self.z0),
+        );
+        point - *origin
+    }
 }
@@ -0,0 +1,30 @@
+use super::{Incident, Light};
+use crate::vec3::{Color, Point3, Vec3};
+
+// Parallel light from infinitely far away, such as sunlight
+pub struct DirectionalLight {
+    // Unit vector towards the light, against the way it travels
+    to_light: Vec3,
+    // In W/m²
+    irradiance: Color,
+}
+
+impl DirectionalLight {
+    // `direction` is the way the light travels
+    pub fn new(direction: &Vec3, irradiance: Color) -> DirectionalLight {
+        DirectionalLight {
+            to_light: -direction.unit_vector(),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn illuminate(&self, _p: &Point3) -> Option<Incident> {
+        Some(Incident {
+            direction: self.to_light,
+            distance: f64::INFINITY,
+            irradiance: self.irradiance,
+        })
+    }
+}
@@ -0,0 +1,21 @@
+pub mod directional;
+pub mod point;
+pub mod spot;
+
+use crate::vec3::{Color, Point3, Vec3};
+
+// A light at a single point or from a single direction. No ray can hit it by chance, so it is
+// only reached by the shadow rays sent towards it from every point being shaded
+pub trait Light: Sync + Send {
+    // How the light arrives at `p`, or None where it casts no light
+    fn illuminate(&self, p: &Point3) -> Option<Incident>;
+}
+
+pub struct Incident {
+    // Unit vector from the lit point towards the light
+    pub direction: Vec3,
+    // How far the shadow ray must travel unblocked
+    pub distance: f64,
+    // Irradiance in W/m² on a surface facing the light
+    pub irradiance: Color,
+}
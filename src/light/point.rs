@@ -0,0 +1,34 @@
+use super::{Incident, Light};
+use crate::vec3::{Color, Point3};
+
+// Shines equally in every direction from one point
+pub struct PointLight {
+    position: Point3,
+    // In W/sr
+    intensity: Color,
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Color) -> PointLight {
+        PointLight {
+            position,
+            intensity,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn illuminate(&self, p: &Point3) -> Option<Incident> {
+        let to_light = self.position - *p;
+        let distance_squared = to_light.length_squared();
+        if distance_squared <= 0.0 {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        Some(Incident {
+            direction: to_light / distance,
+            distance,
+            irradiance: self.intensity / distance_squared,
+        })
+    }
+}
@@ -0,0 +1,97 @@
+use super::{Incident, Light};
+use crate::texture::Texture;
+use crate::vec3::{Color, Point3, Vec3};
+
+// A point light that only shines within a cone. Its intensity fades smoothly to nothing between
+// the inner and outer angles, and an optional gobo texture is projected across the cone like a
+// slide, with u to the right and v upwards as seen from the light
+pub struct SpotLight {
+    position: Point3,
+    // In W/sr along the axis
+    intensity: Color,
+    // Unit axes of the cone: where it points, then right and up across it
+    axis: Vec3,
+    right: Vec3,
+    up: Vec3,
+    cos_inner: f64,
+    cos_outer: f64,
+    // Half-width of the gobo at unit distance along the axis
+    tan_outer: f64,
+    gobo: Option<Box<dyn Texture>>,
+}
+
+impl SpotLight {
+    // The angles are the half-angles of the cones, in degrees
+    pub fn new(
+        position: Point3,
+        direction: &Vec3,
+        intensity: Color,
+        inner_angle: f64,
+        outer_angle: f64,
+        gobo: Option<Box<dyn Texture>>,
+    ) -> SpotLight {
+        let axis = direction.unit_vector();
+        // Up is as close to +y as the axis allows, or +z when it points straight up or down
+        let world_up = if axis.y().abs() > 0.999 {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            Vec3::new(0.0, 1.0, 0.0)
+        };
+        let right = axis.cross(&world_up).unit_vector();
+        let up = right.cross(&axis);
+        let outer = outer_angle.to_radians();
+        let inner = inner_angle.min(outer_angle).to_radians();
+
+        SpotLight {
+            position,
+            intensity,
+            axis,
+            right,
+            up,
+            cos_inner: inner.cos(),
+            cos_outer: outer.cos(),
+            tan_outer: outer.tan(),
+            gobo,
+        }
+    }
+
+    // Share of the intensity sent along the unit vector `direction`
+    fn falloff(&self, direction: &Vec3) -> f64 {
+        let cos_theta = direction.dot(&self.axis);
+        if cos_theta >= self.cos_inner {
+            return 1.0;
+        }
+        let t = ((cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn illuminate(&self, p: &Point3) -> Option<Incident> {
+        let to_point = *p - self.position;
+        let distance_squared = to_point.length_squared();
+        if distance_squared <= 0.0 {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        let direction = to_point / distance;
+        let falloff = self.falloff(&direction);
+        if falloff <= 0.0 {
+            return None;
+        }
+
+        let mut intensity = self.intensity * falloff;
+        if let Some(gobo) = &self.gobo {
+            let along = direction.dot(&self.axis);
+            let u = 0.5 + 0.5 * direction.dot(&self.right) / (along * self.tan_outer);
+            let v = 0.5 + 0.5 * direction.dot(&self.up) / (along * self.tan_outer);
+            intensity = intensity * gobo.value(u, v, p);
+        }
+
+        Some(Incident {
+            direction: -direction,
+            distance,
+            irradiance: intensity / distance_squared,
+        })
+    }
+}
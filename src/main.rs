@@ -2,102 +2,834 @@
 #![deny(clippy::all, clippy::pedantic)]
 
 mod aabb;
+mod aov;
 mod camera;
+mod cli;
+mod codec;
+mod denoise;
+mod environment;
+mod film;
 mod hittable;
+mod light;
 mod material;
+mod mesh;
+mod onb;
+mod output;
+mod pdf;
 mod perlin;
+mod progress;
 mod ray;
+mod sampler;
 mod scene;
+mod scene_file;
 mod texture;
+mod tiles;
+mod tonemap;
 mod utils;
 mod vec3;
 
 use std::fs::File;
 use std::io::{BufWriter, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use rayon::prelude::*;
 
+use aov::Aov;
+use codec::{exr, Bitmap, HdrImage};
+use environment::image_map::ImageMap;
+use film::{Film, Pixel};
+use hittable::HitRecord;
 use hittable::Hittable;
+use material::Lobe;
+use pdf::{environment::EnvironmentPdf, hittable::HittablePdf, mixture::MixturePdf, Pdf};
+use progress::Progress;
 use ray::Ray;
-use vec3::Color;
+use sampler::Sampler;
+use tiles::Tile;
+use tonemap::ToneMap;
+use utils::Rng;
+use vec3::{Color, Point3, Vec3};
 
-fn main() -> Result<(), std::io::Error> {
-    let scene = scene::get(&scene::Type::Final);
+fn main() {
+    let options = match cli::parse(std::env::args().skip(1)) {
+        Ok(cli::Command::Render(options)) => options,
+        Ok(cli::Command::Benchmark(options)) => {
+            if let Err(e) = benchmark(&options) {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        Ok(cli::Command::Denoise(options)) => {
+            if let Err(e) = denoise_file(&options) {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        Ok(cli::Command::ListScenes) => {
+            for scene in &scene::Type::ALL {
+                println!("{}", scene.name());
+            }
+            return;
+        }
+        Ok(cli::Command::Help) => {
+            println!("{}", cli::usage());
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {e}\n\n{}", cli::usage());
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = render(&options) {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn render(options: &cli::Options) -> Result<(), Box<dyn std::error::Error>> {
+    let start = Instant::now();
+    // Resolve the writers up front so a bad output path fails before a long render
+    let format = match options.format {
+        Some(format) => format,
+        None => output::Format::from_path(&options.output)?,
+    };
+    let heatmap_format = match &options.sample_heatmap {
+        Some(path) => Some(output::Format::from_path(path)?),
+        None => None,
+    };
+    let mut scene = match &options.scene_file {
+        Some(path) => scene_file::load(path, &options.overrides)?,
+        None => scene::get(&options.scene, &options.overrides)?,
+    };
+    check_image_size(&scene)?;
+    if options.verbose {
+        for stats in &scene.bvh_stats {
+            eprintln!("bvh: {stats}");
+        }
+    }
+    if let Some(path) = &options.environment {
+        scene.environment = Arc::new(ImageMap::load(path, 1.0, 0.0)?);
+    }
+
+    let crop = crop_window(&scene, options.crop)?;
+    let scene_hash = scene_hash(options, &scene, &crop)?;
+    let mut film = match (&options.checkpoint, options.resume) {
+        (Some(path), true) => {
+            let film = Film::load(path, scene_hash, crop.width(), crop.height())?;
+            let samples: u64 = film.pixels.iter().map(|pixel| pixel.samples).sum();
+            eprintln!("resuming from {} with {samples} samples", path.display());
+            film
+        }
+        _ => Film::new(crop.width(), crop.height()),
+    };
+
+    // A time budget keeps adding passes until it runs out, so the samples per pixel only limit it
+    // when given explicitly
+    let deadline = options.time_budget.map(|budget| start + budget);
+    let limit = match (deadline, options.overrides.samples_per_pixel) {
+        (Some(_), None) => u64::MAX,
+        _ => u64::try_from(scene.samples_per_pixel).expect("Sample count"),
+    };
+    let remaining: u64 = film
+        .pixels
+        .iter()
+        .map(|pixel| limit.saturating_sub(pixel.samples))
+        .fold(0, u64::saturating_add);
+    let job = Job {
+        tiles: tiles::split(
+            film.width,
+            film.height,
+            options.tile_size,
+            options.tile_order,
+        ),
+        crop,
+        limit,
+        deadline,
+        progress: Progress::new(remaining, deadline),
+    };
+
+    // Each pass leaves the film in a state that can be shown or saved, which is done every
+    // checkpoint interval rather than after every pass
+    let mut last_checkpoint = Instant::now();
+    while render_pass(&scene, &mut film, &job)
+        && deadline.is_none_or(|deadline| Instant::now() < deadline)
+    {
+        if let Some(path) = &options.checkpoint {
+            if last_checkpoint.elapsed() >= options.checkpoint_interval {
+                write_image(&options.output, format, &radiance(&film), &scene.tone_map)?;
+                film.save(path, scene_hash)?;
+                last_checkpoint = Instant::now();
+            }
+        }
+    }
+
+    job.progress.finish();
+
+    let (image, aovs) = finish_image(options, &scene, &crop, &film);
+    write_aovs(&options.output, format, &image, &aovs, &scene.tone_map)?;
+    if let Some(path) = &options.checkpoint {
+        film.save(path, scene_hash)?;
+    }
+
+    let counts = film.pixels.iter().map(|pixel| pixel.samples);
+    let (min, max) = counts.clone().fold((u64::MAX, 0), |(min, max), count| {
+        (min.min(count), max.max(count))
+    });
+    // Otherwise every pixel takes the same number of samples
+    if scene.noise_threshold > 0.0 || deadline.is_some() {
+        #[allow(clippy::cast_precision_loss)] // Sample totals are far below 2^52
+        let mean = counts.sum::<u64>() as f64 / film.pixels.len() as f64;
+        eprintln!(
+            "{mean:.1} samples per pixel on average ({min} to {max}) in {:.1} s",
+            start.elapsed().as_secs_f64()
+        );
+    }
+
+    if let (Some(path), Some(format)) = (&options.sample_heatmap, heatmap_format) {
+        #[allow(clippy::cast_precision_loss)] // Sample counts are far below 2^52
+        let max = max.max(1) as f64;
+        let heatmap = film.to_image(|pixel| {
+            #[allow(clippy::cast_precision_loss)] // As above
+            heat(pixel.samples as f64 / max)
+        });
+        // The ramp is already in display colors
+        write_image(path, format, &heatmap, &ToneMap::default())?;
+    }
+
+    Ok(())
+}
+
+// The image to write, denoised if asked for, and the AOVs to write with it
+fn finish_image(
+    options: &cli::Options,
+    scene: &scene::Scene,
+    crop: &Tile,
+    film: &Film,
+) -> (HdrImage, Vec<(Aov, HdrImage)>) {
+    // The denoiser needs the albedo and normal even when they are not written
+    let mut traced = options.aovs.clone();
+    if options.denoise {
+        for aov in [Aov::Albedo, Aov::Normal] {
+            if !traced.contains(&aov) {
+                traced.push(aov);
+            }
+        }
+    }
+    let images = aov::render(scene, crop, &traced);
+    let mut aovs: Vec<(Aov, HdrImage)> = traced.into_iter().zip(images).collect();
+    let mut image = radiance(film);
+    if options.denoise {
+        let find = |wanted: Aov| {
+            let (_, image) = aovs
+                .iter()
+                .find(|(aov, _)| *aov == wanted)
+                .expect("Traced above");
+            image
+        };
+        image = denoise::denoise(
+            &image,
+            find(Aov::Albedo),
+            find(Aov::Normal),
+            options.denoise_strength,
+        );
+    }
+    aovs.retain(|(aov, _)| options.aovs.contains(aov));
+    (image, aovs)
+}
+
+// Denoises an image saved with its albedo and normal AOVs, either as layers of an EXR file or as
+// files beside it
+fn denoise_file(options: &cli::Options) -> Result<(), Box<dyn std::error::Error>> {
+    let format = match options.format {
+        Some(format) => format,
+        None => output::Format::from_path(&options.output)?,
+    };
+    let path = options.denoise_input.as_ref().expect("Denoise command");
+
+    let bytes =
+        std::fs::read(path).map_err(|e| codec::Error::InFile(path.clone(), Box::new(e.into())))?;
+    let (image, albedo, normal) = if bytes.starts_with(&exr::MAGIC) {
+        let channels =
+            exr::decode(&bytes).map_err(|e| codec::Error::InFile(path.clone(), Box::new(e)))?;
+        let layer = |aov: Aov| {
+            channels.layer(aov.name(), aov.channels()).ok_or_else(|| {
+                format!(
+                    "{}: no {} layer; render with --aov albedo,normal",
+                    path.display(),
+                    aov.name()
+                )
+            })
+        };
+        let image = channels
+            .layer("", &["R", "G", "B"])
+            .ok_or_else(|| format!("{}: no R, G and B channels", path.display()))?;
+        (image, layer(Aov::Albedo)?, layer(Aov::Normal)?)
+    } else {
+        let image = codec::decode_hdr(&bytes)
+            .map_err(|e| codec::Error::InFile(path.clone(), Box::new(e)))?;
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        (
+            image,
+            codec::load_hdr(&aov_path(path, Aov::Albedo, extension))?,
+            codec::load_hdr(&aov_path(path, Aov::Normal, extension))?,
+        )
+    };
+
+    for (aov, aov_image) in [(Aov::Albedo, &albedo), (Aov::Normal, &normal)] {
+        if (aov_image.width, aov_image.height) != (image.width, image.height) {
+            return Err(format!("{} AOV is not the size of the image", aov.name()).into());
+        }
+    }
+    let denoised = denoise::denoise(&image, &albedo, &normal, options.denoise_strength);
+    let tone_map = ToneMap::default().with(options.overrides.tone_map, options.overrides.exposure);
+    write_image(&options.output, format, &denoised, &tone_map)
+}
+
+// Identifies what a checkpoint was rendered from: the scene, or the scene file's contents, and
+// every setting that changes the samples. The sample limit is left out so that a finished render
+// can be resumed with more samples. Files a scene file refers to, such as meshes and textures,
+// are not included
+fn scene_hash(options: &cli::Options, scene: &scene::Scene, crop: &Tile) -> std::io::Result<u64> {
+    let mut bytes = match &options.scene_file {
+        Some(path) => std::fs::read(path)?,
+        None => options.scene.name().as_bytes().to_vec(),
+    };
+    if let Some(path) = &options.environment {
+        bytes.extend_from_slice(path.to_string_lossy().as_bytes());
+    }
+    bytes.extend_from_slice(scene.sampler.name().as_bytes());
+    for value in [
+        u64::try_from(scene.image_width).expect("Image dimensions are positive"),
+        u64::try_from(scene.image_height).expect("Image dimensions are positive"),
+        u64::try_from(scene.max_depth).expect("Depth is positive"),
+        scene.seed,
+        scene.noise_threshold.to_bits(),
+        crop.x0 as u64,
+        crop.y0 as u64,
+        crop.x1 as u64,
+        crop.y1 as u64,
+    ] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    Ok(film::hash(&bytes))
+}
+
+// The camera spreads its rays from one edge of the image to the other, which takes at least two
+// pixels each way
+fn check_image_size(scene: &scene::Scene) -> Result<(), cli::Error> {
+    if scene.image_width < 2 || scene.image_height < 2 {
+        return Err(cli::Error::ImageTooSmall {
+            width: scene.image_width,
+            height: scene.image_height,
+        });
+    }
+    Ok(())
+}
+
+// Pixels inside the crop window, given as fractions of the image from its top left corner. At
+// least one pixel is always rendered, so an image without any is an error
+fn crop_window(scene: &scene::Scene, crop: Option<[f64; 4]>) -> Result<Tile, cli::Error> {
+    let (width, height) = match (
+        usize::try_from(scene.image_width),
+        usize::try_from(scene.image_height),
+    ) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => (width, height),
+        _ => {
+            return Err(cli::Error::ImageTooSmall {
+                width: scene.image_width,
+                height: scene.image_height,
+            })
+        }
+    };
+    let Some([x0, y0, x1, y1]) = crop else {
+        return Ok(Tile {
+            x0: 0,
+            y0: 0,
+            x1: width,
+            y1: height,
+        });
+    };
+
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )] // Fractions in [0, 1] of the image size
+    let to_pixel = |fraction: f64, size: usize| (fraction * size as f64).round() as usize;
+    let (x0, y0) = (
+        to_pixel(x0, width).min(width - 1),
+        to_pixel(y0, height).min(height - 1),
+    );
+    Ok(Tile {
+        x0,
+        y0,
+        x1: to_pixel(x1, width).clamp(x0 + 1, width),
+        y1: to_pixel(y1, height).clamp(y0 + 1, height),
+    })
+}
+
+// Average of each pixel's samples, keeping the linear radiance; gamma and clamping only apply to
+// 8-bit output
+fn radiance(film: &Film) -> HdrImage {
+    film.to_image(|pixel| {
+        let color = pixel.color();
+        #[allow(clippy::cast_possible_truncation)] // f32 is plenty for output
+        [color.x() as f32, color.y() as f32, color.z() as f32]
+    })
+}
+
+fn write_image(
+    path: &Path,
+    format: output::Format,
+    image: &HdrImage,
+    tone_map: &ToneMap,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut buf_writer = BufWriter::new(File::create(path)?);
+    match format.writer() {
+        output::Writer::Ldr(writer) => {
+            writer.write(&to_bitmap(image, tone_map), &mut buf_writer)?;
+        }
+        output::Writer::Hdr(writer) => writer.write(image, &mut buf_writer)?,
+    }
+    buf_writer.flush()?;
+    Ok(())
+}
 
-    let pixels: Vec<Vec<Color>> = (0..scene.image_height)
+// Writes the image along with its AOVs: as layers of the one file when writing EXR, and
+// otherwise as files beside it named after each AOV, in the image's format if that keeps floats
+// and as PFM if not
+fn write_aovs(
+    path: &Path,
+    format: output::Format,
+    image: &HdrImage,
+    aovs: &[(Aov, HdrImage)],
+    tone_map: &ToneMap,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let (Some(compression), false) = (format.exr_compression(), aovs.is_empty()) {
+        let mut layers = vec![exr::Layer {
+            name: "",
+            channels: &["R", "G", "B"],
+            image,
+        }];
+        layers.extend(aovs.iter().map(|(aov, image)| exr::Layer {
+            name: aov.name(),
+            channels: aov.channels(),
+            image,
+        }));
+        std::fs::write(path, exr::encode_layers(&layers, compression))?;
+        return Ok(());
+    }
+
+    write_image(path, format, image, tone_map)?;
+    let aov_format = if format.is_hdr() {
+        format
+    } else {
+        output::Format::Pfm
+    };
+    for (aov, image) in aovs {
+        write_image(
+            &aov_path(path, *aov, aov_format.extension()),
+            aov_format,
+            image,
+            tone_map,
+        )?;
+    }
+    Ok(())
+}
+
+// The image path with the AOV's name and `extension` in place of its own extension
+fn aov_path(path: &Path, aov: Aov, extension: &str) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_owned();
+    name.push(format!(".{}.{extension}", aov.name()));
+    path.with_file_name(name)
+}
+
+// Maps a fraction in [0, 1] through blue, cyan, green and yellow to red
+fn heat(t: f64) -> [f32; 3] {
+    const STOPS: [[f32; 3]; 5] = [
+        [0.0, 0.0, 1.0],
+        [0.0, 1.0, 1.0],
+        [0.0, 1.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [1.0, 0.0, 0.0],
+    ];
+    #[allow(clippy::cast_possible_truncation)] // f32 is plenty for a color ramp
+    let position = (t.clamp(0.0, 1.0) * 4.0) as f32;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // Within [0, 4]
+    let stop = (position as usize).min(3);
+    #[allow(clippy::cast_precision_loss)] // As above
+    let f = position - stop as f32;
+    let (a, b) = (STOPS[stop], STOPS[stop + 1]);
+    [0, 1, 2].map(|c| a[c] + (b[c] - a[c]) * f)
+}
+
+// Samples added to each pixel per pass. Adaptive sampling checks for convergence after each pass
+const PASS_SAMPLES: u64 = 16;
+
+// How the passes of a render are split up and when they stop
+struct Job {
+    // Part of the image the film covers
+    crop: Tile,
+    // Tiles of the film, in the order they are rendered
+    tiles: Vec<Tile>,
+    // Samples a pixel takes at most
+    limit: u64,
+    deadline: Option<Instant>,
+    progress: Progress,
+}
+
+// Adds up to PASS_SAMPLES samples to every pixel that has neither reached the limit nor
+// converged, returning false once there are none left. Each render thread takes the next tile in
+// order until none are left, so tiles are started in order even though they finish out of it.
+// Pixels with samples are skipped after the deadline, so a pass cut short leaves some pixels
+// with fewer samples than others, which is why each pixel is averaged over its own count
+fn render_pass(scene: &scene::Scene, film: &mut Film, job: &Job) -> bool {
+    let next_tile = AtomicUsize::new(0);
+    let rendered = AtomicBool::new(false);
+    let film = Mutex::new(film);
+
+    (0..rayon::current_num_threads())
         .into_par_iter()
-        .rev()
-        .map(|j| {
-            (0..scene.image_width)
-                .into_par_iter()
-                .map(|i| {
-                    let mut pixel_color = Color::default();
-                    for _ in 0..scene.samples_per_pixel {
-                        let u = (f64::from(i) + utils::random_float())
-                            / f64::from(scene.image_width - 1);
-                        let v = (f64::from(j) + utils::random_float())
-                            / f64::from(scene.image_height - 1);
-                        let r = scene.cam.get_ray(u, v);
-                        pixel_color +=
-                            &ray_color(&r, &scene.background, &scene.world, scene.max_depth);
+        .for_each(|_| {
+            while let Some(tile) = job.tiles.get(next_tile.fetch_add(1, Ordering::Relaxed)) {
+                // Only copying the pixels in and out holds the lock, not the rendering
+                let mut pixels = film.lock().expect("Render thread panicked").tile(tile);
+                let mut samples = 0;
+                let mut rays = 0;
+                for (index, pixel) in pixels.iter_mut().enumerate() {
+                    if pixel.converged || pixel.samples >= job.limit {
+                        continue;
                     }
-                    Color::new(
-                        (pixel_color.x() * scene.scale).sqrt(),
-                        (pixel_color.y() * scene.scale).sqrt(),
-                        (pixel_color.z() * scene.scale).sqrt(),
-                    )
-                })
-                .collect()
-        })
-        .collect();
+                    if pixel.samples > 0
+                        && job
+                            .deadline
+                            .is_some_and(|deadline| Instant::now() >= deadline)
+                    {
+                        continue;
+                    }
+                    // The film starts at the top row, and the camera at the bottom
+                    let i = job.crop.x0 + tile.x0 + index % tile.width();
+                    let j =
+                        scene_height(scene) - 1 - (job.crop.y0 + tile.y0 + index / tile.width());
+                    let end = (pixel.samples + PASS_SAMPLES).min(job.limit);
+                    samples += end - pixel.samples;
+                    render_pixel(scene, (i, j), pixel, end, &mut rays);
+                }
+                film.lock()
+                    .expect("Render thread panicked")
+                    .set_tile(tile, &pixels);
+                if samples > 0 {
+                    rendered.store(true, Ordering::Relaxed);
+                }
+                job.progress.add(samples, rays);
+            }
+        });
+
+    rendered.into_inner()
+}
 
-    let path = Path::new("output.ppm");
-    let file = File::create(&path)?;
-    let mut buf_writer = BufWriter::new(file);
+fn scene_height(scene: &scene::Scene) -> usize {
+    usize::try_from(scene.image_height).expect("Image dimensions are positive")
+}
 
-    writeln!(
-        buf_writer,
-        "P3\n{} {}\n255",
-        scene.image_width, scene.image_height
-    )?;
+// Continues the pixel's samples up to sample `end`, counting the rays traced. Every sample is
+// seeded by its pixel and index alone, so the image does not depend on how the samples were split
+// into passes or tiles, or resumed
+fn render_pixel(
+    scene: &scene::Scene,
+    (i, j): (usize, usize),
+    pixel: &mut Pixel,
+    end: u64,
+    rays: &mut u64,
+) {
+    let index = scene.pixel_index(i, j);
+    // Strata are a pass long, as the final sample count is not known up front
+    let mut sampler = scene.sampler.create(scene.seed, index, PASS_SAMPLES);
 
-    for v in pixels {
-        for p in v {
-            write_color(&mut buf_writer, &p)?;
+    for sample in pixel.samples..end {
+        sampler.start_sample(sample);
+        let mut rng = utils::sample_rng(scene.seed, index, sample);
+        let ray = scene.camera_ray(i, j, sampler.as_mut());
+        let color = ray_color(
+            &ray,
+            scene,
+            scene.max_depth,
+            None,
+            sampler.as_mut(),
+            &mut rng,
+            rays,
+        );
+        // A degenerate pdf can produce NaN, which would spread to the whole pixel
+        if color.has_nan() {
+            pixel.add(&Color::default());
+        } else {
+            pixel.add(&color);
         }
     }
 
+    if scene.noise_threshold > 0.0 {
+        pixel.converged = pixel.within_noise_threshold(scene.noise_threshold);
+    }
+}
+
+// Renders the scene once per BVH layout and prints the build and render times
+fn benchmark(options: &cli::Options) -> Result<(), Box<dyn std::error::Error>> {
+    let overrides = scene::Overrides {
+        image_width: options.overrides.image_width.or(Some(200)),
+        samples_per_pixel: options.overrides.samples_per_pixel.or(Some(16)),
+        ..options.overrides
+    };
+
+    println!(
+        "{:<10} {:>10} {:>10} {:>14}",
+        "bvh", "build", "render", "samples/s"
+    );
+    let mut render_times = Vec::new();
+    for bvh in scene::Bvh::ALL {
+        let start = Instant::now();
+        let scene = scene::get_with_bvh(&options.scene, &overrides, bvh)?;
+        check_image_size(&scene)?;
+        let build_time = start.elapsed();
+
+        let start = Instant::now();
+        let crop = crop_window(&scene, None)?;
+        let mut film = Film::new(crop.width(), crop.height());
+        let job = Job {
+            tiles: tiles::split(film.width, film.height, 32, tiles::Order::Scanline),
+            crop,
+            limit: u64::try_from(scene.samples_per_pixel).expect("Sample count"),
+            deadline: None,
+            progress: Progress::quiet(),
+        };
+        while render_pass(&scene, &mut film, &job) {}
+        let render_time = start.elapsed();
+
+        let samples = f64::from(scene.image_width)
+            * f64::from(scene.image_height)
+            * f64::from(scene.samples_per_pixel);
+        println!(
+            "{:<10} {:>7.1} ms {:>8.2} s {:>14.0}",
+            bvh.name(),
+            build_time.as_secs_f64() * 1000.0,
+            render_time.as_secs_f64(),
+            samples / render_time.as_secs_f64()
+        );
+        render_times.push(render_time.as_secs_f64());
+    }
+
+    println!(
+        "{} is {:.2}x the speed of {}",
+        scene::Bvh::Linear.name(),
+        render_times[0] / render_times[1],
+        scene::Bvh::Recursive.name()
+    );
     Ok(())
 }
 
-fn write_color(writer: &mut BufWriter<File>, pixel_color: &Color) -> Result<(), std::io::Error> {
-    let ir = utils::float_to_int_truncate(256.0 * utils::clamp(pixel_color.x(), 0.0, 0.999));
-    let ig = utils::float_to_int_truncate(256.0 * utils::clamp(pixel_color.y(), 0.0, 0.999));
-    let ib = utils::float_to_int_truncate(256.0 * utils::clamp(pixel_color.z(), 0.0, 0.999));
+// Tone maps and quantizes to 8 bits
+fn to_bitmap(image: &HdrImage, tone_map: &ToneMap) -> Bitmap {
+    let quantize = |c: f64| {
+        let i = utils::float_to_int_truncate(256.0 * utils::clamp(c, 0.0, 0.999));
+        u8::try_from(i).expect("Clamped to a byte")
+    };
 
-    writeln!(writer, "{} {} {}", ir, ig, ib)
+    let mut bitmap = Bitmap::new(image.width, image.height);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let [r, g, b] = tone_map.apply(image.get(x, y));
+            bitmap.set(x, y, [quantize(r), quantize(g), quantize(b)]);
+        }
+    }
+    bitmap
 }
 
-fn ray_color(r: &Ray, background: &Color, world: &dyn Hittable, depth: i32) -> Color {
+// `material_pdf` is the density with which the material at the ray's origin chose its
+// direction, given when the lights were also sampled there. Light reached this way is then
+// weighted against the chance of the shadow ray having found it (multiple importance sampling).
+// The sampler places the light and scattering samples at each bounce; `rng` covers choices that
+// are not worth stratifying, such as reflection against refraction and scattering in media.
+// `rays` counts the rays traced, including shadow rays
+fn ray_color(
+    r: &Ray,
+    scene: &scene::Scene,
+    depth: i32,
+    material_pdf: Option<f64>,
+    sampler: &mut dyn Sampler,
+    rng: &mut Rng,
+    rays: &mut u64,
+) -> Color {
     if depth <= 0 {
         return Color::default();
     }
 
-    if let Some(rec) = world.hit(r, 0.001, f64::INFINITY) {
-        let mut scattered = Ray::default();
-        let mut attenuation = Color::default();
-        let emitted = rec.material.emitted(rec.u, rec.v, &rec.p);
+    *rays += 1;
+    let Some(rec) = scene.world.hit(r, 0.001, f64::INFINITY, rng) else {
+        let mut radiance = scene.environment.radiance(r.direction());
+        if let Some(material_pdf) = material_pdf {
+            if scene.environment.sampled() {
+                let light_pdf = light_pdf_value(scene, r.origin(), r.direction());
+                radiance *= pdf::power_heuristic(material_pdf, light_pdf);
+            }
+        }
+        return radiance;
+    };
 
-        if !rec
-            .material
-            .scatter(r, &rec, &mut attenuation, &mut scattered)
-        {
-            return emitted;
+    let mut emitted = rec.material.emitted(&rec);
+    // Only emitters can be lights, which saves testing every other hit against the light list
+    if let Some(material_pdf) = material_pdf {
+        if emitted.length_squared() > 0.0 {
+            let light_pdf = light_pdf_value(scene, r.origin(), r.direction());
+            emitted *= pdf::power_heuristic(material_pdf, light_pdf);
         }
+    }
 
-        emitted + attenuation * ray_color(&scattered, background, world, depth - 1)
-    } else {
-        *background
+    let Some(srec) = rec.material.scatter(r, &rec, rng) else {
+        return emitted;
+    };
+
+    let pdf = match srec.lobe {
+        Lobe::Specular(specular) => {
+            return emitted
+                + srec.attenuation
+                    * ray_color(&specular, scene, depth - 1, None, sampler, rng, rays);
+        }
+        Lobe::Diffuse(pdf) => pdf,
+    };
+
+    // Both samples are drawn even without lights, so each bounce uses the same dimensions
+    let light_sample = sampler.get_2d();
+    let direct = direct_light(r, &rec, pdf.as_ref(), scene, light_sample, rng, rays)
+        + delta_light(r, &rec, scene, rng, rays);
+
+    let scattered = Ray::new(rec.p, pdf.generate(sampler.get_2d()), r.time());
+    let pdf_value = pdf.value(scattered.direction());
+    if pdf_value <= 0.0 {
+        return emitted + srec.attenuation * direct;
     }
+
+    let lights_sampled = !scene.lights.objects.is_empty() || scene.environment.sampled();
+    let scattering_pdf = rec.material.scattering_pdf(r, &rec, &scattered);
+    let indirect = scattering_pdf
+        * ray_color(
+            &scattered,
+            scene,
+            depth - 1,
+            lights_sampled.then_some(pdf_value),
+            sampler,
+            rng,
+            rays,
+        )
+        / pdf_value;
+
+    emitted + srec.attenuation * (direct + indirect)
+}
+
+// Light arriving at a hit point from one direction sampled towards the scene's lights or its
+// environment, weighted by the material's scattering_pdf but not yet by its attenuation.
+// `material_pdf` is the distribution the material samples its own directions from, which could
+// also have found the light, and `u` chooses the direction. Whatever the shadow ray reaches first
+// is what lights the point, so an emitter in the way counts in place of the light behind it, just
+// as it does when the material's own sample reaches it
+fn direct_light(
+    r: &Ray,
+    rec: &HitRecord,
+    material_pdf: &dyn Pdf,
+    scene: &scene::Scene,
+    u: (f64, f64),
+    rng: &mut Rng,
+    rays: &mut u64,
+) -> Color {
+    with_light_pdf(scene, &rec.p, |light_pdf| {
+        let Some(light_pdf) = light_pdf else {
+            return Color::default();
+        };
+        let shadow = Ray::new(rec.p, light_pdf.generate(u), r.time());
+        let light_pdf_value = light_pdf.value(shadow.direction());
+        if light_pdf_value <= 0.0 {
+            return Color::default();
+        }
+
+        let scattering_pdf = rec.material.scattering_pdf(r, rec, &shadow);
+        if scattering_pdf <= 0.0 {
+            return Color::default();
+        }
+
+        *rays += 1;
+        let emitted = match scene.world.hit(&shadow, 0.001, f64::INFINITY, rng) {
+            Some(hit) => hit.material.emitted(&hit),
+            // A background that is not sampled is left to the material's own samples
+            None if scene.environment.sampled() => scene.environment.radiance(shadow.direction()),
+            None => return Color::default(),
+        };
+
+        let weight = pdf::power_heuristic(light_pdf_value, material_pdf.value(shadow.direction()));
+        emitted * scattering_pdf * weight / light_pdf_value
+    })
+}
+
+// Calls `f` with the distribution `direct_light` samples from `origin`: the scene's lights, its
+// environment, or a mixture taking half of its samples from each. It is None when there is
+// neither to sample
+fn with_light_pdf<T>(
+    scene: &scene::Scene,
+    origin: &Point3,
+    f: impl FnOnce(Option<&dyn Pdf>) -> T,
+) -> T {
+    let lights = HittablePdf::new(&scene.lights, *origin);
+    let environment = EnvironmentPdf::new(scene.environment.as_ref());
+    match (scene.lights.objects.is_empty(), scene.environment.sampled()) {
+        (true, false) => f(None),
+        (false, false) => f(Some(&lights)),
+        (true, true) => f(Some(&environment)),
+        (false, true) => f(Some(&MixturePdf::new(&lights, &environment))),
+    }
+}
+
+// Light arriving at a hit point from the scene's point, spot and directional lights, weighted by
+// the material's scattering_pdf like `direct_light`. Each gets a shadow ray of its own, since no
+// other sample can find them
+fn delta_light(
+    r: &Ray,
+    rec: &HitRecord,
+    scene: &scene::Scene,
+    rng: &mut Rng,
+    rays: &mut u64,
+) -> Color {
+    let mut total = Color::default();
+    for light in &scene.delta_lights {
+        let Some(incident) = light.illuminate(&rec.p) else {
+            continue;
+        };
+        let shadow = Ray::new(rec.p, incident.direction, r.time());
+        let scattering_pdf = rec.material.scattering_pdf(r, rec, &shadow);
+        if scattering_pdf <= 0.0 {
+            continue;
+        }
+
+        *rays += 1;
+        if scene
+            .world
+            .hit(&shadow, 0.001, incident.distance - 0.001, rng)
+            .is_none()
+        {
+            total += &(incident.irradiance * scattering_pdf);
+        }
+    }
+    total
+}
+
+// Density, per unit solid angle, with which `direct_light` chooses `direction` from `origin`
+fn light_pdf_value(scene: &scene::Scene, origin: &Point3, direction: &Vec3) -> f64 {
+    with_light_pdf(scene, origin, |pdf| {
+        pdf.map_or(0.0, |pdf| pdf.value(direction))
+    })
 }
@@ -1,5 +1,10 @@
-use super::Material;
-use crate::{hittable::HitRecord, ray::Ray, utils, vec3::Color};
+use super::{Lobe, Material, ScatterRecord};
+use crate::{
+    hittable::HitRecord,
+    ray::Ray,
+    utils::{self, Rng},
+    vec3::Color,
+};
 
 pub struct Dielectric {
     refraction_index: f64,
@@ -12,14 +17,7 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
-        *attenuation = Color::new_single(1.0);
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<ScatterRecord> {
         let refraction_ratio = if rec.front_face {
             1.0 / self.refraction_index
         } else {
@@ -32,16 +30,23 @@ impl Material for Dielectric {
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
-        let direction =
-            if cannot_refract || reflectance(cos_theta, refraction_ratio) > utils::random_float() {
-                unit_direction.reflect(&rec.normal)
-            } else {
-                unit_direction.refract(&rec.normal, refraction_ratio)
-            };
+        let direction = if cannot_refract
+            || reflectance(cos_theta, refraction_ratio) > utils::random_float(rng)
+        {
+            unit_direction.reflect(&rec.normal)
+        } else {
+            unit_direction.refract(&rec.normal, refraction_ratio)
+        };
 
-        *scattered = Ray::new(rec.p, direction, r_in.time());
+        Some(ScatterRecord {
+            attenuation: Color::new_single(1.0),
+            lobe: Lobe::Specular(Ray::new(rec.p, direction, r_in.time())),
+        })
+    }
 
-        true
+    // Clear glass passes on all light
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::new_single(1.0)
     }
 }
 
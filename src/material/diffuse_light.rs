@@ -1,39 +1,38 @@
 use crate::{
+    hittable::HitRecord,
     texture::{solid_color::SolidColor, Texture},
-    vec3::{Color, Point3},
+    vec3::Color,
 };
 
 use super::Material;
 
 pub struct DiffuseLight {
     emit: Box<dyn Texture>,
+    // Scales the texture, so that brightness can be set apart from the color
+    intensity: f64,
+    // One-sided lights only emit from their front face, the side their outward normal points to
+    two_sided: bool,
 }
 
 impl DiffuseLight {
     pub fn new(color: Color) -> DiffuseLight {
-        DiffuseLight {
-            emit: Box::new(SolidColor::new(color)),
-        }
+        DiffuseLight::new_texture(Box::new(SolidColor::new(color)), 1.0, true)
     }
 
-    #[allow(dead_code)]
-    pub fn new_texture(texture: Box<dyn Texture>) -> DiffuseLight {
-        DiffuseLight { emit: texture }
+    pub fn new_texture(texture: Box<dyn Texture>, intensity: f64, two_sided: bool) -> DiffuseLight {
+        DiffuseLight {
+            emit: texture,
+            intensity,
+            two_sided,
+        }
     }
 }
 
 impl Material for DiffuseLight {
-    fn scatter(
-        &self,
-        _r_in: &crate::ray::Ray,
-        _rec: &crate::hittable::HitRecord,
-        _attenuation: &mut Color,
-        _scattered: &mut crate::ray::Ray,
-    ) -> bool {
-        false
-    }
-
-    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
-        self.emit.value(u, v, p)
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        if !self.two_sided && !rec.front_face {
+            return Color::default();
+        }
+        self.emit.value_at(rec) * self.intensity
     }
 }
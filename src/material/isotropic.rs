@@ -1,11 +1,15 @@
+use std::f64::consts::PI;
+
 use crate::{
     hittable::HitRecord,
+    pdf::sphere::SpherePdf,
     ray::Ray,
     texture::{solid_color::SolidColor, Texture},
-    vec3::{Color, Vec3},
+    utils::Rng,
+    vec3::Color,
 };
 
-use super::Material;
+use super::{Lobe, Material, ScatterRecord};
 
 pub struct Isotropic {
     albedo: Box<dyn Texture>,
@@ -24,15 +28,18 @@ impl Isotropic {
 }
 
 impl Material for Isotropic {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
-        *scattered = Ray::new(rec.p, Vec3::random_in_unit_sphere(), r_in.time());
-        *attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
-        true
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord, _rng: &mut Rng) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            attenuation: self.albedo.value_at(rec),
+            lobe: Lobe::Diffuse(Box::new(SpherePdf)),
+        })
+    }
+
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value_at(rec)
     }
 }
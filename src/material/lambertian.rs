@@ -1,9 +1,13 @@
-use super::Material;
+use std::f64::consts::PI;
+
+use super::{Lobe, Material, ScatterRecord};
 use crate::{
     hittable::HitRecord,
+    pdf::cosine::CosinePdf,
     ray::Ray,
     texture::{solid_color::SolidColor, Texture},
-    vec3::{Color, Vec3},
+    utils::Rng,
+    vec3::Color,
 };
 
 pub struct Lambertian {
@@ -23,23 +27,19 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
-        let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
-
-        // Catch degenerate scatter direction
-        if scatter_direction.near_zero() {
-            scatter_direction = rec.normal;
-        }
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord, _rng: &mut Rng) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            attenuation: self.albedo.value_at(rec),
+            lobe: Lobe::Diffuse(Box::new(CosinePdf::new(&rec.normal))),
+        })
+    }
 
-        *scattered = Ray::new(rec.p, scatter_direction, r_in.time());
-        *attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cosine = rec.normal.dot(&scattered.direction().unit_vector());
+        (cosine / PI).max(0.0)
+    }
 
-        true
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value_at(rec)
     }
 }
@@ -1,8 +1,10 @@
-use super::Material;
+use super::{Lobe, Material, ScatterRecord};
 use crate::{
     hittable::HitRecord,
+    pdf::{phong::PhongPdf, Pdf},
     ray::Ray,
-    vec3::{Color, Vec3},
+    utils::Rng,
+    vec3::Color,
 };
 
 pub struct Metal {
@@ -17,24 +19,40 @@ impl Metal {
             fuzz: if fuzz < 1.0 { fuzz } else { 1.0 },
         }
     }
+
+    // Fuzzy reflections scatter around the mirror direction in a lobe that narrows as the fuzz
+    // goes to zero and covers the hemisphere around it at a fuzz of one
+    fn lobe(&self, r_in: &Ray, rec: &HitRecord) -> PhongPdf {
+        let reflected = r_in.direction().unit_vector().reflect(&rec.normal);
+        let exponent = 2.0 / (self.fuzz * self.fuzz) - 2.0;
+        PhongPdf::new(&reflected, exponent)
+    }
 }
 
 impl Material for Metal {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
-        let reflected = r_in.direction().unit_vector().reflect(&rec.normal);
-        *scattered = Ray::new(
-            rec.p,
-            reflected + self.fuzz * Vec3::random_in_unit_sphere(),
-            r_in.time(),
-        );
-        *attenuation = self.albedo;
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, _rng: &mut Rng) -> Option<ScatterRecord> {
+        let lobe = if self.fuzz > 0.0 {
+            Lobe::Diffuse(Box::new(self.lobe(r_in, rec)))
+        } else {
+            let reflected = r_in.direction().unit_vector().reflect(&rec.normal);
+            Lobe::Specular(Ray::new(rec.p, reflected, r_in.time()))
+        };
+
+        Some(ScatterRecord {
+            attenuation: self.albedo,
+            lobe,
+        })
+    }
+
+    // Directions below the surface are absorbed
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        if scattered.direction().dot(&rec.normal) <= 0.0 {
+            return 0.0;
+        }
+        self.lobe(r_in, rec).value(scattered.direction())
+    }
 
-        scattered.direction().dot(&rec.normal) > 0.0
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
     }
 }
@@ -4,22 +4,89 @@ pub mod isotropic;
 pub mod lambertian;
 pub mod metal;
 
-use crate::{
-    hittable::HitRecord,
-    ray::Ray,
-    vec3::{Color, Point3},
-};
+use std::sync::Arc;
+
+use crate::{hittable::HitRecord, pdf::Pdf, ray::Ray, utils::Rng, vec3::Color};
+
+pub struct ScatterRecord {
+    pub attenuation: Color,
+    pub lobe: Lobe,
+}
+
+pub enum Lobe {
+    // A single outgoing ray, as from a mirror or glass, whose pdf is a delta function
+    Specular(Ray),
+    // Directions drawn from a distribution, weighted by the material's `scattering_pdf`
+    Diffuse(Box<dyn Pdf>),
+}
 
 pub trait Material: Sync + Send {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool;
-
-    fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord, _rng: &mut Rng) -> Option<ScatterRecord> {
+        None
+    }
+
+    // Density, per unit solid angle, of the material scattering `r_in` into `scattered`
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        0.0
+    }
+
+    // Radiance the surface gives off towards the ray that hit it
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        Color::default()
+    }
+
+    // Fraction of light the surface reflects, ignoring direction, as used by the albedo pass.
+    // Materials that only emit reflect nothing
+    fn albedo(&self, _rec: &HitRecord) -> Color {
         Color::default()
     }
+
+    // Number of the material in the material-id AOV, 0 for one that `MaterialIds` never numbered
+    fn id(&self) -> usize {
+        0
+    }
+}
+
+// Numbers a scene's materials from 1 in the order it creates them, so that each keeps its
+// material-id from one render to the next
+#[derive(Default)]
+pub struct MaterialIds {
+    last: usize,
+}
+
+impl MaterialIds {
+    pub fn add(&mut self, material: impl Material + 'static) -> Arc<dyn Material> {
+        self.last += 1;
+        Arc::new(Numbered {
+            id: self.last,
+            material,
+        })
+    }
+}
+
+struct Numbered<M> {
+    id: usize,
+    material: M,
+}
+
+impl<M: Material> Material for Numbered<M> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, rng: &mut Rng) -> Option<ScatterRecord> {
+        self.material.scatter(r_in, rec, rng)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        self.material.scattering_pdf(r_in, rec, scattered)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.material.emitted(rec)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.material.albedo(rec)
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
 }
@@ -0,0 +1,158 @@
+// Loaders that turn triangle mesh files into a BVH of `Triangle`s
+
+pub mod obj;
+pub mod ply;
+pub mod stl;
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::codec;
+use crate::hittable::{bvh_node::Stats, linear_bvh::LinearBVH};
+use crate::material::{lambertian::Lambertian, Material, MaterialIds};
+use crate::vec3::{Color, Point3};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse(usize, String),
+    Malformed(String),
+    Texture(codec::Error),
+    UnknownFormat(String),
+    Empty,
+    InFile(PathBuf, Box<Error>),
+}
+
+impl Error {
+    pub fn parse(line: usize, message: impl Into<String>) -> Error {
+        Error::Parse(line, message.into())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Parse(line, message) => write!(f, "line {line}: {message}"),
+            Error::Malformed(reason) => write!(f, "malformed mesh: {reason}"),
+            Error::Texture(e) => write!(f, "{e}"),
+            Error::UnknownFormat(path) => write!(f, "cannot infer a mesh format from '{path}'"),
+            Error::Empty => write!(f, "mesh contains no triangles"),
+            Error::InFile(path, e) => write!(f, "{}: {e}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+// Loads a mesh, picking the format from the file extension. `material` is used for faces that
+// do not specify their own; without it, PLY vertex colors or a plain grey material are used.
+// Materials the file brings, and those made up for it, are numbered by `ids`. Returns the BVH
+// over the triangles with its statistics.
+pub fn load(
+    path: &Path,
+    material: Option<Arc<dyn Material>>,
+    ids: &mut MaterialIds,
+) -> Result<(LinearBVH, Stats), Error> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+
+    let triangles = match extension.as_deref() {
+        Some("obj") => obj::load(path, material, ids),
+        Some("ply") => ply::load(path, material, ids),
+        Some("stl") => stl::load(path, material, ids),
+        _ => return Err(Error::UnknownFormat(path.display().to_string())),
+    }
+    .map_err(|e| Error::InFile(path.to_path_buf(), Box::new(e)))?;
+
+    if triangles.objects.is_empty() {
+        return Err(Error::InFile(path.to_path_buf(), Box::new(Error::Empty)));
+    }
+    Ok(LinearBVH::new_from_list(&triangles, 0.0, 0.0))
+}
+
+fn default_material(ids: &mut MaterialIds) -> Arc<dyn Material> {
+    ids.add(Lambertian::new(Color::new_single(0.73)))
+}
+
+// Triangles without area have no normal and can never be hit. The area is measured against the
+// edges rather than an absolute threshold, so that meshes modelled at a small scale, such as CAD
+// files in metres, keep their triangles and only slivers with all three corners in line are dropped
+fn is_degenerate(vertices: &[Point3; 3]) -> bool {
+    let edge1 = vertices[1] - vertices[0];
+    let edge2 = vertices[2] - vertices[0];
+    edge1.cross(&edge2).length() <= 1e-12 * edge1.length() * edge2.length()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::hittable::htlist::HittableList;
+    use crate::ray::Ray;
+    use crate::utils;
+    use crate::vec3::Vec3;
+
+    // Writes `files` into a directory of their own under the system's temporary directory and
+    // returns the path of the first
+    pub fn write_files(test: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("raytracer-{test}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        dir.join(files[0].0)
+    }
+
+    // Material ID of what a ray from +z straight down the z axis through (x, y) hits first
+    pub fn material_at(triangles: &HittableList, x: f64, y: f64) -> Option<usize> {
+        let ray = Ray::new(Point3::new(x, y, 10.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut rng = utils::scene_rng(0);
+        triangles
+            .objects
+            .iter()
+            .filter_map(|object| object.hit(&ray, 0.001, f64::INFINITY, &mut rng))
+            .min_by(|a, b| a.t.total_cmp(&b.t))
+            .map(|rec| rec.material.id())
+    }
+
+    #[test]
+    fn degenerate_triangles() {
+        let triangle = |scale: f64, c: Point3| {
+            [
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(scale, 0.0, 0.0),
+                c * scale,
+            ]
+        };
+        assert!(!is_degenerate(&triangle(1.0, Point3::new(0.0, 1.0, 0.0))));
+        assert!(!is_degenerate(&triangle(1e-6, Point3::new(0.0, 1.0, 0.0))));
+        assert!(is_degenerate(&triangle(1.0, Point3::new(2.0, 0.0, 0.0))));
+        assert!(is_degenerate(&triangle(1.0, Point3::new(0.0, 0.0, 0.0))));
+    }
+
+    #[test]
+    fn load_picks_the_format_from_the_extension() {
+        let mut ids = MaterialIds::default();
+        let error = load(Path::new("model.fbx"), None, &mut ids).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "cannot infer a mesh format from 'model.fbx'"
+        );
+
+        let path = write_files("empty", &[("empty.obj", b"v 0 0 0\n")]);
+        let error = load(&path, None, &mut ids).err().unwrap();
+        assert!(error.to_string().ends_with("mesh contains no triangles"));
+    }
+}
@@ -0,0 +1,391 @@
+// Wavefront OBJ reader with MTL material libraries. Polygons are split into triangle fans, and
+// faces only get smooth normals or texture coordinates when every corner specifies them.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::{default_material, is_degenerate, Error};
+use crate::hittable::htlist::HittableList;
+use crate::hittable::triangle::Triangle;
+use crate::material::{
+    dielectric::Dielectric, diffuse_light::DiffuseLight, lambertian::Lambertian, metal::Metal,
+    Material, MaterialIds,
+};
+use crate::texture::image::{Image, Wrap};
+use crate::vec3::{Color, Point3, Vec3};
+
+pub fn load(
+    path: &Path,
+    material: Option<Arc<dyn Material>>,
+    ids: &mut MaterialIds,
+) -> Result<HittableList, Error> {
+    let source = fs::read_to_string(path)?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    let mut positions: Vec<Point3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<(f64, f64)> = Vec::new();
+    let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+    let mut current = material.unwrap_or_else(|| default_material(ids));
+    let mut triangles = HittableList::default();
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => positions.push(vector(&args, line_number)?),
+            "vn" => normals.push(vector(&args, line_number)?),
+            "vt" => {
+                let u = number(args.first(), line_number)?;
+                let v = match args.get(1) {
+                    Some(_) => number(args.get(1), line_number)?,
+                    None => 0.0,
+                };
+                uvs.push((u, v));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(Error::parse(
+                        line_number,
+                        "face needs at least three vertices",
+                    ));
+                }
+                let corners = args
+                    .iter()
+                    .map(|arg| {
+                        corner(
+                            arg,
+                            [positions.len(), uvs.len(), normals.len()],
+                            line_number,
+                        )
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                // Fan triangulation around the first corner
+                for i in 1..corners.len() - 1 {
+                    let face = [corners[0], corners[i], corners[i + 1]];
+                    let vertices = face.map(|c| positions[c.position]);
+                    if is_degenerate(&vertices) {
+                        continue;
+                    }
+
+                    let face_normals = face
+                        .iter()
+                        .all(|c| c.normal.is_some())
+                        .then(|| face.map(|c| normals[c.normal.expect("checked above")]));
+                    let face_uvs = face
+                        .iter()
+                        .all(|c| c.uv.is_some())
+                        .then(|| face.map(|c| uvs[c.uv.expect("checked above")]));
+
+                    triangles.add(Arc::new(Triangle::new_with_attributes(
+                        vertices,
+                        face_normals,
+                        face_uvs,
+                        None,
+                        current.clone(),
+                    )));
+                }
+            }
+            "mtllib" => {
+                // File names may contain spaces
+                let name = line.trim_start()["mtllib".len()..].trim();
+                let mtl_path = base_dir.join(name);
+                let library = load_library(&mtl_path, ids)
+                    .map_err(|e| Error::InFile(mtl_path, Box::new(e)))?;
+                materials.extend(library);
+            }
+            "usemtl" => {
+                let name = args.first().copied().unwrap_or_default();
+                current = materials.get(name).cloned().ok_or_else(|| {
+                    Error::parse(line_number, format!("unknown material '{name}'"))
+                })?;
+            }
+            // Groups, objects, smoothing groups, lines and points do not affect rendering
+            _ => {}
+        }
+    }
+
+    Ok(triangles)
+}
+
+#[derive(Clone, Copy)]
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+// Parses "v", "v/vt", "v//vn" or "v/vt/vn". Indices are 1-based, or relative to the end of the
+// list when negative.
+fn corner(arg: &str, counts: [usize; 3], line: usize) -> Result<Corner, Error> {
+    let mut indices = [None; 3];
+    for (i, part) in arg.split('/').enumerate() {
+        if i >= 3 {
+            return Err(Error::parse(line, format!("invalid face vertex '{arg}'")));
+        }
+        if part.is_empty() {
+            continue;
+        }
+
+        let index: i64 = part
+            .parse()
+            .map_err(|_| Error::parse(line, format!("invalid face vertex '{arg}'")))?;
+        let count = i64::try_from(counts[i]).expect("element count fits in i64");
+        let resolved = if index < 0 { count + index } else { index - 1 };
+        if index == 0 || resolved < 0 || resolved >= count {
+            let kind = ["vertex", "texture coordinate", "normal"][i];
+            return Err(Error::parse(
+                line,
+                format!("{kind} index {index} is out of range"),
+            ));
+        }
+        indices[i] = Some(usize::try_from(resolved).expect("checked above"));
+    }
+
+    Ok(Corner {
+        position: indices[0]
+            .ok_or_else(|| Error::parse(line, format!("invalid face vertex '{arg}'")))?,
+        uv: indices[1],
+        normal: indices[2],
+    })
+}
+
+fn number(arg: Option<&&str>, line: usize) -> Result<f64, Error> {
+    let arg = arg.ok_or_else(|| Error::parse(line, "missing number"))?;
+    arg.parse()
+        .map_err(|_| Error::parse(line, format!("invalid number '{arg}'")))
+}
+
+fn vector(args: &[&str], line: usize) -> Result<Vec3, Error> {
+    Ok(Vec3::new(
+        number(args.first(), line)?,
+        number(args.get(1), line)?,
+        number(args.get(2), line)?,
+    ))
+}
+
+// The subset of MTL parameters that map onto the available materials
+struct MaterialDef {
+    diffuse: Color,
+    specular: Color,
+    emission: Color,
+    shininess: f64,
+    refraction_index: f64,
+    opacity: f64,
+    illumination: i64,
+    diffuse_map: Option<PathBuf>,
+}
+
+impl Default for MaterialDef {
+    fn default() -> MaterialDef {
+        MaterialDef {
+            diffuse: Color::new_single(0.8),
+            specular: Color::default(),
+            emission: Color::default(),
+            shininess: 0.0,
+            refraction_index: 1.5,
+            opacity: 1.0,
+            illumination: 2,
+            diffuse_map: None,
+        }
+    }
+}
+
+// Materials are numbered in the order the library defines them
+fn load_library(
+    path: &Path,
+    ids: &mut MaterialIds,
+) -> Result<HashMap<String, Arc<dyn Material>>, Error> {
+    let source = fs::read_to_string(path)?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MaterialDef)> = None;
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            if let Some((name, def)) = current.take() {
+                materials.insert(name, to_material(def, ids)?);
+            }
+            let name = args.first().copied().unwrap_or_default().to_string();
+            current = Some((name, MaterialDef::default()));
+            continue;
+        }
+
+        let Some((_, def)) = current.as_mut() else {
+            return Err(Error::parse(
+                line_number,
+                format!("'{keyword}' appears before any 'newmtl'"),
+            ));
+        };
+        match keyword {
+            "Kd" => def.diffuse = vector(&args, line_number)?,
+            "Ks" => def.specular = vector(&args, line_number)?,
+            "Ke" => def.emission = vector(&args, line_number)?,
+            "Ns" => def.shininess = number(args.first(), line_number)?,
+            "Ni" => def.refraction_index = number(args.first(), line_number)?,
+            "d" => def.opacity = number(args.first(), line_number)?,
+            "Tr" => def.opacity = 1.0 - number(args.first(), line_number)?,
+            "illum" => {
+                def.illumination = args
+                    .first()
+                    .and_then(|a| a.parse().ok())
+                    .ok_or_else(|| Error::parse(line_number, "invalid illumination model"))?;
+            }
+            "map_Kd" => {
+                // Options such as "-s" are not supported, so the file name is the last argument
+                let name = args
+                    .last()
+                    .ok_or_else(|| Error::parse(line_number, "missing texture file name"))?;
+                def.diffuse_map = Some(base_dir.join(name));
+            }
+            _ => {}
+        }
+    }
+
+    if let Some((name, def)) = current {
+        materials.insert(name, to_material(def, ids)?);
+    }
+    Ok(materials)
+}
+
+// Emissive materials become lights, transparent or refracting illumination models become glass,
+// reflective models (or a purely specular colour) become metal, and everything else is diffuse
+fn to_material(def: MaterialDef, ids: &mut MaterialIds) -> Result<Arc<dyn Material>, Error> {
+    let max = |c: &Color| c.x().max(c.y()).max(c.z());
+
+    if max(&def.emission) > 0.0 {
+        return Ok(ids.add(DiffuseLight::new(def.emission)));
+    }
+    if def.opacity < 1.0 || matches!(def.illumination, 4 | 6 | 7 | 9) {
+        return Ok(ids.add(Dielectric::new(def.refraction_index)));
+    }
+    if matches!(def.illumination, 3 | 5)
+        || (max(&def.diffuse) == 0.0 && max(&def.specular) > 0.0 && def.diffuse_map.is_none())
+    {
+        // Map the Phong exponent onto a roughness in [0, 1]
+        let fuzz = (2.0 / (def.shininess.max(0.0) + 2.0)).sqrt();
+        return Ok(ids.add(Metal::new(def.specular, fuzz)));
+    }
+
+    match def.diffuse_map {
+        Some(path) => {
+            let image = Image::new_with_wrap(&path.to_string_lossy(), Wrap::Repeat)
+                .map_err(Error::Texture)?;
+            Ok(ids.add(Lambertian::new_with_texture(Box::new(image))))
+        }
+        None => Ok(ids.add(Lambertian::new(def.diffuse))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{material_at, write_files};
+    use super::*;
+
+    const QUADS: &[u8] = b"# two unit squares side by side\n\
+        mtllib quads.mtl\n\
+        v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+        v 2 0 0\nv 2 1 0\n\
+        vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+        vn 0 0 1\n\
+        usemtl red\n\
+        f 1/1/1 2/2/1 3/3/1 4/4/1\n\
+        usemtl glow\n\
+        f -5 5 6 -4\n";
+
+    const LIBRARY: &[u8] = b"newmtl glow\nKe 4 4 4\n\nnewmtl red\nKd 0.8 0.1 0.1\n";
+
+    fn error(test: &str, source: &[u8]) -> String {
+        let path = write_files(test, &[("mesh.obj", source)]);
+        match load(&path, None, &mut MaterialIds::default()) {
+            Ok(_) => panic!("loaded without an error"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn polygons_and_materials() {
+        let path = write_files("obj", &[("quads.obj", QUADS), ("quads.mtl", LIBRARY)]);
+        let mut ids = MaterialIds::default();
+        let triangles = load(&path, None, &mut ids).unwrap();
+        assert_eq!(triangles.objects.len(), 4);
+
+        // The default material comes first, then the library's in the order it defines them
+        assert_eq!(material_at(&triangles, 0.5, 0.5), Some(3));
+        assert_eq!(material_at(&triangles, 1.5, 0.5), Some(2));
+        assert_eq!(material_at(&triangles, 2.5, 0.5), None);
+    }
+
+    #[test]
+    fn degenerate_faces_are_dropped() {
+        let path = write_files(
+            "obj-degenerate",
+            &[(
+                "mesh.obj",
+                b"v 0 0 0\nv 1 0 0\nv 2 0 0\nv 0 1 0\nf 1 2 3\nf 1 2 4\n",
+            )],
+        );
+        let triangles = load(&path, None, &mut MaterialIds::default()).unwrap();
+        assert_eq!(triangles.objects.len(), 1);
+    }
+
+    #[test]
+    fn errors_carry_line_numbers() {
+        assert_eq!(
+            error("obj-range", b"v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n"),
+            "line 4: vertex index 4 is out of range"
+        );
+        assert_eq!(
+            error("obj-number", b"v 0 0 0\nv 1 zero 0\n"),
+            "line 2: invalid number 'zero'"
+        );
+        assert_eq!(
+            error("obj-face", b"v 0 0 0\nv 1 0 0\nf 1 2\n"),
+            "line 3: face needs at least three vertices"
+        );
+        assert_eq!(
+            error("obj-material", b"\nusemtl missing\n"),
+            "line 2: unknown material 'missing'"
+        );
+    }
+
+    #[test]
+    fn library_errors_name_the_library() {
+        let message = error("obj-library", b"mtllib missing.mtl\n");
+        assert!(message.contains("missing.mtl"), "{message}");
+
+        let path = write_files(
+            "obj-library-line",
+            &[
+                ("mesh.obj", b"mtllib bad.mtl\n"),
+                ("bad.mtl", b"Kd 1 1 1\n"),
+            ],
+        );
+        let message = load(&path, None, &mut MaterialIds::default())
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(
+            message.ends_with("bad.mtl: line 1: 'Kd' appears before any 'newmtl'"),
+            "{message}"
+        );
+    }
+}
@@ -0,0 +1,546 @@
+// Stanford PLY reader for ASCII and binary (little or big endian) files. Vertices may carry
+// normals, colors and texture coordinates, and polygon faces are split into triangle fans.
+// Elements other than "vertex" and "face" are read and discarded.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::{default_material, is_degenerate, Error};
+use crate::hittable::htlist::HittableList;
+use crate::hittable::triangle::Triangle;
+use crate::material::{lambertian::Lambertian, Material, MaterialIds};
+use crate::texture::vertex_color::VertexColor;
+use crate::vec3::{Color, Point3, Vec3};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn from_name(name: &str) -> Option<Scalar> {
+        match name {
+            "char" | "int8" => Some(Scalar::I8),
+            "uchar" | "uint8" => Some(Scalar::U8),
+            "short" | "int16" => Some(Scalar::I16),
+            "ushort" | "uint16" => Some(Scalar::U16),
+            "int" | "int32" => Some(Scalar::I32),
+            "uint" | "uint32" => Some(Scalar::U32),
+            "float" | "float32" => Some(Scalar::F32),
+            "double" | "float64" => Some(Scalar::F64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    // Integer colors span the full range of their type, float colors are already in [0, 1]
+    fn color_scale(self) -> f64 {
+        match self {
+            Scalar::U8 => 1.0 / 255.0,
+            Scalar::U16 => 1.0 / 65535.0,
+            _ => 1.0,
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    List(String, Scalar, Scalar),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar(name, _) | Property::List(name, _, _) => name,
+        }
+    }
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn find(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|p| names.contains(&p.name()))
+    }
+
+    // Finds all of the given scalar properties, or none of them
+    fn find_all<const N: usize>(&self, names: [&[&str]; N]) -> Option<[usize; N]> {
+        let mut indices = [0; N];
+        for (index, names) in indices.iter_mut().zip(names) {
+            *index = self.find(names)?;
+        }
+        Some(indices)
+    }
+}
+
+struct Vertex {
+    position: Point3,
+    normal: Option<Vec3>,
+    color: Option<Color>,
+    uv: Option<(f64, f64)>,
+}
+
+pub fn load(
+    path: &Path,
+    material: Option<Arc<dyn Material>>,
+    ids: &mut MaterialIds,
+) -> Result<HittableList, Error> {
+    let bytes = fs::read(path)?;
+    let (format, elements, body) = header(&bytes)?;
+
+    let mut reader = match format {
+        Format::Ascii => Reader::Ascii(
+            std::str::from_utf8(&bytes[body..])
+                .map_err(|_| Error::Malformed("ASCII body is not valid text".to_string()))?
+                .split_ascii_whitespace(),
+        ),
+        _ => Reader::Binary {
+            bytes: &bytes[body..],
+            pos: 0,
+            big_endian: format == Format::BigEndian,
+        },
+    };
+
+    let mut vertices = Vec::new();
+    let mut faces = Vec::new();
+    let mut row = Vec::new();
+    for element in &elements {
+        for _ in 0..element.count {
+            reader.row(element, &mut row)?;
+            match element.name.as_str() {
+                "vertex" => vertices.push(vertex(element, &row)?),
+                "face" => {
+                    let index = element
+                        .find(&["vertex_indices", "vertex_index"])
+                        .ok_or_else(|| {
+                            Error::Malformed("face has no vertex indices".to_string())
+                        })?;
+                    let Value::List(indices) = &row[index] else {
+                        return Err(Error::Malformed(
+                            "face vertex indices must be a list".to_string(),
+                        ));
+                    };
+                    faces.push(
+                        indices
+                            .iter()
+                            .map(|&i| to_index(i))
+                            .collect::<Result<Vec<_>, _>>()?,
+                    );
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Without a material of its own, a mesh with vertex colors is shaded by them
+    let colored = !vertices.is_empty() && vertices.iter().all(|v| v.color.is_some());
+    let material = match material {
+        Some(material) => material,
+        None if colored => ids.add(Lambertian::new_with_texture(Box::new(
+            VertexColor::default(),
+        ))),
+        None => default_material(ids),
+    };
+
+    let mut triangles = HittableList::default();
+    for face in &faces {
+        if face.len() < 3 {
+            return Err(Error::Malformed(
+                "face needs at least three vertices".to_string(),
+            ));
+        }
+        if let Some(&index) = face.iter().find(|&&i| i >= vertices.len()) {
+            return Err(Error::Malformed(format!(
+                "face references vertex {index} of {}",
+                vertices.len()
+            )));
+        }
+
+        for i in 1..face.len() - 1 {
+            let corners = [
+                &vertices[face[0]],
+                &vertices[face[i]],
+                &vertices[face[i + 1]],
+            ];
+            let positions = corners.map(|v| v.position);
+            if is_degenerate(&positions) {
+                continue;
+            }
+
+            let normals = corners
+                .iter()
+                .all(|v| v.normal.is_some())
+                .then(|| corners.map(|v| v.normal.expect("checked above")));
+            let uvs = corners
+                .iter()
+                .all(|v| v.uv.is_some())
+                .then(|| corners.map(|v| v.uv.expect("checked above")));
+            let colors = colored.then(|| corners.map(|v| v.color.expect("checked above")));
+            let triangle =
+                Triangle::new_with_attributes(positions, normals, uvs, colors, material.clone());
+            triangles.add(Arc::new(triangle));
+        }
+    }
+
+    Ok(triangles)
+}
+
+fn vertex(element: &Element, row: &[Value]) -> Result<Vertex, Error> {
+    let scalar = |index: usize| match row[index] {
+        Value::Scalar(v) => Ok(v),
+        Value::List(_) => Err(Error::Malformed(format!(
+            "vertex property '{}' must not be a list",
+            element.properties[index].name()
+        ))),
+    };
+    let vector = |[x, y, z]: [usize; 3]| -> Result<Vec3, Error> {
+        Ok(Vec3::new(scalar(x)?, scalar(y)?, scalar(z)?))
+    };
+
+    let position = element
+        .find_all([&["x"], &["y"], &["z"]])
+        .ok_or_else(|| Error::Malformed("vertex has no position".to_string()))?;
+    let normal = element.find_all([&["nx"], &["ny"], &["nz"]]);
+    let color = element.find_all([
+        &["red", "r", "diffuse_red"],
+        &["green", "g", "diffuse_green"],
+        &["blue", "b", "diffuse_blue"],
+    ]);
+    let uv = element.find_all([
+        &["u", "s", "texture_u", "texture_s"],
+        &["v", "t", "texture_v", "texture_t"],
+    ]);
+
+    Ok(Vertex {
+        position: vector(position)?,
+        normal: normal.map(vector).transpose()?,
+        color: color
+            .map(|indices| {
+                let scale = match element.properties[indices[0]] {
+                    Property::Scalar(_, kind) => kind.color_scale(),
+                    Property::List(..) => 1.0,
+                };
+                vector(indices).map(|c| c * scale)
+            })
+            .transpose()?,
+        uv: uv
+            .map(|[u, v]| -> Result<(f64, f64), Error> { Ok((scalar(u)?, scalar(v)?)) })
+            .transpose()?,
+    })
+}
+
+fn to_index(value: f64) -> Result<usize, Error> {
+    if value >= 0.0 && value.fract() == 0.0 && value <= f64::from(u32::MAX) {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // Checked above
+        Ok(value as usize)
+    } else {
+        Err(Error::Malformed(format!("invalid index or count {value}")))
+    }
+}
+
+// Returns the format, the declared elements and the offset of the first byte after the header
+fn header(bytes: &[u8]) -> Result<(Format, Vec<Element>, usize), Error> {
+    const END: &[u8] = b"end_header";
+    let end = bytes
+        .windows(END.len())
+        .position(|w| w == END)
+        .ok_or_else(|| Error::Malformed("missing 'end_header'".to_string()))?;
+    let mut body = end + END.len();
+    if bytes.get(body) == Some(&b'\r') {
+        body += 1;
+    }
+    if bytes.get(body) == Some(&b'\n') {
+        body += 1;
+    }
+
+    let text = String::from_utf8_lossy(&bytes[..end]);
+    let mut lines = text.lines().enumerate().map(|(i, l)| (i + 1, l));
+    if lines.next().map(|(_, l)| l.trim()) != Some("ply") {
+        return Err(Error::parse(1, "not a PLY file"));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for (line, text) in lines {
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        match tokens.as_slice() {
+            [] | ["comment" | "obj_info", ..] => {}
+            ["format", name, _version] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::LittleEndian,
+                    "binary_big_endian" => Format::BigEndian,
+                    _ => return Err(Error::parse(line, format!("unknown format '{name}'"))),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: (*name).to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| Error::parse(line, format!("invalid element count '{count}'")))?,
+                properties: Vec::new(),
+            }),
+            ["property", rest @ ..] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| Error::parse(line, "property declared before any element"))?;
+                let scalar = |name: &str| {
+                    Scalar::from_name(name).ok_or_else(|| {
+                        Error::parse(line, format!("unknown property type '{name}'"))
+                    })
+                };
+                element.properties.push(match rest {
+                    ["list", count, item, name] => {
+                        Property::List((*name).to_string(), scalar(count)?, scalar(item)?)
+                    }
+                    [kind, name] => Property::Scalar((*name).to_string(), scalar(kind)?),
+                    _ => return Err(Error::parse(line, "invalid property declaration")),
+                });
+            }
+            _ => {
+                return Err(Error::parse(
+                    line,
+                    format!("unexpected header line '{text}'"),
+                ))
+            }
+        }
+    }
+
+    let format = format.ok_or_else(|| Error::Malformed("missing 'format' line".to_string()))?;
+    Ok((format, elements, body))
+}
+
+enum Value {
+    Scalar(f64),
+    List(Vec<f64>),
+}
+
+enum Reader<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary {
+        bytes: &'a [u8],
+        pos: usize,
+        big_endian: bool,
+    },
+}
+
+impl Reader<'_> {
+    fn row(&mut self, element: &Element, row: &mut Vec<Value>) -> Result<(), Error> {
+        row.clear();
+        for property in &element.properties {
+            row.push(match *property {
+                Property::Scalar(_, kind) => Value::Scalar(self.scalar(kind)?),
+                Property::List(_, count, item) => {
+                    let count = to_index(self.scalar(count)?)?;
+                    let items = (0..count)
+                        .map(|_| self.scalar(item))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Value::List(items)
+                }
+            });
+        }
+        Ok(())
+    }
+
+    fn scalar(&mut self, kind: Scalar) -> Result<f64, Error> {
+        let truncated = || Error::Malformed("unexpected end of data".to_string());
+        match self {
+            Reader::Ascii(tokens) => {
+                let token = tokens.next().ok_or_else(truncated)?;
+                token
+                    .parse()
+                    .map_err(|_| Error::Malformed(format!("invalid number '{token}'")))
+            }
+            Reader::Binary {
+                bytes,
+                pos,
+                big_endian,
+            } => {
+                let size = kind.size();
+                let data = bytes.get(*pos..*pos + size).ok_or_else(truncated)?;
+                *pos += size;
+
+                // Normalize to little endian so every type is decoded the same way
+                let mut b = [0u8; 8];
+                b[..size].copy_from_slice(data);
+                if *big_endian {
+                    b[..size].reverse();
+                }
+                Ok(match kind {
+                    Scalar::I8 => f64::from(i8::from_le_bytes([b[0]])),
+                    Scalar::U8 => f64::from(b[0]),
+                    Scalar::I16 => f64::from(i16::from_le_bytes([b[0], b[1]])),
+                    Scalar::U16 => f64::from(u16::from_le_bytes([b[0], b[1]])),
+                    Scalar::I32 => f64::from(i32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                    Scalar::U32 => f64::from(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                    Scalar::F32 => f64::from(f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                    Scalar::F64 => f64::from_le_bytes(b),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write;
+
+    use super::super::tests::{material_at, write_files};
+    use super::*;
+
+    const HEADER: &str = "element vertex 4\n\
+                          property float x\n\
+                          property float y\n\
+                          property float z\n\
+                          property uchar red\n\
+                          property uchar green\n\
+                          property uchar blue\n\
+                          element face 1\n\
+                          property list uchar int vertex_indices\n\
+                          end_header\n";
+
+    // A unit square in the z = 0 plane, red along x = 0 and blue along x = 1
+    const VERTICES: [([f32; 3], [u8; 3]); 4] = [
+        ([0.0, 0.0, 0.0], [255, 0, 0]),
+        ([1.0, 0.0, 0.0], [0, 0, 255]),
+        ([1.0, 1.0, 0.0], [0, 0, 255]),
+        ([0.0, 1.0, 0.0], [255, 0, 0]),
+    ];
+
+    fn ascii() -> Vec<u8> {
+        let mut text = format!("ply\nformat ascii 1.0\ncomment a square\n{HEADER}");
+        for ([x, y, z], [r, g, b]) in VERTICES {
+            writeln!(text, "{x} {y} {z} {r} {g} {b}").expect("writing to a string");
+        }
+        text += "4 0 1 2 3\n";
+        text.into_bytes()
+    }
+
+    fn binary(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian { "big" } else { "little" };
+        let mut bytes = format!("ply\nformat binary_{format}_endian 1.0\n{HEADER}").into_bytes();
+        for (position, color) in VERTICES {
+            for value in position {
+                bytes.extend(if big_endian {
+                    value.to_be_bytes()
+                } else {
+                    value.to_le_bytes()
+                });
+            }
+            bytes.extend(color);
+        }
+        bytes.push(4);
+        for index in 0..4i32 {
+            bytes.extend(if big_endian {
+                index.to_be_bytes()
+            } else {
+                index.to_le_bytes()
+            });
+        }
+        bytes
+    }
+
+    fn error(test: &str, source: &[u8]) -> String {
+        let path = write_files(test, &[("mesh.ply", source)]);
+        match load(&path, None, &mut MaterialIds::default()) {
+            Ok(_) => panic!("loaded without an error"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn ascii_and_binary_agree() {
+        for (test, bytes) in [
+            ("ply-ascii", ascii()),
+            ("ply-little", binary(false)),
+            ("ply-big", binary(true)),
+        ] {
+            let path = write_files(test, &[("square.ply", &bytes)]);
+            let triangles = load(&path, None, &mut MaterialIds::default()).unwrap();
+            assert_eq!(triangles.objects.len(), 2, "{test}");
+
+            // Both triangles are shaded by the vertex color material made for the mesh
+            assert_eq!(material_at(&triangles, 0.8, 0.2), Some(1), "{test}");
+            assert_eq!(material_at(&triangles, 0.2, 0.8), Some(1), "{test}");
+            assert_eq!(material_at(&triangles, 1.5, 0.5), None, "{test}");
+        }
+    }
+
+    #[test]
+    fn vertex_colors_are_blended() {
+        let path = write_files("ply-colors", &[("square.ply", &ascii())]);
+        let triangles = load(&path, None, &mut MaterialIds::default()).unwrap();
+        let ray = crate::ray::Ray::new(Point3::new(0.25, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut rng = crate::utils::scene_rng(0);
+        let color = triangles
+            .objects
+            .iter()
+            .find_map(|object| object.hit(&ray, 0.001, f64::INFINITY, &mut rng))
+            .and_then(|rec| rec.vertex_color)
+            .unwrap();
+        assert!((color - Color::new(0.75, 0.0, 0.25)).length() < 1e-9);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            error("ply-magic", b"plx\nformat ascii 1.0\nend_header\n"),
+            "line 1: not a PLY file"
+        );
+        assert_eq!(
+            error("ply-format", b"ply\nformat utf8 1.0\nend_header\n"),
+            "line 2: unknown format 'utf8'"
+        );
+        assert_eq!(
+            error(
+                "ply-property",
+                b"ply\nformat ascii 1.0\nelement vertex 1\nproperty half x\nend_header\n"
+            ),
+            "line 4: unknown property type 'half'"
+        );
+
+        let mut truncated = ascii();
+        truncated.truncate(truncated.len() - 4);
+        assert_eq!(
+            error("ply-truncated", &truncated),
+            "malformed mesh: unexpected end of data"
+        );
+
+        let mut out_of_range = ascii();
+        out_of_range.truncate(out_of_range.len() - 2);
+        out_of_range.extend_from_slice(b"9\n");
+        assert_eq!(
+            error("ply-range", &out_of_range),
+            "malformed mesh: face references vertex 9 of 4"
+        );
+    }
+}
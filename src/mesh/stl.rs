@@ -0,0 +1,202 @@
+// STL reader for ASCII and binary files. Facet normals are ignored since triangles compute
+// their own, and STL has no texture coordinates, so every facet uses the same material.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::{default_material, is_degenerate, Error};
+use crate::hittable::htlist::HittableList;
+use crate::hittable::triangle::Triangle;
+use crate::material::{Material, MaterialIds};
+use crate::vec3::Point3;
+
+const HEADER_SIZE: usize = 80;
+const FACET_SIZE: usize = 50;
+
+pub fn load(
+    path: &Path,
+    material: Option<Arc<dyn Material>>,
+    ids: &mut MaterialIds,
+) -> Result<HittableList, Error> {
+    let bytes = fs::read(path)?;
+    let material = material.unwrap_or_else(|| default_material(ids));
+
+    let facets = if is_binary(&bytes) {
+        binary(&bytes)?
+    } else {
+        let text = std::str::from_utf8(&bytes)
+            .map_err(|_| Error::Malformed("truncated binary STL".to_string()))?;
+        ascii(text)?
+    };
+
+    let mut triangles = HittableList::default();
+    for vertices in facets {
+        if !is_degenerate(&vertices) {
+            let [v0, v1, v2] = vertices;
+            triangles.add(Arc::new(Triangle::new(v0, v1, v2, material.clone())));
+        }
+    }
+    Ok(triangles)
+}
+
+// Binary files may also start with "solid", so the facet count has to match the file size too
+fn is_binary(bytes: &[u8]) -> bool {
+    if bytes.len() >= HEADER_SIZE + 4 {
+        let count = u32::from_le_bytes(
+            bytes[HEADER_SIZE..HEADER_SIZE + 4]
+                .try_into()
+                .expect("slice of four bytes"),
+        );
+        let size = usize::try_from(count)
+            .ok()
+            .and_then(|c| c.checked_mul(FACET_SIZE))
+            .and_then(|s| s.checked_add(HEADER_SIZE + 4));
+        if size == Some(bytes.len()) {
+            return true;
+        }
+    }
+    !bytes.trim_ascii_start().starts_with(b"solid")
+}
+
+// Each facet is a normal, three vertices and a 16-bit attribute, all little endian
+fn binary(bytes: &[u8]) -> Result<Vec<[Point3; 3]>, Error> {
+    let body = bytes
+        .get(HEADER_SIZE + 4..)
+        .ok_or_else(|| Error::Malformed("truncated binary STL".to_string()))?;
+    if body.len() % FACET_SIZE != 0 {
+        return Err(Error::Malformed("truncated binary STL".to_string()));
+    }
+
+    let float = |b: &[u8]| f64::from(f32::from_le_bytes(b.try_into().expect("four bytes")));
+    let point = |b: &[u8]| Point3::new(float(&b[0..4]), float(&b[4..8]), float(&b[8..12]));
+    Ok(body
+        .chunks_exact(FACET_SIZE)
+        .map(|facet| {
+            [
+                point(&facet[12..24]),
+                point(&facet[24..36]),
+                point(&facet[36..48]),
+            ]
+        })
+        .collect())
+}
+
+fn ascii(text: &str) -> Result<Vec<[Point3; 3]>, Error> {
+    let mut facets = Vec::new();
+    let mut vertices = Vec::with_capacity(3);
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["vertex", x, y, z] => {
+                let number = |s: &str| {
+                    s.parse::<f64>()
+                        .map_err(|_| Error::parse(line_number, format!("invalid number '{s}'")))
+                };
+                vertices.push(Point3::new(number(x)?, number(y)?, number(z)?));
+            }
+            ["vertex", ..] => {
+                return Err(Error::parse(line_number, "vertex needs three coordinates"));
+            }
+            ["endloop"] => {
+                let facet: [Point3; 3] = vertices.as_slice().try_into().map_err(|_| {
+                    Error::parse(line_number, "facet must have exactly three vertices")
+                })?;
+                facets.push(facet);
+                vertices.clear();
+            }
+            // "solid", "facet normal", "outer loop", "endfacet" and "endsolid" carry no geometry
+            _ => {}
+        }
+    }
+
+    Ok(facets)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write;
+
+    use super::super::tests::{material_at, write_files};
+    use super::*;
+
+    // A unit square in the z = 0 plane, split along its diagonal
+    const FACETS: [[[f32; 3]; 3]; 2] = [
+        [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]],
+        [[0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]],
+    ];
+
+    fn ascii() -> Vec<u8> {
+        let mut text = String::from("solid square\n");
+        for facet in FACETS {
+            text += "  facet normal 0 0 1\n    outer loop\n";
+            for [x, y, z] in facet {
+                writeln!(text, "      vertex {x} {y} {z}").expect("writing to a string");
+            }
+            text += "    endloop\n  endfacet\n";
+        }
+        text += "endsolid square\n";
+        text.into_bytes()
+    }
+
+    // The header starts with "solid" as some exporters write it, leaving the size to tell
+    fn binary() -> Vec<u8> {
+        let mut bytes = b"solid square".to_vec();
+        bytes.resize(HEADER_SIZE, b' ');
+        bytes.extend(2u32.to_le_bytes());
+        for facet in FACETS {
+            bytes.extend([0.0f32, 0.0, 1.0].iter().flat_map(|v| v.to_le_bytes()));
+            bytes.extend(facet.iter().flatten().flat_map(|v| v.to_le_bytes()));
+            bytes.extend([0, 0]);
+        }
+        bytes
+    }
+
+    fn error(test: &str, source: &[u8]) -> String {
+        let path = write_files(test, &[("mesh.stl", source)]);
+        match load(&path, None, &mut MaterialIds::default()) {
+            Ok(_) => panic!("loaded without an error"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn ascii_and_binary_agree() {
+        for (test, bytes) in [("stl-ascii", ascii()), ("stl-binary", binary())] {
+            assert_eq!(is_binary(&bytes), test == "stl-binary");
+            let path = write_files(test, &[("square.stl", &bytes)]);
+            let triangles = load(&path, None, &mut MaterialIds::default()).unwrap();
+            assert_eq!(triangles.objects.len(), 2, "{test}");
+            assert_eq!(material_at(&triangles, 0.8, 0.2), Some(1), "{test}");
+            assert_eq!(material_at(&triangles, 0.2, 0.8), Some(1), "{test}");
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let mut truncated = binary();
+        truncated.pop();
+        truncated[0] = b'x';
+        assert_eq!(
+            error("stl-truncated", &truncated),
+            "malformed mesh: truncated binary STL"
+        );
+
+        assert_eq!(
+            error(
+                "stl-vertex",
+                b"solid s\nfacet normal 0 0 1\nouter loop\nvertex 0 0\n"
+            ),
+            "line 4: vertex needs three coordinates"
+        );
+        assert_eq!(
+            error(
+                "stl-facet",
+                b"solid s\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nendloop\n"
+            ),
+            "line 6: facet must have exactly three vertices"
+        );
+    }
+}
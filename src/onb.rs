@@ -0,0 +1,31 @@
+use crate::vec3::Vec3;
+
+// Orthonormal basis whose w axis points along a given direction, used to turn directions
+// generated around +z into directions around a surface normal
+pub struct Onb {
+    axis: [Vec3; 3],
+}
+
+impl Onb {
+    pub fn build_from_w(normal: &Vec3) -> Onb {
+        let w = normal.unit_vector();
+        // Any vector not parallel to w will do to start the cross products
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(&a).unit_vector();
+        let u = w.cross(&v);
+
+        Onb { axis: [u, v, w] }
+    }
+
+    pub fn w(&self) -> &Vec3 {
+        &self.axis[2]
+    }
+
+    pub fn local(&self, a: &Vec3) -> Vec3 {
+        a.x() * self.axis[0] + a.y() * self.axis[1] + a.z() * self.axis[2]
+    }
+}
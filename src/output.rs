@@ -0,0 +1,210 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::codec::{exr, pfm, png, ppm, rgbe, Bitmap, HdrImage};
+
+// Writes a display-ready 8-bit image
+pub trait ImageWriter {
+    fn write(&self, image: &Bitmap, out: &mut dyn Write) -> io::Result<()>;
+}
+
+// Writes linear, unclamped radiance
+pub trait HdrWriter {
+    fn write(&self, image: &HdrImage, out: &mut dyn Write) -> io::Result<()>;
+}
+
+pub enum Writer {
+    Ldr(Box<dyn ImageWriter>),
+    Hdr(Box<dyn HdrWriter>),
+}
+
+pub struct Png;
+
+impl ImageWriter for Png {
+    fn write(&self, image: &Bitmap, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(&png::encode(image))
+    }
+}
+
+// Binary (P6) portable pixmap
+pub struct Ppm;
+
+impl ImageWriter for Ppm {
+    fn write(&self, image: &Bitmap, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(&ppm::encode_binary(image))
+    }
+}
+
+// ASCII (P3) portable pixmap
+pub struct AsciiPpm;
+
+impl ImageWriter for AsciiPpm {
+    fn write(&self, image: &Bitmap, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(&ppm::encode_ascii(image))
+    }
+}
+
+// Portable float map
+pub struct Pfm;
+
+impl HdrWriter for Pfm {
+    fn write(&self, image: &HdrImage, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(&pfm::encode(image))
+    }
+}
+
+// Radiance RGBE
+pub struct Radiance;
+
+impl HdrWriter for Radiance {
+    fn write(&self, image: &HdrImage, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(&rgbe::encode(image))
+    }
+}
+
+pub struct OpenExr {
+    pub compression: exr::Compression,
+}
+
+impl HdrWriter for OpenExr {
+    fn write(&self, image: &HdrImage, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(&exr::encode(image, self.compression))
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Format {
+    Png,
+    Ppm,
+    AsciiPpm,
+    Pfm,
+    Radiance,
+    Exr,
+    ExrUncompressed,
+}
+
+impl Format {
+    pub const ALL: [Format; 7] = [
+        Format::Png,
+        Format::Ppm,
+        Format::AsciiPpm,
+        Format::Pfm,
+        Format::Radiance,
+        Format::Exr,
+        Format::ExrUncompressed,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Ppm => "p6",
+            Format::AsciiPpm => "p3",
+            Format::Pfm => "pfm",
+            Format::Radiance => "hdr",
+            Format::Exr => "exr",
+            Format::ExrUncompressed => "exr-none",
+        }
+    }
+
+    // Extension `from_path` recognizes the format by
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Ppm => "pnm",
+            Format::AsciiPpm => "ppm",
+            Format::Pfm => "pfm",
+            Format::Radiance => "hdr",
+            Format::Exr | Format::ExrUncompressed => "exr",
+        }
+    }
+
+    // Whether the format keeps linear, unclamped values
+    pub fn is_hdr(self) -> bool {
+        matches!(self.writer(), Writer::Hdr(_))
+    }
+
+    // Compression of the EXR formats, which can hold several layers in one file
+    pub fn exr_compression(self) -> Option<exr::Compression> {
+        match self {
+            Format::Exr => Some(exr::Compression::Zip),
+            Format::ExrUncompressed => Some(exr::Compression::None),
+            _ => None,
+        }
+    }
+
+    // .ppm files are ASCII P3, as the renderer has always written them, and .pnm files the far
+    // smaller binary P6. .exr files are ZIP compressed; uncompressed EXR must be requested
+    // explicitly
+    pub fn from_path(path: &Path) -> Result<Format, Error> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+
+        match extension.as_deref() {
+            Some("png") => Ok(Format::Png),
+            Some("ppm") => Ok(Format::AsciiPpm),
+            Some("pnm") => Ok(Format::Ppm),
+            Some("pfm") => Ok(Format::Pfm),
+            Some("hdr" | "rgbe") => Ok(Format::Radiance),
+            Some("exr") => Ok(Format::Exr),
+            _ => Err(Error::UnknownExtension(path.display().to_string())),
+        }
+    }
+
+    pub fn writer(self) -> Writer {
+        match self {
+            Format::Png => Writer::Ldr(Box::new(Png)),
+            Format::Ppm => Writer::Ldr(Box::new(Ppm)),
+            Format::AsciiPpm => Writer::Ldr(Box::new(AsciiPpm)),
+            Format::Pfm => Writer::Hdr(Box::new(Pfm)),
+            Format::Radiance => Writer::Hdr(Box::new(Radiance)),
+            Format::Exr => Writer::Hdr(Box::new(OpenExr {
+                compression: exr::Compression::Zip,
+            })),
+            Format::ExrUncompressed => Writer::Hdr(Box::new(OpenExr {
+                compression: exr::Compression::None,
+            })),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Format, Error> {
+        let name = s.to_ascii_lowercase();
+        Format::ALL
+            .into_iter()
+            .find(|f| f.name() == name)
+            .ok_or_else(|| Error::UnknownFormat(s.to_string()))
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    UnknownFormat(String),
+    UnknownExtension(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = Format::ALL.iter().map(|f| f.name()).collect();
+        match self {
+            Error::UnknownFormat(name) => write!(
+                f,
+                "unknown output format '{name}', expected one of: {}",
+                names.join(", ")
+            ),
+            Error::UnknownExtension(path) => write!(
+                f,
+                "cannot infer an output format from '{path}', use --format with one of: {}",
+                names.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
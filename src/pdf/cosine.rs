@@ -0,0 +1,37 @@
+use std::f64::consts::PI;
+
+use super::Pdf;
+use crate::{onb::Onb, vec3::Vec3};
+
+// Directions in the hemisphere around a normal, weighted by the cosine to it
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(w: &Vec3) -> CosinePdf {
+        CosinePdf {
+            uvw: Onb::build_from_w(w),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let cosine = direction.unit_vector().dot(self.uvw.w());
+        (cosine / PI).max(0.0)
+    }
+
+    fn generate(&self, u: (f64, f64)) -> Vec3 {
+        self.uvw.local(&cosine_direction(u))
+    }
+}
+
+fn cosine_direction((r1, r2): (f64, f64)) -> Vec3 {
+    let phi = 2.0 * PI * r1;
+    let x = phi.cos() * r2.sqrt();
+    let y = phi.sin() * r2.sqrt();
+    let z = (1.0 - r2).sqrt();
+
+    Vec3::new(x, y, z)
+}
@@ -0,0 +1,23 @@
+use super::Pdf;
+use crate::{environment::Environment, vec3::Vec3};
+
+// Directions towards the bright parts of an environment that is sampled like a light
+pub struct EnvironmentPdf<'a> {
+    environment: &'a dyn Environment,
+}
+
+impl EnvironmentPdf<'_> {
+    pub fn new(environment: &dyn Environment) -> EnvironmentPdf<'_> {
+        EnvironmentPdf { environment }
+    }
+}
+
+impl Pdf for EnvironmentPdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.environment.pdf_value(direction)
+    }
+
+    fn generate(&self, u: (f64, f64)) -> Vec3 {
+        self.environment.random(u)
+    }
+}
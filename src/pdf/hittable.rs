@@ -0,0 +1,27 @@
+use super::Pdf;
+use crate::{
+    hittable::Hittable,
+    vec3::{Point3, Vec3},
+};
+
+// Directions from a point towards an object, such as a light
+pub struct HittablePdf<'a> {
+    object: &'a dyn Hittable,
+    origin: Point3,
+}
+
+impl HittablePdf<'_> {
+    pub fn new(object: &dyn Hittable, origin: Point3) -> HittablePdf<'_> {
+        HittablePdf { object, origin }
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.object.pdf_value(&self.origin, direction)
+    }
+
+    fn generate(&self, u: (f64, f64)) -> Vec3 {
+        self.object.random(&self.origin, u)
+    }
+}
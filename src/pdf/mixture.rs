@@ -0,0 +1,28 @@
+use super::Pdf;
+use crate::vec3::Vec3;
+
+// Draws from either of two distributions with equal probability
+pub struct MixturePdf<'a> {
+    pdfs: [&'a dyn Pdf; 2],
+}
+
+impl MixturePdf<'_> {
+    pub fn new<'a>(p0: &'a dyn Pdf, p1: &'a dyn Pdf) -> MixturePdf<'a> {
+        MixturePdf { pdfs: [p0, p1] }
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        0.5 * self.pdfs[0].value(direction) + 0.5 * self.pdfs[1].value(direction)
+    }
+
+    // The first coordinate picks a distribution and is then stretched back over [0, 1)
+    fn generate(&self, (u0, u1): (f64, f64)) -> Vec3 {
+        if u0 < 0.5 {
+            self.pdfs[0].generate((u0 * 2.0, u1))
+        } else {
+            self.pdfs[1].generate((u0 * 2.0 - 1.0, u1))
+        }
+    }
+}
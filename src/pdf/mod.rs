@@ -0,0 +1,24 @@
+pub mod cosine;
+pub mod environment;
+pub mod hittable;
+pub mod mixture;
+pub mod phong;
+pub mod sphere;
+
+use crate::vec3::Vec3;
+
+// A distribution of directions: `generate` draws from it and `value` gives the density of a
+// direction per unit solid angle, so a sample can be weighted by one over its probability.
+// `generate` maps a point `u` of the unit square to a direction, so well-spread points give
+// well-spread directions
+pub trait Pdf {
+    fn value(&self, direction: &Vec3) -> f64;
+    fn generate(&self, u: (f64, f64)) -> Vec3;
+}
+
+// Weight for a sample drawn with density `f` when another strategy could have drawn the same
+// direction with density `g`; the weights of both strategies sum to one
+pub fn power_heuristic(f: f64, g: f64) -> f64 {
+    let f2 = f * f;
+    f2 / (f2 + g * g)
+}
@@ -0,0 +1,39 @@
+use std::f64::consts::PI;
+
+use super::Pdf;
+use crate::{onb::Onb, vec3::Vec3};
+
+// Directions clustered around an axis with density proportional to cos^exponent of the angle to
+// it, as in a Phong highlight. Higher exponents give a tighter lobe
+pub struct PhongPdf {
+    uvw: Onb,
+    exponent: f64,
+}
+
+impl PhongPdf {
+    pub fn new(axis: &Vec3, exponent: f64) -> PhongPdf {
+        PhongPdf {
+            uvw: Onb::build_from_w(axis),
+            exponent,
+        }
+    }
+}
+
+impl Pdf for PhongPdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let cosine = direction.unit_vector().dot(self.uvw.w());
+        if cosine <= 0.0 {
+            return 0.0;
+        }
+        (self.exponent + 1.0) / (2.0 * PI) * cosine.powf(self.exponent)
+    }
+
+    fn generate(&self, (r1, r2): (f64, f64)) -> Vec3 {
+        let phi = 2.0 * PI * r1;
+        let z = r2.powf(1.0 / (self.exponent + 1.0));
+        let sin_theta = (1.0 - z * z).sqrt();
+
+        self.uvw
+            .local(&Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z))
+    }
+}
@@ -0,0 +1,17 @@
+use std::f64::consts::PI;
+
+use super::Pdf;
+use crate::vec3::Vec3;
+
+// Directions spread evenly over the whole sphere
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self, u: (f64, f64)) -> Vec3 {
+        Vec3::uniform_sphere(u)
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    utils,
+    utils::{self, Rng},
     vec3::{Point3, Vec3},
 };
 
@@ -13,6 +13,20 @@ pub struct Perlin {
 }
 
 impl Perlin {
+    pub fn new(rng: &mut Rng) -> Perlin {
+        let mut rand_vec = Vec::with_capacity(POINT_COUNT as usize);
+        for _ in 0..POINT_COUNT {
+            rand_vec.push(Vec3::random_range(rng, -1.0, 1.0).unit_vector());
+        }
+
+        Perlin {
+            rand_vec,
+            perm_x: perlin_generate_perm(rng),
+            perm_y: perlin_generate_perm(rng),
+            perm_z: perlin_generate_perm(rng),
+        }
+    }
+
     pub fn noise(&self, point: &Point3) -> f64 {
         let u = point.x() - point.x().floor();
         let v = point.y() - point.y().floor();
@@ -58,28 +72,12 @@ impl Perlin {
     }
 }
 
-impl Default for Perlin {
-    fn default() -> Perlin {
-        let mut rand_vec = Vec::with_capacity(POINT_COUNT as usize);
-        for _ in 0..POINT_COUNT {
-            rand_vec.push(Vec3::random_range(-1.0, 1.0).unit_vector());
-        }
-
-        Perlin {
-            rand_vec,
-            perm_x: perlin_generate_perm(),
-            perm_y: perlin_generate_perm(),
-            perm_z: perlin_generate_perm(),
-        }
-    }
-}
-
-fn perlin_generate_perm() -> Vec<i32> {
+fn perlin_generate_perm(rng: &mut Rng) -> Vec<i32> {
     let mut perm = Vec::with_capacity(POINT_COUNT as usize);
     perm.extend(0..POINT_COUNT);
 
     for i in (1..POINT_COUNT).rev() {
-        let target = utils::random_int(0, i);
+        let target = utils::random_int(rng, 0, i);
         perm.swap(i.try_into().unwrap(), target.try_into().unwrap());
     }
 
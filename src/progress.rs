@@ -0,0 +1,137 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Time between progress lines on a terminal, where each replaces the last, and elsewhere
+const TERMINAL_INTERVAL: Duration = Duration::from_millis(250);
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+// Reports how far a render has got on stderr, with the estimated time left and the rays traced
+// per second. Render threads add their work as they finish tiles
+pub struct Progress {
+    start: Instant,
+    // Samples the render is expected to take, at most; adaptive sampling can finish early
+    total_samples: u64,
+    deadline: Option<Instant>,
+    samples: AtomicU64,
+    rays: AtomicU64,
+    // When the last line was printed, or None when reporting is off
+    last_report: Option<Mutex<Instant>>,
+    terminal: bool,
+}
+
+impl Progress {
+    pub fn new(total_samples: u64, deadline: Option<Instant>) -> Progress {
+        let start = Instant::now();
+        Progress {
+            start,
+            total_samples,
+            deadline,
+            samples: AtomicU64::new(0),
+            rays: AtomicU64::new(0),
+            last_report: Some(Mutex::new(start)),
+            terminal: io::stderr().is_terminal(),
+        }
+    }
+
+    // Counts work without printing anything
+    pub fn quiet() -> Progress {
+        Progress {
+            last_report: None,
+            ..Progress::new(u64::MAX, None)
+        }
+    }
+
+    pub fn rays(&self) -> u64 {
+        self.rays.load(Ordering::Relaxed)
+    }
+
+    pub fn add(&self, samples: u64, rays: u64) {
+        self.samples.fetch_add(samples, Ordering::Relaxed);
+        self.rays.fetch_add(rays, Ordering::Relaxed);
+
+        let Some(last_report) = &self.last_report else {
+            return;
+        };
+        // Another thread is already printing
+        let Ok(mut last_report) = last_report.try_lock() else {
+            return;
+        };
+        let interval = if self.terminal {
+            TERMINAL_INTERVAL
+        } else {
+            LOG_INTERVAL
+        };
+        if last_report.elapsed() >= interval {
+            *last_report = Instant::now();
+            self.print();
+        }
+    }
+
+    // Ends the line a terminal was updating in place, and prints the totals
+    pub fn finish(&self) {
+        if self.last_report.is_some() {
+            self.print();
+            if self.terminal {
+                eprintln!();
+            }
+        }
+    }
+
+    fn print(&self) {
+        let elapsed = self.start.elapsed();
+        let samples = self.samples.load(Ordering::Relaxed);
+        #[allow(clippy::cast_precision_loss)] // Counts are far below 2^52
+        let fraction = match self.deadline {
+            Some(deadline) => {
+                let budget = deadline.saturating_duration_since(self.start).as_secs_f64();
+                elapsed.as_secs_f64() / budget.max(f64::MIN_POSITIVE)
+            }
+            None => samples as f64 / self.total_samples.max(1) as f64,
+        }
+        .min(1.0);
+        let remaining = if fraction > 0.0 {
+            elapsed.as_secs_f64() * (1.0 - fraction) / fraction
+        } else {
+            f64::INFINITY
+        };
+        #[allow(clippy::cast_precision_loss)] // As above
+        let rays_per_second = self.rays() as f64 / elapsed.as_secs_f64().max(f64::MIN_POSITIVE);
+
+        let line = format!(
+            "{:5.1}%  {} elapsed  {} left  {:.2} Mrays/s",
+            fraction * 100.0,
+            clock(elapsed.as_secs_f64()),
+            clock(remaining),
+            rays_per_second / 1e6
+        );
+        let mut stderr = io::stderr().lock();
+        // A failed progress line is not worth stopping the render for
+        let _ = if self.terminal {
+            write!(stderr, "\r{line}\x1b[K")
+        } else {
+            writeln!(stderr, "{line}")
+        };
+        let _ = stderr.flush();
+    }
+}
+
+// Hours, minutes and seconds
+fn clock(seconds: f64) -> String {
+    if !seconds.is_finite() {
+        return "--:--".to_string();
+    }
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // Rounded, non-negative
+    let seconds = seconds.max(0.0).round() as u64;
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
@@ -0,0 +1,120 @@
+use super::{hash, to_float, Sampler};
+
+// Dimensions past this many primes fall back to independent random values, since Halton points in
+// large bases are poorly spread over the small sample counts of one pixel
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+// The Halton sequence, the radical inverse of the sample index in a different prime base for each
+// dimension. Each pixel shifts every dimension by its own random offset (a Cranley-Patterson
+// rotation) so that neighbouring pixels do not share the same pattern
+pub struct Halton {
+    seed: u64,
+    sample: u64,
+    dimension: usize,
+}
+
+impl Halton {
+    pub fn new(seed: u64) -> Halton {
+        Halton {
+            seed,
+            sample: 0,
+            dimension: 0,
+        }
+    }
+
+    fn next(&mut self) -> f64 {
+        let offset = hash(self.seed, self.dimension as u64);
+        let value = match PRIMES.get(self.dimension) {
+            Some(&base) => {
+                let shifted = radical_inverse(base, self.sample) + to_float(offset);
+                shifted - shifted.floor()
+            }
+            None => to_float(hash(offset, self.sample)),
+        };
+        self.dimension += 1;
+        value
+    }
+}
+
+impl Sampler for Halton {
+    fn start_sample(&mut self, index: u64) {
+        self.sample = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.next()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.next(), self.next())
+    }
+}
+
+// Mirrors the digits of `index` in `base` about the radix point
+fn radical_inverse(base: u64, mut index: u64) -> f64 {
+    #[allow(clippy::cast_precision_loss)] // Bases are small primes
+    let inverse_base = 1.0 / base as f64;
+    let mut scale = inverse_base;
+    let mut result = 0.0;
+
+    while index > 0 {
+        #[allow(clippy::cast_precision_loss)] // Digits are below the base
+        let digit = (index % base) as f64;
+        result += digit * scale;
+        scale *= inverse_base;
+        index /= base;
+    }
+
+    // Rounding can otherwise reach one
+    result.min(1.0 - f64::EPSILON)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn radical_inverse_mirrors_the_digits() {
+        let expected = [
+            (2, 1, 0.5),
+            (2, 2, 0.25),
+            (2, 3, 0.75),
+            (2, 6, 0.375),
+            (3, 1, 1.0 / 3.0),
+            (3, 5, 1.0 / 9.0 + 2.0 / 3.0),
+            (5, 7, 2.0 / 5.0 + 1.0 / 25.0),
+        ];
+        for (base, index, value) in expected {
+            assert!((radical_inverse(base, index) - value).abs() < 1e-15);
+        }
+        assert!(radical_inverse(2, u64::MAX) < 1.0);
+    }
+
+    #[test]
+    fn dimensions_are_shifted_sequences() {
+        // Each dimension is the sequence in its own base, rotated by the same offset for every
+        // sample, which the first sample (index 0) reveals
+        let mut sampler = Halton::new(11);
+        let mut values = Vec::new();
+        for index in 0..20 {
+            sampler.start_sample(index);
+            values.push([sampler.get_1d(), sampler.get_1d(), sampler.get_1d()]);
+        }
+
+        for (dimension, base) in PRIMES[..3].iter().enumerate() {
+            for (index, value) in (0..).zip(&values) {
+                let unshifted = value[dimension] - values[0][dimension];
+                let unshifted = unshifted - unshifted.floor();
+                let expected = radical_inverse(*base, index);
+                assert!(
+                    (unshifted - expected).abs() < 1e-9,
+                    "base {base}, index {index}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use super::{hash, to_float, Sampler};
+
+// Every value is an independent uniform random number
+pub struct Independent {
+    seed: u64,
+    sample: u64,
+    dimension: u64,
+}
+
+impl Independent {
+    pub fn new(seed: u64) -> Independent {
+        Independent {
+            seed,
+            sample: 0,
+            dimension: 0,
+        }
+    }
+
+    fn next(&mut self) -> f64 {
+        let value = to_float(hash(hash(self.seed, self.sample), self.dimension));
+        self.dimension += 1;
+        value
+    }
+}
+
+impl Sampler for Independent {
+    fn start_sample(&mut self, index: u64) {
+        self.sample = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.next()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.next(), self.next())
+    }
+}
@@ -0,0 +1,140 @@
+pub mod halton;
+pub mod independent;
+pub mod sobol;
+pub mod stratified;
+
+use std::str::FromStr;
+
+use crate::utils;
+
+// Source of the uniform numbers that place a pixel sample: its position in the pixel, on the lens,
+// in time, and the directions chosen at each bounce. Each value belongs to a dimension, counted from
+// the start of the sample, and well-distributed samplers spread the values of one dimension evenly
+// over the samples of a pixel rather than leaving them to chance
+pub trait Sampler {
+    // Moves to sample `index` of the pixel, restarting at the first dimension
+    fn start_sample(&mut self, index: u64);
+    // Range [0, 1)
+    fn get_1d(&mut self) -> f64;
+    // Range [0, 1) in both coordinates
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Clone, Copy)]
+pub enum Type {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl Type {
+    pub const ALL: [Type; 4] = [
+        Type::Independent,
+        Type::Stratified,
+        Type::Halton,
+        Type::Sobol,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Type::Independent => "independent",
+            Type::Stratified => "stratified",
+            Type::Halton => "halton",
+            Type::Sobol => "sobol",
+        }
+    }
+
+    // Sampler for one pixel. The stratified sampler spreads each round of `strata` samples over as
+    // many strata
+    pub fn create(self, seed: u64, pixel: u64, strata: u64) -> Box<dyn Sampler> {
+        let seed = hash(seed, pixel);
+        match self {
+            Type::Independent => Box::new(independent::Independent::new(seed)),
+            Type::Stratified => Box::new(stratified::Stratified::new(seed, strata)),
+            Type::Halton => Box::new(halton::Halton::new(seed)),
+            Type::Sobol => Box::new(sobol::Sobol::new(seed)),
+        }
+    }
+}
+
+impl FromStr for Type {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Type, ()> {
+        let name = s.to_ascii_lowercase();
+        Type::ALL.into_iter().find(|t| t.name() == name).ok_or(())
+    }
+}
+
+fn hash(a: u64, b: u64) -> u64 {
+    utils::mix(a ^ utils::mix(b))
+}
+
+// Top 53 bits of a hash as a float in [0, 1)
+fn to_float(bits: u64) -> f64 {
+    #[allow(clippy::cast_precision_loss)] // 53 bits fit exactly
+    let value = (bits >> 11) as f64;
+    value / 9_007_199_254_740_992.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The first `dimensions` 2D values of each of the first `count` samples
+    fn values(sampler: &mut dyn Sampler, count: u64, dimensions: usize) -> Vec<(f64, f64)> {
+        let mut values = Vec::new();
+        for index in 0..count {
+            sampler.start_sample(index);
+            for _ in 0..dimensions {
+                values.push(sampler.get_2d());
+            }
+        }
+        values
+    }
+
+    #[test]
+    fn deterministic() {
+        for kind in Type::ALL {
+            let first = values(kind.create(7, 42, 16).as_mut(), 64, 40);
+            let second = values(kind.create(7, 42, 16).as_mut(), 64, 40);
+            assert_eq!(first, second, "{}", kind.name());
+
+            let other_pixel = values(kind.create(7, 43, 16).as_mut(), 64, 40);
+            let other_seed = values(kind.create(8, 42, 16).as_mut(), 64, 40);
+            assert_ne!(first, other_pixel, "{}", kind.name());
+            assert_ne!(first, other_seed, "{}", kind.name());
+
+            assert!(
+                first
+                    .iter()
+                    .all(|&(x, y)| (0.0..1.0).contains(&x) && (0.0..1.0).contains(&y)),
+                "{}",
+                kind.name()
+            );
+        }
+    }
+
+    #[test]
+    fn samples_do_not_depend_on_the_order_they_are_taken() {
+        for kind in Type::ALL {
+            let mut sampler = kind.create(3, 5, 16);
+            let all = values(sampler.as_mut(), 32, 3);
+
+            // Sample 20 alone, as a resumed render would take it
+            sampler.start_sample(20);
+            let resumed: Vec<_> = (0..3).map(|_| sampler.get_2d()).collect();
+            assert_eq!(resumed, all[60..63], "{}", kind.name());
+        }
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for kind in Type::ALL {
+            assert_eq!(kind.name().parse::<Type>().map(Type::name), Ok(kind.name()));
+        }
+        assert_eq!("SOBOL".parse::<Type>().map(Type::name), Ok("sobol"));
+        assert!("random".parse::<Type>().is_err());
+    }
+}
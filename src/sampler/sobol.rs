@@ -0,0 +1,155 @@
+use super::{hash, Sampler};
+
+// Direction numbers of the second Sobol dimension; the first is the bit-reversed index
+const DIRECTIONS: [u32; 32] = sobol_directions();
+
+// Owen-scrambled Sobol points, following Burley, "Practical Hash-based Owen Scrambling". Every
+// pair of dimensions is drawn from the first two Sobol dimensions with its own scramble and its own
+// shuffle of the sample order, which keeps each pair well stratified while pairs stay independent.
+// Any number of samples is well spread, and best at powers of two
+pub struct Sobol {
+    seed: u64,
+    sample: u32,
+    dimension: u64,
+}
+
+impl Sobol {
+    pub fn new(seed: u64) -> Sobol {
+        Sobol {
+            seed,
+            sample: 0,
+            dimension: 0,
+        }
+    }
+
+    fn next_seed(&mut self) -> u32 {
+        #[allow(clippy::cast_possible_truncation)] // Only 32 bits of the hash are needed
+        let seed = hash(self.seed, self.dimension) as u32;
+        self.dimension += 1;
+        seed
+    }
+}
+
+impl Sampler for Sobol {
+    fn start_sample(&mut self, index: u64) {
+        // The sequence repeats after 2^32 samples, far more than a pixel takes
+        #[allow(clippy::cast_possible_truncation)]
+        let index = index as u32;
+        self.sample = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let seed = self.next_seed();
+        let index = nested_uniform_scramble(self.sample, seed);
+        to_float(nested_uniform_scramble(
+            index.reverse_bits(),
+            hash32(seed, 0),
+        ))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let seed = self.next_seed();
+        let index = nested_uniform_scramble(self.sample, seed);
+        (
+            to_float(nested_uniform_scramble(
+                index.reverse_bits(),
+                hash32(seed, 0),
+            )),
+            to_float(nested_uniform_scramble(sobol(index), hash32(seed, 1))),
+        )
+    }
+}
+
+fn sobol(index: u32) -> u32 {
+    let mut result = 0;
+    for (bit, direction) in DIRECTIONS.iter().enumerate() {
+        if index >> bit & 1 == 1 {
+            result ^= direction;
+        }
+    }
+    result
+}
+
+// Primitive polynomial x + 1 with initial direction number 1
+const fn sobol_directions() -> [u32; 32] {
+    let mut directions = [0; 32];
+    directions[0] = 1 << 31;
+    let mut i = 1;
+    while i < 32 {
+        directions[i] = directions[i - 1] ^ (directions[i - 1] >> 1);
+        i += 1;
+    }
+    directions
+}
+
+// Randomly flips each bit depending on the bits above it, as an Owen scramble does
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+// Each bit of the result depends only on the same and lower bits of the input
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+fn hash32(seed: u32, i: u64) -> u32 {
+    #[allow(clippy::cast_possible_truncation)] // Only 32 bits of the hash are needed
+    let value = hash(u64::from(seed), i) as u32;
+    value
+}
+
+fn to_float(bits: u32) -> f64 {
+    f64::from(bits) * (1.0 / 4_294_967_296.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Cell of a `cells` by `cells` grid that a 2D value falls in
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // Values are in [0, 1)
+    fn cell((x, y): (f64, f64), cells: usize) -> usize {
+        let scale = f64::from(u32::try_from(cells).unwrap());
+        (y * scale) as usize * cells + (x * scale) as usize
+    }
+
+    #[test]
+    fn unscrambled_points() {
+        // The first dimension is the van der Corput sequence and the second starts the same way
+        let first: Vec<u32> = (0..4).map(|i: u32| i.reverse_bits()).collect();
+        assert_eq!(first, [0, 1 << 31, 1 << 30, 3 << 30]);
+        let second: Vec<u32> = (0..4).map(sobol).collect();
+        assert_eq!(second, [0, 1 << 31, 3 << 30, 1 << 30]);
+    }
+
+    #[test]
+    fn power_of_two_counts_are_stratified() {
+        for seed in 0..8 {
+            let mut sampler = Sobol::new(seed);
+            // Each pair of dimensions puts one of 16 samples in every cell of a 4 by 4 grid, and
+            // one of 64 in every cell of an 8 by 8 grid
+            for (samples, cells) in [(16, 4), (64, 8)] {
+                for dimension in 0..4 {
+                    let mut seen = vec![false; cells * cells];
+                    for index in 0..samples {
+                        sampler.start_sample(index);
+                        for _ in 0..dimension {
+                            sampler.get_2d();
+                        }
+                        seen[cell(sampler.get_2d(), cells)] = true;
+                    }
+                    assert!(
+                        seen.iter().all(|&s| s),
+                        "seed {seed}, dimension {dimension}"
+                    );
+                }
+            }
+        }
+    }
+}
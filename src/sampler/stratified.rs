@@ -0,0 +1,151 @@
+use super::{hash, to_float, Sampler};
+
+// Divides each dimension into strata and puts one jittered sample in each, a round of samples at
+// a time. Two-dimensional values use a grid of strata as close to square as their count allows.
+// Which sample lands in which stratum is shuffled per dimension and round so that dimensions are
+// not correlated. The number of strata is fixed rather than taken from the pixel's final sample
+// count, so a sample lands in the same place however many more follow it
+pub struct Stratified {
+    seed: u64,
+    strata: u64,
+    // Grid of strata for two-dimensional values, columns by rows
+    grid: (u64, u64),
+    sample: u64,
+    dimension: u64,
+}
+
+impl Stratified {
+    pub fn new(seed: u64, strata: u64) -> Stratified {
+        let strata = strata.max(1);
+        // The largest divisor no greater than the square root gives the squarest grid
+        let columns = (1..=strata)
+            .take_while(|c| c * c <= strata)
+            .filter(|c| strata.is_multiple_of(*c))
+            .last()
+            .unwrap_or(1);
+
+        Stratified {
+            seed,
+            strata,
+            grid: (columns, strata / columns),
+            sample: 0,
+            dimension: 0,
+        }
+    }
+
+    // Stratum of the current sample and the hash used to jitter within it
+    fn stratum(&mut self) -> (u64, u64) {
+        let round = self.sample / self.strata;
+        let seed = hash(hash(self.seed, self.dimension), round);
+        let stratum = permute(self.sample % self.strata, self.strata, seed);
+        self.dimension += 1;
+        (stratum, hash(seed, self.sample))
+    }
+}
+
+impl Sampler for Stratified {
+    fn start_sample(&mut self, index: u64) {
+        self.sample = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (stratum, jitter) = self.stratum();
+        (as_float(stratum) + to_float(jitter)) / as_float(self.strata)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (stratum, jitter) = self.stratum();
+        let (columns, rows) = self.grid;
+        let x = (as_float(stratum % columns) + to_float(jitter)) / as_float(columns);
+        let y = (as_float(stratum / columns) + to_float(hash(jitter, 1))) / as_float(rows);
+        (x, y)
+    }
+}
+
+fn as_float(value: u64) -> f64 {
+    #[allow(clippy::cast_precision_loss)] // Sample counts are far below 2^52
+    let value = value as f64;
+    value
+}
+
+// Element `index` of a random permutation of [0, length) chosen by `seed`, without building the
+// permutation (Kensler, "Correlated Multi-Jittered Sampling")
+fn permute(index: u64, length: u64, seed: u64) -> u64 {
+    #[allow(clippy::cast_possible_truncation)] // Only the low 32 bits of the seed are used
+    let p = seed as u32;
+    let (Ok(mut i), Ok(l)) = (u32::try_from(index), u32::try_from(length)) else {
+        return index % length;
+    };
+
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    // Permuting within the next power of two and skipping values past the end stays a permutation
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+
+    u64::from(i.wrapping_add(p) % l)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permute_is_a_permutation() {
+        for length in [1, 2, 7, 16, 100] {
+            for seed in 0..4 {
+                let mut seen = vec![false; usize::try_from(length).unwrap()];
+                for index in 0..length {
+                    seen[usize::try_from(permute(index, length, seed)).unwrap()] = true;
+                }
+                assert!(seen.iter().all(|&s| s), "length {length}, seed {seed}");
+            }
+        }
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // Values are in [0, 1)
+    fn every_round_fills_every_stratum() {
+        let mut sampler = Stratified::new(9, 12);
+        assert_eq!(sampler.grid, (3, 4));
+        for round in 0..3 {
+            let mut seen_1d = [false; 12];
+            let mut seen_2d = [false; 12];
+            for index in round * 12..(round + 1) * 12 {
+                sampler.start_sample(index);
+                seen_1d[(sampler.get_1d() * 12.0) as usize] = true;
+                let (x, y) = sampler.get_2d();
+                seen_2d[(y * 4.0) as usize * 3 + (x * 3.0) as usize] = true;
+            }
+            assert!(seen_1d.iter().all(|&s| s), "round {round}");
+            assert!(seen_2d.iter().all(|&s| s), "round {round}");
+        }
+    }
+}
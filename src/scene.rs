@@ -1,10 +1,15 @@
+use std::str::FromStr;
 use std::sync::Arc;
 
 use crate::camera::Camera;
+use crate::codec;
+use crate::environment::uniform::Uniform;
+use crate::environment::Environment;
 use crate::hittable::b0x::B0x;
-use crate::hittable::bvh_node::BVHNode;
+use crate::hittable::bvh_node::{BVHNode, Stats};
 use crate::hittable::constant_medium::ConstantMedium;
 use crate::hittable::htlist::HittableList;
+use crate::hittable::linear_bvh::LinearBVH;
 use crate::hittable::moving_sphere::MovingSphere;
 use crate::hittable::rotate_y::RotateY;
 use crate::hittable::sphere::Sphere;
@@ -12,15 +17,21 @@ use crate::hittable::translate::Translate;
 use crate::hittable::xyrect::XYRect;
 use crate::hittable::xzrect::XZRect;
 use crate::hittable::yzrect::YZRect;
+use crate::hittable::Hittable;
+use crate::light::Light;
 use crate::material::diffuse_light::DiffuseLight;
-use crate::material::{dielectric::Dielectric, lambertian::Lambertian, metal::Metal};
+use crate::material::{
+    dielectric::Dielectric, isotropic::Isotropic, lambertian::Lambertian, metal::Metal, MaterialIds,
+};
+use crate::ray::Ray;
+use crate::sampler::{self, Sampler};
 use crate::texture::checker::Checker;
-use crate::texture::image::Image;
+use crate::texture::image::{Image, Wrap};
 use crate::texture::noise::Noise;
-use crate::utils;
+use crate::tonemap::{self, ToneMap};
+use crate::utils::{self, Rng};
 use crate::vec3::{Axis, Color, Point3, Vec3};
 
-#[allow(dead_code)]
 pub enum Type {
     Random,
     RandomMod,
@@ -33,21 +44,260 @@ pub enum Type {
     Final,
 }
 
+impl Type {
+    pub const ALL: [Type; 9] = [
+        Type::Random,
+        Type::RandomMod,
+        Type::TwoSpheres,
+        Type::TwoPerlinSpheres,
+        Type::Earth,
+        Type::SimpleLight,
+        Type::CornellBox,
+        Type::CornellSmoke,
+        Type::Final,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Type::Random => "random",
+            Type::RandomMod => "random-mod",
+            Type::TwoSpheres => "two-spheres",
+            Type::TwoPerlinSpheres => "two-perlin-spheres",
+            Type::Earth => "earth",
+            Type::SimpleLight => "simple-light",
+            Type::CornellBox => "cornell-box",
+            Type::CornellSmoke => "cornell-smoke",
+            Type::Final => "final",
+        }
+    }
+}
+
+impl FromStr for Type {
+    type Err = ();
+
+    // Scene names are matched case-insensitively and accept '_' in place of '-'
+    fn from_str(s: &str) -> Result<Type, ()> {
+        let name = s.to_ascii_lowercase().replace('_', "-");
+        Type::ALL.into_iter().find(|t| t.name() == name).ok_or(())
+    }
+}
+
+// Render settings that take precedence over the values baked into each scene
+#[derive(Clone, Copy, Default)]
+pub struct Overrides {
+    pub image_width: Option<i32>,
+    pub samples_per_pixel: Option<i32>,
+    pub max_depth: Option<i32>,
+    pub aspect_ratio: Option<f64>,
+    pub seed: Option<u64>,
+    pub sampler: Option<sampler::Type>,
+    pub noise_threshold: Option<f64>,
+    pub tone_map: Option<tonemap::Operator>,
+    pub exposure: Option<f64>,
+}
+
+// Camera and render settings that describe how a scene is viewed
+pub struct Settings {
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub vup: Vec3,
+    pub vfov: f64,
+    pub aperture: f64,
+    pub focus_dist: f64,
+    pub time_start: f64,
+    pub time_end: f64,
+    pub background: Color,
+    // Takes the place of the background color when set
+    pub environment: Option<Arc<dyn Environment>>,
+    pub aspect_ratio: f64,
+    pub image_width: i32,
+    pub samples_per_pixel: i32,
+    pub max_depth: i32,
+    // Renders with the same seed and settings are identical
+    pub seed: u64,
+    pub sampler: sampler::Type,
+    // Adaptive sampling stops a pixel once its relative standard error is below this; 0 disables it
+    pub noise_threshold: f64,
+    pub tone_map: ToneMap,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            look_from: Point3::new(0.0, 0.0, -1.0),
+            look_at: Point3::default(),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 40.0,
+            aperture: 0.0,
+            focus_dist: 10.0,
+            time_start: 0.0,
+            time_end: 1.0,
+            background: Color::default(),
+            environment: None,
+            aspect_ratio: 1.0,
+            image_width: 400,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            seed: 0,
+            sampler: sampler::Type::Sobol,
+            noise_threshold: 0.0,
+            tone_map: ToneMap::default(),
+        }
+    }
+}
+
 pub struct Scene {
     pub world: HittableList,
+    // Emitters that scattered rays are steered towards; the same objects must also be in `world`
+    pub lights: HittableList,
+    // Point, spot and directional lights, which only shadow rays reach
+    pub delta_lights: Vec<Box<dyn Light>>,
+    // Statistics of the BVHs built for the scene, in the order they were built
+    pub bvh_stats: Vec<Stats>,
     pub cam: Camera,
-    pub background: Color,
-    pub aspect_ratio: f64,
+    pub environment: Arc<dyn Environment>,
     pub image_width: i32,
     pub image_height: i32,
     pub samples_per_pixel: i32,
     pub max_depth: i32,
-    pub scale: f64,
+    pub seed: u64,
+    pub sampler: sampler::Type,
+    pub noise_threshold: f64,
+    // Only applies to 8-bit output; HDR formats keep the radiance
+    pub tone_map: ToneMap,
+}
+
+impl Scene {
+    pub fn new(
+        world: HittableList,
+        lights: HittableList,
+        delta_lights: Vec<Box<dyn Light>>,
+        bvh_stats: Vec<Stats>,
+        settings: &Settings,
+        overrides: &Overrides,
+    ) -> Scene {
+        let aspect_ratio = overrides.aspect_ratio.unwrap_or(settings.aspect_ratio);
+        let image_width = overrides.image_width.unwrap_or(settings.image_width);
+        let samples_per_pixel = overrides
+            .samples_per_pixel
+            .unwrap_or(settings.samples_per_pixel);
+        let max_depth = overrides.max_depth.unwrap_or(settings.max_depth);
+        let seed = overrides.seed.unwrap_or(settings.seed);
+        let sampler = overrides.sampler.unwrap_or(settings.sampler);
+        let noise_threshold = overrides
+            .noise_threshold
+            .unwrap_or(settings.noise_threshold);
+        let tone_map = settings
+            .tone_map
+            .with(overrides.tone_map, overrides.exposure);
+
+        let cam = Camera::new(
+            settings.look_from,
+            settings.look_at,
+            settings.vup,
+            settings.vfov,
+            aspect_ratio,
+            settings.aperture,
+            settings.focus_dist,
+            settings.time_start,
+            settings.time_end,
+        );
+
+        Scene {
+            world,
+            lights,
+            delta_lights,
+            bvh_stats,
+            cam,
+            environment: match &settings.environment {
+                Some(environment) => environment.clone(),
+                None => Arc::new(Uniform::new(settings.background)),
+            },
+            image_width,
+            image_height: utils::float_to_int_truncate(f64::from(image_width) / aspect_ratio),
+            samples_per_pixel,
+            max_depth,
+            seed,
+            sampler,
+            noise_threshold,
+            tone_map,
+        }
+    }
+
+    // Number of the pixel `i` columns from the left and `j` rows from the bottom, which seeds its
+    // samples
+    pub fn pixel_index(&self, i: usize, j: usize) -> u64 {
+        u64::try_from(j).expect("Pixel index")
+            * u64::try_from(self.image_width).expect("Image dimensions are positive")
+            + u64::try_from(i).expect("Pixel index")
+    }
+
+    // Ray through a point of pixel (i, j) chosen by the sampler, which also places it on the lens
+    // and in time
+    pub fn camera_ray(&self, i: usize, j: usize, sampler: &mut dyn Sampler) -> Ray {
+        #[allow(clippy::cast_precision_loss)] // Image dimensions are far below 2^52
+        let position = (i as f64, j as f64);
+        let (du, dv) = sampler.get_2d();
+        let u = (position.0 + du) / f64::from(self.image_width - 1);
+        let v = (position.1 + dv) / f64::from(self.image_height - 1);
+        self.cam.get_ray(u, v, sampler)
+    }
+}
+
+// Acceleration structure built over groups of objects
+#[derive(Clone, Copy)]
+pub enum Bvh {
+    Recursive,
+    Linear,
+}
+
+impl Bvh {
+    pub const ALL: [Bvh; 2] = [Bvh::Recursive, Bvh::Linear];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Bvh::Recursive => "recursive",
+            Bvh::Linear => "linear",
+        }
+    }
+
+    // Builds the tree, adding its statistics to `stats`
+    fn build(
+        self,
+        list: &HittableList,
+        time0: f64,
+        time1: f64,
+        stats: &mut Vec<Stats>,
+    ) -> Box<dyn Hittable> {
+        let (bvh, tree_stats): (Box<dyn Hittable>, Stats) = match self {
+            Bvh::Recursive => {
+                let (node, tree_stats) = BVHNode::build(&list.objects, time0, time1);
+                (Box::new(node), tree_stats)
+            }
+            Bvh::Linear => {
+                let (bvh, tree_stats) = LinearBVH::new_from_list(list, time0, time1);
+                (Box::new(bvh), tree_stats)
+            }
+        };
+        stats.push(tree_stats);
+        bvh
+    }
+}
+
+pub fn get(scene: &Type, overrides: &Overrides) -> Result<Scene, codec::Error> {
+    get_with_bvh(scene, overrides, Bvh::Linear)
 }
 
 #[allow(clippy::too_many_lines)]
-pub fn get(scene: &Type) -> Scene {
+pub fn get_with_bvh(scene: &Type, overrides: &Overrides, bvh: Bvh) -> Result<Scene, codec::Error> {
+    // Built-in scenes keep the default seed, so only an override changes their random layout
+    let seed = overrides.seed.unwrap_or(Settings::default().seed);
+    let mut rng = utils::scene_rng(seed);
+
     let world;
+    let mut lights = HittableList::default();
+    let mut bvh_stats = Vec::new();
+    let mut tone_map = ToneMap::default();
     let look_from;
     let look_at;
     let vfov;
@@ -60,7 +310,7 @@ pub fn get(scene: &Type) -> Scene {
 
     match scene {
         Type::Random => {
-            world = random_scene();
+            world = random_scene(bvh, &mut rng, &mut bvh_stats);
             look_from = Point3::new(13.0, 2.0, 3.0);
             look_at = Point3::default();
             vfov = 20.0;
@@ -72,7 +322,7 @@ pub fn get(scene: &Type) -> Scene {
             max_depth = 50;
         }
         Type::RandomMod => {
-            world = random_scene_mod();
+            world = random_scene_mod(bvh, &mut rng, &mut bvh_stats);
             look_from = Point3::new(13.0, 2.0, 3.0);
             look_at = Point3::default();
             vfov = 20.0;
@@ -96,7 +346,7 @@ pub fn get(scene: &Type) -> Scene {
             max_depth = 50;
         }
         Type::TwoPerlinSpheres => {
-            world = two_perlin_spheres();
+            world = two_perlin_spheres(&mut rng);
             look_from = Point3::new(13.0, 2.0, 3.0);
             look_at = Point3::default();
             vfov = 20.0;
@@ -108,7 +358,7 @@ pub fn get(scene: &Type) -> Scene {
             max_depth = 50;
         }
         Type::Earth => {
-            world = earth();
+            world = earth()?;
             look_from = Point3::new(13.0, 2.0, 3.0);
             look_at = Point3::default();
             vfov = 20.0;
@@ -120,7 +370,13 @@ pub fn get(scene: &Type) -> Scene {
             max_depth = 50;
         }
         Type::SimpleLight => {
-            world = simple_light();
+            (world, lights) = simple_light(&mut rng);
+            // The light is several times brighter than white, which clamping blows out, while the
+            // rest of the scene is dim
+            tone_map = ToneMap {
+                operator: tonemap::Operator::Aces,
+                exposure: 1.0,
+            };
             background = Color::default();
             look_from = Point3::new(26.0, 3.0, 6.0);
             look_at = Point3::new(0.0, 2.0, 0.0);
@@ -132,7 +388,7 @@ pub fn get(scene: &Type) -> Scene {
             max_depth = 50;
         }
         Type::CornellBox => {
-            world = cornell_box();
+            (world, lights) = cornell_box();
             background = Color::default();
             look_from = Point3::new(278.0, 278.0, -800.0);
             look_at = Point3::new(278.0, 278.0, 0.0);
@@ -144,7 +400,7 @@ pub fn get(scene: &Type) -> Scene {
             max_depth = 50;
         }
         Type::CornellSmoke => {
-            world = cornell_smoke();
+            (world, lights) = cornell_smoke();
             background = Color::default();
             look_from = Point3::new(278.0, 278.0, -800.0);
             look_at = Point3::new(278.0, 278.0, 0.0);
@@ -156,7 +412,7 @@ pub fn get(scene: &Type) -> Scene {
             max_depth = 50;
         }
         Type::Final => {
-            world = final_scene();
+            (world, lights) = final_scene(bvh, &mut rng, &mut bvh_stats)?;
             background = Color::default();
             look_from = Point3::new(478.0, 278.0, -600.0);
             look_at = Point3::new(278.0, 278.0, 0.0);
@@ -169,101 +425,96 @@ pub fn get(scene: &Type) -> Scene {
         }
     }
 
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-    let dist_to_focus = 10.0;
-    let time_start = 0.0;
-    let time_end = 1.0;
-    let cam = Camera::new(
-        look_from,
-        look_at,
-        vup,
-        vfov,
-        aspect_ratio,
-        aperture,
-        dist_to_focus,
-        time_start,
-        time_end,
-    );
-
-    Scene {
+    Ok(Scene::new(
         world,
-        cam,
-        background,
-        aspect_ratio,
-        image_width,
-        image_height: utils::float_to_int_truncate(f64::from(image_width) / aspect_ratio),
-        samples_per_pixel,
-        max_depth,
-        scale: 1.0 / f64::from(samples_per_pixel),
-    }
+        lights,
+        Vec::new(),
+        bvh_stats,
+        &Settings {
+            look_from,
+            look_at,
+            vfov,
+            aperture,
+            background,
+            aspect_ratio,
+            image_width,
+            samples_per_pixel,
+            max_depth,
+            tone_map,
+            ..Settings::default()
+        },
+        overrides,
+    ))
 }
 
-fn random_scene() -> HittableList {
+fn random_scene(bvh: Bvh, rng: &mut Rng, bvh_stats: &mut Vec<Stats>) -> HittableList {
+    let mut ids = MaterialIds::default();
     let mut world = HittableList::single(Arc::new(Sphere::new(
         Point3::new(0.0, -1000.0, 0.0),
         1000.0,
-        Arc::new(Lambertian::new(Color::new_single(0.5))),
+        ids.add(Lambertian::new(Color::new_single(0.5))),
     )));
 
     for a in -11..11 {
         for b in -11..11 {
-            let choose_mat = utils::random_float();
+            let choose_mat = utils::random_float(rng);
             let center = Point3::new(
-                f64::from(a) + 0.9 * utils::random_float(),
+                f64::from(a) + 0.9 * utils::random_float(rng),
                 0.2,
-                f64::from(b) + 0.9 * utils::random_float(),
+                f64::from(b) + 0.9 * utils::random_float(rng),
             );
 
             if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
                 if choose_mat < 0.8 {
                     // diffuse
-                    let mat = Arc::new(Lambertian::new(Color::random() * Color::random()));
+                    let mat = ids.add(Lambertian::new(Color::random(rng) * Color::random(rng)));
                     world.add(Arc::new(Sphere::new(center, 0.2, mat)));
                 } else if choose_mat < 0.95 {
                     // metal
-                    let mat = Arc::new(Metal::new(
-                        Color::random_range(0.5, 1.0),
-                        utils::random_float_range(0.0, 0.5),
+                    let mat = ids.add(Metal::new(
+                        Color::random_range(rng, 0.5, 1.0),
+                        utils::random_float_range(rng, 0.0, 0.5),
                     ));
                     world.add(Arc::new(Sphere::new(center, 0.2, mat)));
                 } else {
                     // glass
-                    let mat = Arc::new(Dielectric::new(1.5));
+                    let mat = ids.add(Dielectric::new(1.5));
                     world.add(Arc::new(Sphere::new(center, 0.2, mat)));
                 }
             }
         }
     }
 
-    let material1 = Arc::new(Dielectric::new(1.5));
+    let material1 = ids.add(Dielectric::new(1.5));
     world.add(Arc::new(Sphere::new(
         Point3::new(0.0, 1.0, 0.0),
         1.0,
         material1,
     )));
 
-    let material2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
+    let material2 = ids.add(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
     world.add(Arc::new(Sphere::new(
         Point3::new(-4.0, 1.0, 0.0),
         1.0,
         material2,
     )));
 
-    let material3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+    let material3 = ids.add(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
     world.add(Arc::new(Sphere::new(
         Point3::new(4.0, 1.0, 0.0),
         1.0,
         material3,
     )));
 
-    HittableList::single(Arc::new(BVHNode::new_from_list(&world, 0.0, 1.0)))
+    HittableList::single(Arc::from(bvh.build(&world, 0.0, 1.0, bvh_stats)))
 }
 
-fn random_scene_mod() -> HittableList {
+fn random_scene_mod(bvh: Bvh, rng: &mut Rng, bvh_stats: &mut Vec<Stats>) -> HittableList {
+    let mut ids = MaterialIds::default();
     let mut world = HittableList::single(Arc::new(Sphere::new(
         Point3::new(0.0, -1000.0, 0.0),
         1000.0,
-        Arc::new(Lambertian::new_with_texture(Box::new(Checker::new(
+        ids.add(Lambertian::new_with_texture(Box::new(Checker::new(
             Color::new(0.2, 0.3, 0.1),
             Color::new(0.9, 0.9, 0.9),
         )))),
@@ -271,67 +522,69 @@ fn random_scene_mod() -> HittableList {
 
     for a in -11..11 {
         for b in -11..11 {
-            let choose_mat = utils::random_float();
+            let choose_mat = utils::random_float(rng);
             let center = Point3::new(
-                f64::from(a) + 0.9 * utils::random_float(),
+                f64::from(a) + 0.9 * utils::random_float(rng),
                 0.2,
-                f64::from(b) + 0.9 * utils::random_float(),
+                f64::from(b) + 0.9 * utils::random_float(rng),
             );
 
             if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
                 if choose_mat < 0.8 {
                     // diffuse
-                    let center2 = center + Vec3::new(0.0, utils::random_float_range(0.0, 0.5), 0.0);
-                    let mat = Arc::new(Lambertian::new(Color::random() * Color::random()));
+                    let center2 =
+                        center + Vec3::new(0.0, utils::random_float_range(rng, 0.0, 0.5), 0.0);
+                    let mat = ids.add(Lambertian::new(Color::random(rng) * Color::random(rng)));
                     world.add(Arc::new(MovingSphere::new(
                         center, center2, 0.0, 1.0, 0.2, mat,
                     )));
                 } else if choose_mat < 0.95 {
                     // metal
-                    let mat = Arc::new(Metal::new(
-                        Color::random_range(0.5, 1.0),
-                        utils::random_float_range(0.0, 0.5),
+                    let mat = ids.add(Metal::new(
+                        Color::random_range(rng, 0.5, 1.0),
+                        utils::random_float_range(rng, 0.0, 0.5),
                     ));
                     world.add(Arc::new(Sphere::new(center, 0.2, mat)));
                 } else {
                     // glass
-                    let mat = Arc::new(Dielectric::new(1.5));
+                    let mat = ids.add(Dielectric::new(1.5));
                     world.add(Arc::new(Sphere::new(center, 0.2, mat)));
                 }
             }
         }
     }
 
-    let material1 = Arc::new(Dielectric::new(1.5));
+    let material1 = ids.add(Dielectric::new(1.5));
     world.add(Arc::new(Sphere::new(
         Point3::new(0.0, 1.0, 0.0),
         1.0,
         material1,
     )));
 
-    let material2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
+    let material2 = ids.add(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
     world.add(Arc::new(Sphere::new(
         Point3::new(-4.0, 1.0, 0.0),
         1.0,
         material2,
     )));
 
-    let material3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+    let material3 = ids.add(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
     world.add(Arc::new(Sphere::new(
         Point3::new(4.0, 1.0, 0.0),
         1.0,
         material3,
     )));
 
-    HittableList::single(Arc::new(BVHNode::new_from_list(&world, 0.0, 1.0)))
+    HittableList::single(Arc::from(bvh.build(&world, 0.0, 1.0, bvh_stats)))
 }
 
 fn two_spheres() -> HittableList {
+    let mut ids = MaterialIds::default();
     let checker = Box::new(Checker::new(
         Color::new(0.2, 0.3, 0.1),
         Color::new(0.9, 0.9, 0.9),
     ));
-    let mat = Arc::new(Lambertian::new_with_texture(checker));
+    let mat = ids.add(Lambertian::new_with_texture(checker));
 
     HittableList::new(&[
         Arc::new(Sphere::new(Point3::new(0.0, -10.0, 0.0), 10.0, mat.clone())),
@@ -339,9 +592,10 @@ fn two_spheres() -> HittableList {
     ])
 }
 
-fn two_perlin_spheres() -> HittableList {
-    let perlin = Box::new(Noise::new(4.0, Axis::Z));
-    let mat = Arc::new(Lambertian::new_with_texture(perlin));
+fn two_perlin_spheres(rng: &mut Rng) -> HittableList {
+    let mut ids = MaterialIds::default();
+    let perlin = Box::new(Noise::new(4.0, Axis::Z, rng));
+    let mat = ids.add(Lambertian::new_with_texture(perlin));
 
     HittableList::new(&[
         Arc::new(Sphere::new(
@@ -353,46 +607,65 @@ fn two_perlin_spheres() -> HittableList {
     ])
 }
 
-fn earth() -> HittableList {
-    let earth_texture = Box::new(Image::new("resources/earthmap.jpg"));
-    let earth_surface = Arc::new(Lambertian::new_with_texture(earth_texture));
+fn earth() -> Result<HittableList, codec::Error> {
+    let mut ids = MaterialIds::default();
+    // Longitude runs all the way around the globe, so the map repeats across its seam
+    let earth_texture = Box::new(Image::new_with_wrap(
+        "resources/earthmap.jpg",
+        Wrap::Repeat,
+    )?);
+    let earth_surface = ids.add(Lambertian::new_with_texture(earth_texture));
     let globe = Arc::new(Sphere::new(Point3::default(), 2.0, earth_surface));
 
-    HittableList::single(globe)
+    Ok(HittableList::single(globe))
 }
 
-fn simple_light() -> HittableList {
-    let perlin = Box::new(Noise::new(4.0, Axis::Z));
-    let mat = Arc::new(Lambertian::new_with_texture(perlin));
+// Returns the world and the lights in it
+fn simple_light(rng: &mut Rng) -> (HittableList, HittableList) {
+    let mut ids = MaterialIds::default();
+    let perlin = Box::new(Noise::new(4.0, Axis::Z, rng));
+    let mat = ids.add(Lambertian::new_with_texture(perlin));
+    let light: Arc<dyn Hittable> = Arc::new(XYRect::new(
+        3.0,
+        5.0,
+        1.0,
+        3.0,
+        -2.0,
+        ids.add(DiffuseLight::new(Color::new(4.0, 4.0, 4.0))),
+    ));
 
-    HittableList::new(&[
+    let world = HittableList::new(&[
         Arc::new(Sphere::new(
             Point3::new(0.0, -1000.0, 0.0),
             1000.0,
             mat.clone(),
         )),
         Arc::new(Sphere::new(Point3::new(0.0, 2.0, 0.0), 2.0, mat)),
-        Arc::new(XYRect::new(
-            3.0,
-            5.0,
-            1.0,
-            3.0,
-            -2.0,
-            Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0))),
-        )),
-    ])
+        light.clone(),
+    ]);
+
+    (world, HittableList::single(light))
 }
 
-fn cornell_box() -> HittableList {
-    let red = Arc::new(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
-    let white = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
-    let green = Arc::new(Lambertian::new(Color::new(0.12, 0.45, 0.15)));
-    let light = Arc::new(DiffuseLight::new(Color::new(15.0, 15.0, 15.0)));
+// Returns the world and the lights in it
+fn cornell_box() -> (HittableList, HittableList) {
+    let mut ids = MaterialIds::default();
+    let red = ids.add(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
+    let white = ids.add(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let green = ids.add(Lambertian::new(Color::new(0.12, 0.45, 0.15)));
+    let light: Arc<dyn Hittable> = Arc::new(XZRect::new(
+        213.0,
+        343.0,
+        227.0,
+        332.0,
+        554.0,
+        ids.add(DiffuseLight::new(Color::new(15.0, 15.0, 15.0))),
+    ));
 
-    HittableList::new(&[
+    let world = HittableList::new(&[
         Arc::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, green)),
         Arc::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, red)),
-        Arc::new(XZRect::new(213.0, 343.0, 227.0, 332.0, 554.0, light)),
+        light.clone(),
         Arc::new(XZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, white.clone())),
         Arc::new(XZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white.clone())),
         Arc::new(XYRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white.clone())),
@@ -418,19 +691,30 @@ fn cornell_box() -> HittableList {
             )),
             Vec3::new(130.0, 0.0, 65.0),
         )),
-    ])
+    ]);
+
+    (world, HittableList::single(light))
 }
 
-fn cornell_smoke() -> HittableList {
-    let red = Arc::new(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
-    let white = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
-    let green = Arc::new(Lambertian::new(Color::new(0.12, 0.45, 0.15)));
-    let light = Arc::new(DiffuseLight::new(Color::new(7.0, 7.0, 7.0)));
+// Returns the world and the lights in it
+fn cornell_smoke() -> (HittableList, HittableList) {
+    let mut ids = MaterialIds::default();
+    let red = ids.add(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
+    let white = ids.add(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let green = ids.add(Lambertian::new(Color::new(0.12, 0.45, 0.15)));
+    let light: Arc<dyn Hittable> = Arc::new(XZRect::new(
+        113.0,
+        443.0,
+        127.0,
+        432.0,
+        554.0,
+        ids.add(DiffuseLight::new(Color::new(7.0, 7.0, 7.0))),
+    ));
 
-    HittableList::new(&[
+    let world = HittableList::new(&[
         Arc::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, green)),
         Arc::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, red)),
-        Arc::new(XZRect::new(113.0, 443.0, 127.0, 432.0, 554.0, light)),
+        light.clone(),
         Arc::new(XZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white.clone())),
         Arc::new(XZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, white.clone())),
         Arc::new(XYRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white.clone())),
@@ -447,7 +731,7 @@ fn cornell_smoke() -> HittableList {
                 Vec3::new(265.0, 0.0, 295.0),
             )),
             0.01,
-            Color::default(),
+            ids.add(Isotropic::new(Color::default())),
         )),
         Arc::new(ConstantMedium::new(
             Arc::new(Translate::new(
@@ -462,14 +746,23 @@ fn cornell_smoke() -> HittableList {
                 Vec3::new(130.0, 0.0, 65.0),
             )),
             0.01,
-            Color::new(1.0, 1.0, 1.0),
+            ids.add(Isotropic::new(Color::new(1.0, 1.0, 1.0))),
         )),
-    ])
+    ]);
+
+    (world, HittableList::single(light))
 }
 
-fn final_scene() -> HittableList {
+// Returns the world and the lights in it
+#[allow(clippy::too_many_lines)]
+fn final_scene(
+    bvh: Bvh,
+    rng: &mut Rng,
+    bvh_stats: &mut Vec<Stats>,
+) -> Result<(HittableList, HittableList), codec::Error> {
+    let mut ids = MaterialIds::default();
     let mut boxes1 = HittableList::default();
-    let ground = Arc::new(Lambertian::new(Color::new(0.48, 0.83, 0.53)));
+    let ground = ids.add(Lambertian::new(Color::new(0.48, 0.83, 0.53)));
     let boxes_per_side = 20;
     for i in 0..boxes_per_side {
         for j in 0..boxes_per_side {
@@ -478,7 +771,7 @@ fn final_scene() -> HittableList {
             let z0 = -1000.0 + f64::from(j) * w;
             let y0 = 0.0;
             let x1 = x0 + w;
-            let y1 = utils::random_float_range(1.0, 101.0);
+            let y1 = utils::random_float_range(rng, 1.0, 101.0);
             let z1 = z0 + w;
 
             boxes1.add(Arc::new(B0x::new(
@@ -490,10 +783,10 @@ fn final_scene() -> HittableList {
     }
 
     let mut boxes2 = HittableList::default();
-    let white = Arc::new(Lambertian::new(Color::new_single(0.73)));
+    let white = ids.add(Lambertian::new(Color::new_single(0.73)));
     for _ in 0..1000 {
         boxes2.add(Arc::new(Sphere::new(
-            Point3::random_range(0.0, 165.0),
+            Point3::random_range(rng, 0.0, 165.0),
             10.0,
             white.clone(),
         )));
@@ -504,80 +797,75 @@ fn final_scene() -> HittableList {
     let boundary = Arc::new(Sphere::new(
         Point3::new(360.0, 150.0, 145.0),
         70.0,
-        Arc::new(Dielectric::new(1.5)),
+        ids.add(Dielectric::new(1.5)),
     ));
 
-    HittableList::new(&[
-        Arc::new(BVHNode::new_from_list(&boxes1, 0.0, 1.0)),
-        Arc::new(XZRect::new(
-            123.0,
-            423.0,
-            147.0,
-            412.0,
-            554.0,
-            Arc::new(DiffuseLight::new(Color::new_single(7.0))),
-        )),
+    let light: Arc<dyn Hittable> = Arc::new(XZRect::new(
+        123.0,
+        423.0,
+        147.0,
+        412.0,
+        554.0,
+        ids.add(DiffuseLight::new(Color::new_single(7.0))),
+    ));
+
+    let world = HittableList::new(&[
+        Arc::from(bvh.build(&boxes1, 0.0, 1.0, bvh_stats)),
+        light.clone(),
         Arc::new(MovingSphere::new(
             center1,
             center2,
             0.0,
             1.0,
             50.0,
-            Arc::new(Lambertian::new(Color::new(0.7, 0.3, 0.1))),
+            ids.add(Lambertian::new(Color::new(0.7, 0.3, 0.1))),
         )),
         Arc::new(Sphere::new(
             Point3::new(260.0, 150.0, 45.0),
             50.0,
-            Arc::new(Dielectric::new(1.5)),
+            ids.add(Dielectric::new(1.5)),
         )),
         Arc::new(Sphere::new(
             Point3::new(0.0, 150.0, 145.0),
             50.0,
-            Arc::new(Metal::new(Color::new(0.8, 0.8, 0.9), 1.0)),
+            ids.add(Metal::new(Color::new(0.8, 0.8, 0.9), 1.0)),
         )),
         boundary.clone(),
         Arc::new(ConstantMedium::new(
             boundary,
             0.2,
-            Color::new(0.2, 0.4, 0.9),
+            ids.add(Isotropic::new(Color::new(0.2, 0.4, 0.9))),
         )),
         Arc::new(ConstantMedium::new(
             Arc::new(Sphere::new(
                 Point3::default(),
                 5000.0,
-                Arc::new(Dielectric::new(1.5)),
+                ids.add(Dielectric::new(1.5)),
             )),
             0.0001,
-            Color::new_single(1.0),
+            ids.add(Isotropic::new(Color::new_single(1.0))),
         )),
-        /* TODO: Finish Image texture implementation
         Arc::new(Sphere::new(
             Point3::new(400.0, 200.0, 400.0),
             100.0,
-            Arc::new(Lambertian::new_with_texture(Box::new(Image::new(
+            ids.add(Lambertian::new_with_texture(Box::new(Image::new(
                 "resources/earthmap.jpg",
-            )))),
-        )),*/
-        // Replacement sphere until image texture sphere is completed
-        Arc::new(Sphere::new(
-            Point3::new(400.0, 200.0, 400.0),
-            100.0,
-            Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0)),
+            )?))),
         )),
         Arc::new(Sphere::new(
             Point3::new(220.0, 280.0, 300.0),
             80.0,
-            Arc::new(Lambertian::new_with_texture(Box::new(Noise::new(
+            ids.add(Lambertian::new_with_texture(Box::new(Noise::new(
                 0.1,
                 Axis::X,
+                rng,
             )))),
         )),
         Arc::new(Translate::new(
-            Box::new(RotateY::new(
-                Box::new(BVHNode::new_from_list(&boxes2, 0.0, 1.0)),
-                15.0,
-            )),
+            Box::new(RotateY::new(bvh.build(&boxes2, 0.0, 1.0, bvh_stats), 15.0)),
             Vec3::new(-100.0, 270.0, 395.0),
         )),
-    ])
+    ]);
+
+    Ok((world, HittableList::single(light)))
 }
@@ -0,0 +1,956 @@
+// Loads scenes from declarative TOML files. See scenes/cornell_box.toml for an example.
+
+pub mod toml;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use self::toml::{Kind, Table, Value};
+use crate::environment::image_map::ImageMap;
+use crate::environment::sky::Sky;
+use crate::environment::Environment;
+use crate::hittable::b0x::B0x;
+use crate::hittable::bvh_node::Stats;
+use crate::hittable::constant_medium::ConstantMedium;
+use crate::hittable::flip_face::FlipFace;
+use crate::hittable::htlist::HittableList;
+use crate::hittable::linear_bvh::LinearBVH;
+use crate::hittable::moving_sphere::MovingSphere;
+use crate::hittable::rotate_y::RotateY;
+use crate::hittable::sphere::Sphere;
+use crate::hittable::translate::Translate;
+use crate::hittable::triangle::Triangle;
+use crate::hittable::xyrect::XYRect;
+use crate::hittable::xzrect::XZRect;
+use crate::hittable::yzrect::YZRect;
+use crate::hittable::Hittable;
+use crate::light::{directional::DirectionalLight, point::PointLight, spot::SpotLight, Light};
+use crate::material::{
+    dielectric::Dielectric, diffuse_light::DiffuseLight, isotropic::Isotropic,
+    lambertian::Lambertian, metal::Metal, Material, MaterialIds,
+};
+use crate::mesh;
+use crate::scene::{Overrides, Scene, Settings};
+use crate::texture::checker::Checker;
+use crate::texture::image::{Image, Wrap};
+use crate::texture::noise::Noise;
+use crate::texture::solid_color::SolidColor;
+use crate::texture::vertex_color::VertexColor;
+use crate::texture::Texture;
+use crate::utils::{self, Rng};
+use crate::vec3::{Axis, Vec3};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Line(usize, String),
+    InFile(PathBuf, Box<Error>),
+}
+
+impl Error {
+    pub fn new(line: usize, message: impl Into<String>) -> Error {
+        Error::Line(line, message.into())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Line(line, message) => write!(f, "line {line}: {message}"),
+            Error::InFile(path, e) => write!(f, "{}: {e}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub fn load(path: &Path, overrides: &Overrides) -> Result<Scene, Error> {
+    let wrap = |e| Error::InFile(path.to_path_buf(), Box::new(e));
+    let source = fs::read_to_string(path).map_err(|e| wrap(Error::Io(e)))?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+    parse(&source, base_dir, overrides).map_err(wrap)
+}
+
+// Relative image and mesh paths are resolved against `base_dir`
+pub fn parse(source: &str, base_dir: &Path, overrides: &Overrides) -> Result<Scene, Error> {
+    let root = toml::parse(source)?;
+    let mut fields = Fields::new(&root, "scene file");
+
+    let mut settings = Settings::default();
+    if let Some(camera) = fields.optional("camera") {
+        read_camera(table(camera)?, &mut settings)?;
+    }
+    if let Some(render) = fields.optional("render") {
+        read_render(table(render)?, &mut settings)?;
+    }
+    if let Some(environment) = fields.optional("environment") {
+        settings.environment = Some(read_environment(table(environment)?, base_dir)?);
+    }
+
+    let mut loader = Loader {
+        base_dir,
+        texture_defs: HashMap::new(),
+        materials: HashMap::new(),
+        material_ids: RefCell::new(MaterialIds::default()),
+        objects: HashMap::new(),
+        groups: HashMap::new(),
+        bvh_stats: Vec::new(),
+        time: (settings.time_start, settings.time_end),
+        rng: RefCell::new(utils::scene_rng(overrides.seed.unwrap_or(settings.seed))),
+    };
+
+    if let Some(textures) = fields.optional("textures") {
+        for (name, value) in &table(textures)?.entries {
+            loader.texture_defs.insert(name.clone(), table(value)?);
+        }
+    }
+    if let Some(materials) = fields.optional("materials") {
+        for (name, value) in &table(materials)?.entries {
+            let material = loader.material_from_table(table(value)?)?;
+            loader.materials.insert(name.clone(), material);
+        }
+    }
+
+    let mut world = HittableList::default();
+    let mut lights = HittableList::default();
+    if let Some(objects) = fields.optional("objects") {
+        let Kind::Array(elements) = &objects.kind else {
+            return Err(type_error(objects, "an array of tables ([[objects]])"));
+        };
+        for element in elements {
+            loader.add_object(table(element)?, &mut world, &mut lights)?;
+        }
+    }
+    // A group no `bvh` took would otherwise vanish from the scene without a word
+    if let Some((name, (line, _))) = loader.groups.iter().min_by_key(|(_, (line, _))| *line) {
+        return Err(Error::new(
+            *line,
+            format!("group '{name}' is not used by any bvh"),
+        ));
+    }
+    let mut delta_lights = Vec::new();
+    if let Some(elements) = fields.optional("lights") {
+        let Kind::Array(elements) = &elements.kind else {
+            return Err(type_error(elements, "an array of tables ([[lights]])"));
+        };
+        for element in elements {
+            delta_lights.push(loader.light(table(element)?)?);
+        }
+    }
+    fields.finish()?;
+
+    Ok(Scene::new(
+        world,
+        lights,
+        delta_lights,
+        loader.bvh_stats,
+        &settings,
+        overrides,
+    ))
+}
+
+fn read_camera(camera: &Table, settings: &mut Settings) -> Result<(), Error> {
+    let mut fields = Fields::new(camera, "camera");
+    if let Some(v) = fields.optional("look_from") {
+        settings.look_from = vec3(v)?;
+    }
+    if let Some(v) = fields.optional("look_at") {
+        settings.look_at = vec3(v)?;
+    }
+    if let Some(v) = fields.optional("vup") {
+        settings.vup = vec3(v)?;
+    }
+    if let Some(v) = fields.optional("vfov") {
+        settings.vfov = number(v)?;
+    }
+    if let Some(v) = fields.optional("aperture") {
+        settings.aperture = number(v)?;
+    }
+    if let Some(v) = fields.optional("focus_distance") {
+        settings.focus_dist = number(v)?;
+    }
+    if let Some(v) = fields.optional("time_start") {
+        settings.time_start = number(v)?;
+    }
+    if let Some(v) = fields.optional("time_end") {
+        settings.time_end = number(v)?;
+    }
+    fields.finish()
+}
+
+fn read_render(render: &Table, settings: &mut Settings) -> Result<(), Error> {
+    let mut fields = Fields::new(render, "render");
+    if let Some(v) = fields.optional("background") {
+        settings.background = vec3(v)?;
+    }
+    if let Some(v) = fields.optional("aspect_ratio") {
+        settings.aspect_ratio = aspect_ratio(v)?;
+    }
+    if let Some(v) = fields.optional("image_width") {
+        settings.image_width = positive_integer(v)?;
+    }
+    if let Some(v) = fields.optional("samples_per_pixel") {
+        settings.samples_per_pixel = positive_integer(v)?;
+    }
+    if let Some(v) = fields.optional("max_depth") {
+        settings.max_depth = positive_integer(v)?;
+    }
+    if let Some(v) = fields.optional("seed") {
+        settings.seed = unsigned_integer(v)?;
+    }
+    if let Some(v) = fields.optional("sampler") {
+        let name = string(v)?;
+        settings.sampler = name
+            .parse()
+            .map_err(|()| Error::new(v.line, format!("unknown sampler '{name}'")))?;
+    }
+    if let Some(v) = fields.optional("noise_threshold") {
+        settings.noise_threshold = number(v)?;
+        if !(settings.noise_threshold >= 0.0 && settings.noise_threshold.is_finite()) {
+            return Err(Error::new(v.line, "noise threshold must not be negative"));
+        }
+    }
+    if let Some(v) = fields.optional("tone_map") {
+        let name = string(v)?;
+        settings.tone_map.operator = name
+            .parse()
+            .map_err(|()| Error::new(v.line, format!("unknown tone map '{name}'")))?;
+    }
+    if let Some(v) = fields.optional("exposure") {
+        settings.tone_map.exposure = number(v)?;
+        if !settings.tone_map.exposure.is_finite() {
+            return Err(Error::new(v.line, "exposure must be finite"));
+        }
+    }
+    fields.finish()
+}
+
+fn read_environment(environment: &Table, base_dir: &Path) -> Result<Arc<dyn Environment>, Error> {
+    let mut fields = Fields::new(environment, "environment");
+    let kind = string(fields.required("type")?)?;
+    let result: Arc<dyn Environment> = match kind {
+        "image" => {
+            let path_value = fields.required("path")?;
+            let intensity = match fields.optional("intensity") {
+                Some(v) => number(v)?,
+                None => 1.0,
+            };
+            let rotation = match fields.optional("rotation") {
+                Some(v) => number(v)?,
+                None => 0.0,
+            };
+            let path = base_dir.join(string(path_value)?);
+            let map = ImageMap::load(&path, intensity, rotation)
+                .map_err(|e| Error::new(path_value.line, e.to_string()))?;
+            Arc::new(map)
+        }
+        "sky" => {
+            let mut parameter = |key: &str, default: f64, min: f64, max: f64| {
+                let Some(v) = fields.optional(key) else {
+                    return Ok(default);
+                };
+                let value = number(v)?;
+                if (min..=max).contains(&value) {
+                    Ok(value)
+                } else {
+                    Err(Error::new(
+                        v.line,
+                        format!("{key} must be between {min} and {max}"),
+                    ))
+                }
+            };
+            let elevation = parameter("sun_elevation", 45.0, 0.0, 90.0)?;
+            let azimuth = parameter("sun_azimuth", 0.0, -360.0, 360.0)?;
+            let turbidity = parameter("turbidity", 3.0, 1.7, 10.0)?;
+            let intensity = parameter("intensity", 1.0, 0.0, f64::MAX)?;
+            Arc::new(Sky::new(elevation, azimuth, turbidity, intensity))
+        }
+        other => return Err(unknown_type(environment, "environment", other)),
+    };
+    fields.finish()?;
+    Ok(result)
+}
+
+struct Loader<'a> {
+    base_dir: &'a Path,
+    // Textures are owned by the material using them, so they are built fresh for each reference
+    texture_defs: HashMap<String, &'a Table>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    // Named materials are numbered in the order they are declared, then inline ones and those of
+    // meshes in the order the objects use them
+    material_ids: RefCell<MaterialIds>,
+    objects: HashMap<String, Arc<dyn Hittable>>,
+    // Objects waiting for a `bvh` to use them, with the line of each group's first member
+    groups: HashMap<String, (usize, HittableList)>,
+    // Statistics of the BVHs built for `bvh` objects and meshes
+    bvh_stats: Vec<Stats>,
+    time: (f64, f64),
+    // Seeded generator for textures built from random tables
+    rng: RefCell<Rng>,
+}
+
+impl Loader<'_> {
+    fn texture(&self, value: &Value) -> Result<Box<dyn Texture>, Error> {
+        match &value.kind {
+            Kind::String(name) => {
+                let def = self
+                    .texture_defs
+                    .get(name)
+                    .ok_or_else(|| Error::new(value.line, format!("unknown texture '{name}'")))?;
+                self.texture_from_table(def)
+            }
+            Kind::Table(def) => self.texture_from_table(def),
+            _ => Err(type_error(value, "a texture name or inline table")),
+        }
+    }
+
+    fn texture_from_table(&self, def: &Table) -> Result<Box<dyn Texture>, Error> {
+        let mut fields = Fields::new(def, "texture");
+        let kind = string(fields.required("type")?)?;
+        let texture: Box<dyn Texture> = match kind {
+            "solid" => Box::new(SolidColor::new(vec3(fields.required("color")?)?)),
+            "checker" => Box::new(Checker::new(
+                vec3(fields.required("even")?)?,
+                vec3(fields.required("odd")?)?,
+            )),
+            "noise" => {
+                let scale = number(fields.required("scale")?)?;
+                let axis = match fields.optional("axis") {
+                    Some(v) => axis(v)?,
+                    None => Axis::Z,
+                };
+                Box::new(Noise::new(scale, axis, &mut self.rng.borrow_mut()))
+            }
+            "image" => {
+                let path_value = fields.required("path")?;
+                let wrap = match fields.optional("wrap") {
+                    Some(v) => wrap(v)?,
+                    None => Wrap::Clamp,
+                };
+                let path = self.base_dir.join(string(path_value)?);
+                let image = Image::new_with_wrap(&path.to_string_lossy(), wrap)
+                    .map_err(|e| Error::new(path_value.line, e.to_string()))?;
+                Box::new(image)
+            }
+            "vertex_color" => match fields.optional("fallback") {
+                Some(v) => Box::new(VertexColor::new(vec3(v)?)),
+                None => Box::new(VertexColor::default()),
+            },
+            other => return Err(unknown_type(def, "texture", other)),
+        };
+        fields.finish()?;
+        Ok(texture)
+    }
+
+    // Reads a color from `color_key` or a texture from "texture"
+    fn color_or_texture(
+        &self,
+        fields: &mut Fields,
+        color_key: &str,
+    ) -> Result<Box<dyn Texture>, Error> {
+        match (fields.optional(color_key), fields.optional("texture")) {
+            (Some(color), None) => Ok(Box::new(SolidColor::new(vec3(color)?))),
+            (None, Some(texture)) => self.texture(texture),
+            (Some(v), Some(_)) => Err(Error::new(
+                v.line,
+                format!("'{color_key}' and 'texture' cannot both be set"),
+            )),
+            (None, None) => Err(Error::new(
+                fields.table.line,
+                format!("{} is missing '{color_key}' or 'texture'", fields.what),
+            )),
+        }
+    }
+
+    fn material(&self, value: &Value) -> Result<Arc<dyn Material>, Error> {
+        match &value.kind {
+            Kind::String(name) => self
+                .materials
+                .get(name)
+                .cloned()
+                .ok_or_else(|| Error::new(value.line, format!("unknown material '{name}'"))),
+            Kind::Table(def) => self.material_from_table(def),
+            _ => Err(type_error(value, "a material name or inline table")),
+        }
+    }
+
+    fn material_from_table(&self, def: &Table) -> Result<Arc<dyn Material>, Error> {
+        let mut fields = Fields::new(def, "material");
+        let kind = string(fields.required("type")?)?;
+        let mut ids = self.material_ids.borrow_mut();
+        let material = match kind {
+            "lambertian" => ids.add(Lambertian::new_with_texture(
+                self.color_or_texture(&mut fields, "albedo")?,
+            )),
+            "metal" => {
+                let fuzz = match fields.optional("fuzz") {
+                    Some(v) => number(v)?,
+                    None => 0.0,
+                };
+                ids.add(Metal::new(vec3(fields.required("albedo")?)?, fuzz))
+            }
+            "dielectric" => ids.add(Dielectric::new(number(
+                fields.required("refraction_index")?,
+            )?)),
+            "diffuse_light" => {
+                let emit = self.color_or_texture(&mut fields, "emit")?;
+                let intensity = match fields.optional("intensity") {
+                    Some(v) => number(v)?,
+                    None => 1.0,
+                };
+                if !(intensity >= 0.0 && intensity.is_finite()) {
+                    return Err(Error::new(def.line, "intensity must not be negative"));
+                }
+                let two_sided = match fields.optional("two_sided") {
+                    Some(v) => boolean(v)?,
+                    None => true,
+                };
+                ids.add(DiffuseLight::new_texture(emit, intensity, two_sided))
+            }
+            "isotropic" => ids.add(Isotropic::new_texture(
+                self.color_or_texture(&mut fields, "albedo")?,
+            )),
+            other => return Err(unknown_type(def, "material", other)),
+        };
+        fields.finish()?;
+        Ok(material)
+    }
+
+    // Intensities are in W/sr and the directional light's irradiance in W/m²
+    fn light(&self, def: &Table) -> Result<Box<dyn Light>, Error> {
+        let mut fields = Fields::new(def, "light");
+        let kind = string(fields.required("type")?)?;
+        let light: Box<dyn Light> = match kind {
+            "point" => Box::new(PointLight::new(
+                vec3(fields.required("position")?)?,
+                vec3(fields.required("intensity")?)?,
+            )),
+            "spot" => {
+                let position = vec3(fields.required("position")?)?;
+                let direction = direction(fields.required("direction")?)?;
+                let intensity = vec3(fields.required("intensity")?)?;
+                // As in pbrt, the light fades out over the last 5 degrees by default
+                let outer_angle = match fields.optional("outer_angle") {
+                    Some(v) => number(v)?,
+                    None => 30.0,
+                };
+                if !(outer_angle > 0.0 && outer_angle < 90.0) {
+                    return Err(Error::new(
+                        def.line,
+                        "outer_angle must be between 0 and 90 degrees",
+                    ));
+                }
+                let inner_angle = match fields.optional("inner_angle") {
+                    Some(v) => number(v)?,
+                    None => (outer_angle - 5.0).max(0.0),
+                };
+                let gobo = fields
+                    .optional("gobo")
+                    .map(|v| self.texture(v))
+                    .transpose()?;
+                Box::new(SpotLight::new(
+                    position,
+                    &direction,
+                    intensity,
+                    inner_angle,
+                    outer_angle,
+                    gobo,
+                ))
+            }
+            "directional" => Box::new(DirectionalLight::new(
+                &direction(fields.required("direction")?)?,
+                vec3(fields.required("irradiance")?)?,
+            )),
+            other => return Err(unknown_type(def, "light", other)),
+        };
+        fields.finish()?;
+        Ok(light)
+    }
+
+    fn object(&self, value: &Value) -> Result<Arc<dyn Hittable>, Error> {
+        let name = string(value)?;
+        self.objects.get(name).cloned().ok_or_else(|| {
+            Error::new(
+                value.line,
+                format!(
+                    "unknown object '{name}' (objects must be named before they are referenced)"
+                ),
+            )
+        })
+    }
+
+    fn add_object(
+        &mut self,
+        def: &Table,
+        world: &mut HittableList,
+        lights: &mut HittableList,
+    ) -> Result<(), Error> {
+        let mut fields = Fields::new(def, "object");
+        let name = fields.optional("name").map(string).transpose()?;
+        let group = fields.optional("group").map(string).transpose()?;
+        let hidden = match fields.optional("hidden") {
+            Some(v) => boolean(v)?,
+            None => false,
+        };
+        let light = fields.optional("light");
+
+        let mut hittable = self.shape(&mut fields)?;
+
+        // Transforms apply in a fixed order: rotate about the object's origin, then translate
+        let rotate_y = fields.optional("rotate_y");
+        if let Some(angle) = rotate_y {
+            hittable = Box::new(RotateY::new(hittable, number(angle)?));
+        }
+        let translate = fields.optional("translate");
+        if let Some(offset) = translate {
+            hittable = Box::new(Translate::new(hittable, vec3(offset)?));
+        }
+        // Applied last, as the transforms work out the front face afresh
+        if let Some(v) = fields.optional("flip") {
+            if boolean(v)? {
+                hittable = Box::new(FlipFace::new(hittable));
+            }
+        }
+        fields.finish()?;
+
+        // Lights are sampled directly, which only spheres and rectangles in place support
+        let light = match light {
+            Some(v) if boolean(v)? => {
+                let kind = def.get("type").map(string).transpose()?;
+                if !matches!(kind, Some("sphere" | "xy_rect" | "xz_rect" | "yz_rect")) {
+                    return Err(Error::new(
+                        v.line,
+                        "only spheres and rectangles can be lights",
+                    ));
+                }
+                if hidden || rotate_y.is_some() || translate.is_some() {
+                    return Err(Error::new(v.line, "lights cannot be hidden or transformed"));
+                }
+                true
+            }
+            _ => false,
+        };
+
+        let hittable: Arc<dyn Hittable> = Arc::from(hittable);
+        if let Some(name) = name {
+            if self.objects.contains_key(name) {
+                return Err(Error::new(
+                    def.line,
+                    format!("duplicate object name '{name}'"),
+                ));
+            }
+            self.objects.insert(name.to_string(), hittable.clone());
+        }
+
+        if light {
+            lights.add(hittable.clone());
+        }
+        if hidden {
+            return Ok(());
+        }
+        match group {
+            Some(group) => self
+                .groups
+                .entry(group.to_string())
+                .or_insert_with(|| (def.line, HittableList::default()))
+                .1
+                .add(hittable),
+            None => world.add(hittable),
+        }
+
+        Ok(())
+    }
+
+    fn shape(&mut self, fields: &mut Fields) -> Result<Box<dyn Hittable>, Error> {
+        let kind = string(fields.required("type")?)?;
+        let (time0, time1) = self.time;
+
+        Ok(match kind {
+            "sphere" => Box::new(Sphere::new(
+                vec3(fields.required("center")?)?,
+                number(fields.required("radius")?)?,
+                self.material(fields.required("material")?)?,
+            )),
+            "moving_sphere" => {
+                let start = match fields.optional("time_start") {
+                    Some(v) => number(v)?,
+                    None => time0,
+                };
+                let end = match fields.optional("time_end") {
+                    Some(v) => number(v)?,
+                    None => time1,
+                };
+                Box::new(MovingSphere::new(
+                    vec3(fields.required("center_start")?)?,
+                    vec3(fields.required("center_end")?)?,
+                    start,
+                    end,
+                    number(fields.required("radius")?)?,
+                    self.material(fields.required("material")?)?,
+                ))
+            }
+            "xy_rect" => Box::new(XYRect::new(
+                number(fields.required("x0")?)?,
+                number(fields.required("x1")?)?,
+                number(fields.required("y0")?)?,
+                number(fields.required("y1")?)?,
+                number(fields.required("k")?)?,
+                self.material(fields.required("material")?)?,
+            )),
+            "xz_rect" => Box::new(XZRect::new(
+                number(fields.required("x0")?)?,
+                number(fields.required("x1")?)?,
+                number(fields.required("z0")?)?,
+                number(fields.required("z1")?)?,
+                number(fields.required("k")?)?,
+                self.material(fields.required("material")?)?,
+            )),
+            "yz_rect" => Box::new(YZRect::new(
+                number(fields.required("y0")?)?,
+                number(fields.required("y1")?)?,
+                number(fields.required("z0")?)?,
+                number(fields.required("z1")?)?,
+                number(fields.required("k")?)?,
+                self.material(fields.required("material")?)?,
+            )),
+            "triangle" => Box::new(Triangle::new(
+                vec3(fields.required("v0")?)?,
+                vec3(fields.required("v1")?)?,
+                vec3(fields.required("v2")?)?,
+                self.material(fields.required("material")?)?,
+            )),
+            "box" => Box::new(B0x::new(
+                vec3(fields.required("min")?)?,
+                vec3(fields.required("max")?)?,
+                self.material(fields.required("material")?)?,
+            )),
+            "constant_medium" => {
+                let boundary = self.object(fields.required("boundary")?)?;
+                let density = number(fields.required("density")?)?;
+                let phase_func = Isotropic::new_texture(self.color_or_texture(fields, "color")?);
+                Box::new(ConstantMedium::new(
+                    boundary,
+                    density,
+                    self.material_ids.borrow_mut().add(phase_func),
+                ))
+            }
+            "mesh" => Box::new(self.mesh(fields)?),
+            "bvh" => Box::new(self.bvh(fields)?),
+            other => return Err(unknown_type(fields.table, "object", other)),
+        })
+    }
+
+    fn mesh(&mut self, fields: &mut Fields) -> Result<LinearBVH, Error> {
+        let path_value = fields.required("path")?;
+        let material = fields
+            .optional("material")
+            .map(|v| self.material(v))
+            .transpose()?;
+        let path = self.base_dir.join(string(path_value)?);
+        let (bvh, stats) = mesh::load(&path, material, &mut self.material_ids.borrow_mut())
+            .map_err(|e| Error::new(path_value.line, e.to_string()))?;
+        self.bvh_stats.push(stats);
+        Ok(bvh)
+    }
+
+    fn bvh(&mut self, fields: &mut Fields) -> Result<LinearBVH, Error> {
+        let (time0, time1) = self.time;
+        let members = fields.required("members")?;
+        let name = string(members)?;
+        let (_, list) = self.groups.remove(name).ok_or_else(|| {
+            Error::new(
+                members.line,
+                format!("unknown or empty group '{name}' (members must come first)"),
+            )
+        })?;
+        if list
+            .objects
+            .iter()
+            .any(|o| o.bounding_box(time0, time1).is_none())
+        {
+            return Err(Error::new(
+                members.line,
+                format!("group '{name}' contains an object without a bounding box"),
+            ));
+        }
+        let (bvh, stats) = LinearBVH::new_from_list(&list, time0, time1);
+        self.bvh_stats.push(stats);
+        Ok(bvh)
+    }
+}
+
+// Tracks which keys of a table have been read so that unknown keys can be reported
+struct Fields<'a> {
+    table: &'a Table,
+    what: &'static str,
+    used: Vec<&'a str>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(table: &'a Table, what: &'static str) -> Fields<'a> {
+        Fields {
+            table,
+            what,
+            used: Vec::new(),
+        }
+    }
+
+    fn optional(&mut self, key: &str) -> Option<&'a Value> {
+        let (k, v) = self.table.entries.iter().find(|(k, _)| k == key)?;
+        self.used.push(k);
+        Some(v)
+    }
+
+    fn required(&mut self, key: &str) -> Result<&'a Value, Error> {
+        self.optional(key)
+            .ok_or_else(|| Error::new(self.table.line, format!("{} is missing '{key}'", self.what)))
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        match self
+            .table
+            .entries
+            .iter()
+            .find(|(k, _)| !self.used.contains(&k.as_str()))
+        {
+            Some((key, value)) => Err(Error::new(
+                value.line,
+                format!("unknown key '{key}' in {}", self.what),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+fn type_error(value: &Value, expected: &str) -> Error {
+    Error::new(
+        value.line,
+        format!("expected {expected}, found {}", value.type_name()),
+    )
+}
+
+fn unknown_type(def: &Table, what: &str, kind: &str) -> Error {
+    let line = def.get("type").map_or(def.line, |v| v.line);
+    Error::new(line, format!("unknown {what} type '{kind}'"))
+}
+
+fn table(value: &Value) -> Result<&Table, Error> {
+    match &value.kind {
+        Kind::Table(t) => Ok(t),
+        _ => Err(type_error(value, "a table")),
+    }
+}
+
+fn string(value: &Value) -> Result<&str, Error> {
+    match &value.kind {
+        Kind::String(s) => Ok(s),
+        _ => Err(type_error(value, "a string")),
+    }
+}
+
+fn boolean(value: &Value) -> Result<bool, Error> {
+    match value.kind {
+        Kind::Boolean(b) => Ok(b),
+        _ => Err(type_error(value, "a boolean")),
+    }
+}
+
+fn number(value: &Value) -> Result<f64, Error> {
+    match value.kind {
+        Kind::Float(f) => Ok(f),
+        #[allow(clippy::cast_precision_loss)] // Scene values are small
+        Kind::Integer(i) => Ok(i as f64),
+        _ => Err(type_error(value, "a number")),
+    }
+}
+
+fn positive_integer(value: &Value) -> Result<i32, Error> {
+    match value.kind {
+        Kind::Integer(i) => i32::try_from(i).ok().filter(|&i| i > 0).ok_or_else(|| {
+            Error::new(
+                value.line,
+                format!("expected a positive integer, found {i}"),
+            )
+        }),
+        _ => Err(type_error(value, "an integer")),
+    }
+}
+
+fn unsigned_integer(value: &Value) -> Result<u64, Error> {
+    match value.kind {
+        Kind::Integer(i) => u64::try_from(i).map_err(|_| {
+            Error::new(
+                value.line,
+                format!("expected a non-negative integer, found {i}"),
+            )
+        }),
+        _ => Err(type_error(value, "an integer")),
+    }
+}
+
+fn vec3(value: &Value) -> Result<Vec3, Error> {
+    match &value.kind {
+        Kind::Array(elements) if elements.len() == 3 => Ok(Vec3::new(
+            number(&elements[0])?,
+            number(&elements[1])?,
+            number(&elements[2])?,
+        )),
+        _ => Err(type_error(value, "an array of three numbers")),
+    }
+}
+
+// A vector that is not zero, so that it has a direction
+fn direction(value: &Value) -> Result<Vec3, Error> {
+    let v = vec3(value)?;
+    if v.length_squared() > 0.0 {
+        Ok(v)
+    } else {
+        Err(Error::new(value.line, "direction must not be zero"))
+    }
+}
+
+// Either a number or a "width:height" string
+fn aspect_ratio(value: &Value) -> Result<f64, Error> {
+    let ratio = match &value.kind {
+        Kind::String(s) => s
+            .split_once(':')
+            .and_then(|(w, h)| Some(w.trim().parse::<f64>().ok()? / h.trim().parse::<f64>().ok()?))
+            .ok_or_else(|| Error::new(value.line, format!("invalid aspect ratio '{s}'")))?,
+        _ => number(value)?,
+    };
+    if ratio.is_finite() && ratio > 0.0 {
+        Ok(ratio)
+    } else {
+        Err(Error::new(value.line, "aspect ratio must be positive"))
+    }
+}
+
+fn axis(value: &Value) -> Result<Axis, Error> {
+    match string(value)? {
+        "x" => Ok(Axis::X),
+        "y" => Ok(Axis::Y),
+        "z" => Ok(Axis::Z),
+        other => Err(Error::new(value.line, format!("unknown axis '{other}'"))),
+    }
+}
+
+fn wrap(value: &Value) -> Result<Wrap, Error> {
+    match string(value)? {
+        "clamp" => Ok(Wrap::Clamp),
+        "repeat" => Ok(Wrap::Repeat),
+        "mirror" => Ok(Wrap::Mirror),
+        other => Err(Error::new(
+            value.line,
+            format!("unknown wrap mode '{other}'"),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPHERE: &str = "[materials.white]\n\
+                          type = \"lambertian\"\n\
+                          albedo = [0.73, 0.73, 0.73]\n\
+                          \n\
+                          [[objects]]\n\
+                          type = \"sphere\"\n\
+                          center = [0, 0, -1]\n\
+                          radius = 0.5\n\
+                          material = \"white\"\n";
+
+    fn error(source: &str) -> String {
+        match parse(source, Path::new("."), &Overrides::default()) {
+            Ok(_) => panic!("loaded without an error: {source}"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn bundled_scenes_load() {
+        for entry in fs::read_dir("scenes").unwrap() {
+            let path = entry.unwrap().path();
+            if let Err(e) = load(&path, &Overrides::default()) {
+                panic!("{e}");
+            }
+        }
+    }
+
+    #[test]
+    fn settings_and_overrides() {
+        let source = format!("[render]\nimage_width = 64\nseed = 7\n\n{SPHERE}");
+        let overrides = Overrides {
+            samples_per_pixel: Some(3),
+            ..Overrides::default()
+        };
+        let scene = parse(&source, Path::new("."), &overrides).unwrap();
+        assert_eq!(scene.image_width, 64);
+        assert_eq!(scene.samples_per_pixel, 3);
+        assert_eq!(scene.world.objects.len(), 1);
+    }
+
+    #[test]
+    fn toml_errors_keep_their_line() {
+        assert_eq!(
+            error(&format!("{SPHERE}radius = 1\n")),
+            "line 10: duplicate key 'radius'"
+        );
+    }
+
+    #[test]
+    fn unknown_names() {
+        let source = SPHERE.replace("material = \"white\"", "material = \"black\"");
+        assert_eq!(error(&source), "line 9: unknown material 'black'");
+
+        let source = SPHERE.replace("type = \"sphere\"", "type = \"cone\"");
+        assert_eq!(error(&source), "line 6: unknown object type 'cone'");
+
+        let source = SPHERE.replace("radius = 0.5", "radius = 0.5\nradios = 1");
+        assert_eq!(error(&source), "line 9: unknown key 'radios' in object");
+    }
+
+    #[test]
+    fn missing_and_mistyped_keys() {
+        let source = SPHERE.replace("radius = 0.5\n", "");
+        assert_eq!(error(&source), "line 5: object is missing 'radius'");
+
+        let source = SPHERE.replace("radius = 0.5", "radius = \"big\"");
+        assert_eq!(error(&source), "line 8: expected a number, found a string");
+
+        let source = SPHERE.replace("[0, 0, -1]", "[0, 0]");
+        assert!(error(&source).starts_with("line 7: "));
+    }
+
+    #[test]
+    fn unused_group() {
+        // The group's first member is the object at line 11
+        let source = format!(
+            "{SPHERE}\n\
+             [[objects]]\n\
+             type = \"sphere\"\n\
+             center = [1, 0, -1]\n\
+             radius = 0.5\n\
+             material = \"white\"\n\
+             group = \"spheres\"\n"
+        );
+        assert_eq!(
+            error(&source),
+            "line 11: group 'spheres' is not used by any bvh"
+        );
+    }
+
+    #[test]
+    fn missing_files_name_the_file() {
+        let source = format!("{SPHERE}\n[[objects]]\ntype = \"mesh\"\npath = \"missing.obj\"\n");
+        let message = error(&source);
+        assert!(message.starts_with("line 13: "), "{message}");
+        assert!(message.contains("missing.obj"), "{message}");
+    }
+}
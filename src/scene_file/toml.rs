@@ -0,0 +1,567 @@
+// Parser for the subset of TOML used by scene files: tables, arrays of tables, strings, numbers,
+// booleans, arrays and inline tables. Every value remembers the line it was declared on.
+
+use super::Error;
+
+pub enum Kind {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+pub struct Value {
+    pub kind: Kind,
+    pub line: usize,
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self.kind {
+            Kind::String(_) => "a string",
+            Kind::Integer(_) => "an integer",
+            Kind::Float(_) => "a float",
+            Kind::Boolean(_) => "a boolean",
+            Kind::Array(_) => "an array",
+            Kind::Table(_) => "a table",
+        }
+    }
+}
+
+// Key/value pairs in declaration order
+#[derive(Default)]
+pub struct Table {
+    pub entries: Vec<(String, Value)>,
+    pub line: usize,
+    // Set once a header or inline table has given the table's contents, rather than a longer
+    // header only passing through it, so that defining it again can be caught
+    defined: bool,
+}
+
+impl Table {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        self.entries
+            .iter_mut()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    fn insert(&mut self, key: String, value: Value) -> Result<(), Error> {
+        if self.get(&key).is_some() {
+            return Err(Error::new(value.line, format!("duplicate key '{key}'")));
+        }
+        self.entries.push((key, value));
+        Ok(())
+    }
+}
+
+pub fn parse(source: &str) -> Result<Table, Error> {
+    let mut parser = Parser {
+        chars: source.chars().collect(),
+        pos: 0,
+        line: 1,
+    };
+    let mut root = Table {
+        entries: Vec::new(),
+        line: 1,
+        defined: true,
+    };
+    // Path of the table that key/value pairs are currently added to
+    let mut current: Vec<String> = Vec::new();
+
+    loop {
+        parser.skip_whitespace_and_comments();
+        let Some(c) = parser.peek() else {
+            break;
+        };
+
+        if c == '[' {
+            let line = parser.line;
+            parser.pos += 1;
+            let array = parser.peek() == Some('[');
+            if array {
+                parser.pos += 1;
+            }
+
+            current = parser.header_path()?;
+            parser.expect(']')?;
+            if array {
+                parser.expect(']')?;
+            }
+            parser.end_of_line()?;
+
+            open_table(&mut root, &current, array, line)?;
+        } else {
+            let (key, value) = parser.key_value()?;
+            parser.end_of_line()?;
+            table_at(&mut root, &current).insert(key, value)?;
+        }
+    }
+
+    Ok(root)
+}
+
+// Creates the table named by a [header] or appends a new element for an [[array]] header. Tables
+// that only a longer header created, such as `a` for [a.b], may still get a header of their own
+fn open_table(root: &mut Table, path: &[String], array: bool, line: usize) -> Result<(), Error> {
+    let mut table = root;
+    for (i, name) in path.iter().enumerate() {
+        let last = i + 1 == path.len();
+        if table.get(name).is_none() {
+            let kind = if last && array {
+                Kind::Array(Vec::new())
+            } else {
+                Kind::Table(Table {
+                    entries: Vec::new(),
+                    line,
+                    defined: false,
+                })
+            };
+            table.entries.push((name.clone(), Value { kind, line }));
+        }
+
+        if last {
+            let defined_twice =
+                || Error::new(line, format!("table '{}' is defined twice", path.join(".")));
+            match &table.get(name).expect("inserted above").kind {
+                Kind::Table(_) if array => {
+                    return Err(Error::new(
+                        line,
+                        format!("'{name}' is not an array of tables"),
+                    ));
+                }
+                Kind::Table(t) if t.defined => return Err(defined_twice()),
+                Kind::Array(_) if !array => return Err(defined_twice()),
+                _ => {}
+            }
+        }
+
+        let value = table.get_mut(name).expect("inserted above");
+        table = match &mut value.kind {
+            Kind::Table(t) => {
+                if last {
+                    t.defined = true;
+                    t.line = line;
+                }
+                t
+            }
+            Kind::Array(elements) => {
+                if last {
+                    elements.push(Value {
+                        kind: Kind::Table(Table {
+                            entries: Vec::new(),
+                            line,
+                            defined: true,
+                        }),
+                        line,
+                    });
+                }
+                match elements.last_mut().map(|v| &mut v.kind) {
+                    Some(Kind::Table(t)) => t,
+                    _ => return Err(Error::new(line, format!("'{name}' is not a table"))),
+                }
+            }
+            _ => return Err(Error::new(line, format!("'{name}' is not a table"))),
+        };
+    }
+    Ok(())
+}
+
+// Finds the table for a header path that has already been opened
+fn table_at<'a>(root: &'a mut Table, path: &[String]) -> &'a mut Table {
+    let mut table = root;
+    for name in path {
+        let value = table.get_mut(name).expect("header was opened");
+        table = match &mut value.kind {
+            Kind::Table(t) => t,
+            Kind::Array(elements) => match elements.last_mut().map(|v| &mut v.kind) {
+                Some(Kind::Table(t)) => t,
+                _ => unreachable!("array of tables only holds tables"),
+            },
+            _ => unreachable!("header paths only pass through tables"),
+        };
+    }
+    table
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn error(&self, message: impl Into<String>) -> Error {
+        Error::new(self.line, message)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), Error> {
+        self.skip_spaces();
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{expected}'")))
+        }
+    }
+
+    // Skips spaces and tabs on the current line
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), Some('\n') | None) {
+                self.pos += 1;
+            }
+        }
+    }
+
+    fn skip_whitespace_and_comments(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') => {
+                    self.line += 1;
+                    self.pos += 1;
+                }
+                Some('\r') => self.pos += 1,
+                _ => break,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), Error> {
+        self.skip_spaces();
+        self.skip_comment();
+        match self.peek() {
+            None | Some('\n' | '\r') => Ok(()),
+            Some(c) => Err(self.error(format!("unexpected '{c}' after value"))),
+        }
+    }
+
+    fn key(&mut self) -> Result<String, Error> {
+        self.skip_spaces();
+        if self.peek() == Some('"') {
+            return self.string();
+        }
+
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(self.error("expected a key"));
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn header_path(&mut self) -> Result<Vec<String>, Error> {
+        let mut path = vec![self.key()?];
+        loop {
+            self.skip_spaces();
+            if self.peek() == Some('.') {
+                self.pos += 1;
+                path.push(self.key()?);
+            } else {
+                return Ok(path);
+            }
+        }
+    }
+
+    fn key_value(&mut self) -> Result<(String, Value), Error> {
+        let key = self.key()?;
+        self.skip_spaces();
+        if self.peek() == Some('.') {
+            return Err(self.error("dotted keys are not supported, use a [table] header"));
+        }
+        self.expect('=')?;
+        let value = self.value()?;
+        Ok((key, value))
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        self.skip_spaces();
+        let line = self.line;
+        let kind = match self.peek() {
+            Some('"') => Kind::String(self.string()?),
+            Some('[') => Kind::Array(self.array()?),
+            Some('{') => Kind::Table(self.inline_table()?),
+            Some('t' | 'f') => Kind::Boolean(self.boolean()?),
+            Some(c) if c == '+' || c == '-' || c == '.' || c.is_ascii_digit() => self.number()?,
+            _ => return Err(self.error("expected a value")),
+        };
+        Ok(Value { kind, line })
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            match self.peek() {
+                None | Some('\n') => return Err(self.error("unterminated string")),
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    let escaped = match self.peek() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        _ => return Err(self.error("invalid escape sequence")),
+                    };
+                    s.push(escaped);
+                    self.pos += 1;
+                }
+                Some(c) => {
+                    s.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn boolean(&mut self) -> Result<bool, Error> {
+        for (word, value) in [("true", true), ("false", false)] {
+            let end = self.pos + word.len();
+            if end <= self.chars.len() && self.chars[self.pos..end].iter().copied().eq(word.chars())
+            {
+                self.pos = end;
+                return Ok(value);
+            }
+        }
+        Err(self.error("expected a value"))
+    }
+
+    fn number(&mut self) -> Result<Kind, Error> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.' | '_'))
+        {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos]
+            .iter()
+            .filter(|&&c| c != '_')
+            .collect();
+
+        if let Ok(i) = text.parse::<i64>() {
+            Ok(Kind::Integer(i))
+        } else if let Ok(f) = text.parse::<f64>() {
+            Ok(Kind::Float(f))
+        } else {
+            Err(self.error(format!("invalid number '{text}'")))
+        }
+    }
+
+    fn array(&mut self) -> Result<Vec<Value>, Error> {
+        self.pos += 1;
+        let mut elements = Vec::new();
+        loop {
+            self.skip_whitespace_and_comments();
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return Ok(elements);
+            }
+
+            elements.push(self.value()?);
+
+            self.skip_whitespace_and_comments();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {}
+                _ => return Err(self.error("expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Table, Error> {
+        let mut table = Table {
+            entries: Vec::new(),
+            line: self.line,
+            defined: true,
+        };
+        self.pos += 1;
+        self.skip_spaces();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(table);
+        }
+
+        loop {
+            let (key, value) = self.key_value()?;
+            table.insert(key, value)?;
+            self.skip_spaces();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(table);
+                }
+                _ => return Err(self.error("expected ',' or '}' in inline table")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> String {
+        match parse(source) {
+            Ok(_) => panic!("parsed without an error: {source}"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn table(value: &Value) -> &Table {
+        match &value.kind {
+            Kind::Table(t) => t,
+            _ => panic!("expected a table, found {}", value.type_name()),
+        }
+    }
+
+    #[test]
+    fn values_and_lines() {
+        let root = parse(
+            "# comment\n\
+             name = \"a \\\"quoted\\\" \\\\ name\"\n\
+             count = 1_000\n\
+             ratio = -2.5e-1 # trailing comment\n\
+             on = true\n\
+             list = [1, 2.0,\n  \"three\", # comment\n]\n\
+             inline = { x = 1, y = { z = false } }\n",
+        )
+        .unwrap();
+
+        let name = root.get("name").unwrap();
+        assert!(matches!(&name.kind, Kind::String(s) if s == "a \"quoted\" \\ name"));
+        assert_eq!(name.line, 2);
+        assert!(matches!(
+            root.get("count").unwrap().kind,
+            Kind::Integer(1000)
+        ));
+        assert!(
+            matches!(root.get("ratio").unwrap().kind, Kind::Float(f) if (f + 0.25).abs() < 1e-12)
+        );
+        assert!(matches!(root.get("on").unwrap().kind, Kind::Boolean(true)));
+
+        let Kind::Array(list) = &root.get("list").unwrap().kind else {
+            panic!("list is not an array");
+        };
+        assert_eq!(list.len(), 3);
+        assert_eq!(list[2].line, 7);
+
+        let inline = table(root.get("inline").unwrap());
+        assert_eq!(inline.line, 9);
+        assert!(table(inline.get("y").unwrap()).get("z").is_some());
+    }
+
+    #[test]
+    fn tables_and_arrays_of_tables() {
+        let root = parse(
+            "[camera]\nvfov = 40\n\n\
+             [materials.red]\ntype = \"lambertian\"\n\n\
+             [[objects]]\ntype = \"sphere\"\n\n\
+             [[objects]]\ntype = \"bvh\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(table(root.get("camera").unwrap()).line, 1);
+        let materials = table(root.get("materials").unwrap());
+        assert_eq!(table(materials.get("red").unwrap()).line, 4);
+        let Kind::Array(objects) = &root.get("objects").unwrap().kind else {
+            panic!("objects is not an array");
+        };
+        assert_eq!(objects.len(), 2);
+        assert_eq!(table(&objects[1]).line, 10);
+        assert!(table(&objects[1]).get("type").is_some());
+    }
+
+    #[test]
+    fn implicit_table_can_be_defined_later() {
+        let root = parse("[a.b]\nx = 1\n[a]\ny = 2\n").unwrap();
+        let a = table(root.get("a").unwrap());
+        assert_eq!(a.line, 3);
+        assert!(a.get("y").is_some());
+        assert!(table(a.get("b").unwrap()).get("x").is_some());
+
+        assert!(parse("[a]\ny = 2\n[a.b]\nx = 1\n").is_ok());
+    }
+
+    #[test]
+    fn duplicate_key() {
+        assert_eq!(error("x = 1\ny = 2\nx = 3\n"), "line 3: duplicate key 'x'");
+        assert_eq!(
+            error("[a]\nx = 1\n[b]\nt = { y = 1, y = 2 }\n"),
+            "line 4: duplicate key 'y'"
+        );
+        assert_eq!(error("a = 1\n[a]\n"), "line 2: 'a' is not a table");
+    }
+
+    #[test]
+    fn table_defined_twice() {
+        assert_eq!(
+            error("[a]\n[b]\n[a]\n"),
+            "line 3: table 'a' is defined twice"
+        );
+        assert_eq!(
+            error("[a.b]\n[a.b]\n"),
+            "line 2: table 'a.b' is defined twice"
+        );
+        assert_eq!(
+            error("[a.b]\n[a]\n[a]\n"),
+            "line 3: table 'a' is defined twice"
+        );
+        assert_eq!(error("[[a]]\n[a]\n"), "line 2: table 'a' is defined twice");
+        assert_eq!(
+            error("[a]\n[[a]]\n"),
+            "line 2: 'a' is not an array of tables"
+        );
+    }
+
+    #[test]
+    fn unterminated_string() {
+        assert_eq!(
+            error("x = 1\ny = \"abc\nz = 2\n"),
+            "line 2: unterminated string"
+        );
+        assert_eq!(error("y = \"abc"), "line 1: unterminated string");
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(error("x = 1 2\n"), "line 1: unexpected '2' after value");
+        assert_eq!(error("\n\nx =\n"), "line 3: expected a value");
+        assert_eq!(error("x = 1.2.3\n"), "line 1: invalid number '1.2.3'");
+        assert_eq!(error("x = \"\\q\"\n"), "line 1: invalid escape sequence");
+        assert_eq!(
+            error("a.b = 1\n"),
+            "line 1: dotted keys are not supported, use a [table] header"
+        );
+        assert_eq!(error("x = [1 2]\n"), "line 1: expected ',' or ']' in array");
+        assert_eq!(error("[a\n"), "line 1: expected ']'");
+    }
+}
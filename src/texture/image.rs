@@ -1,48 +1,86 @@
+use std::path::Path;
+
 use super::Texture;
+use crate::codec::{self, Bitmap};
+use crate::utils;
 use crate::vec3::{Color, Point3};
 
+// How texture coordinates outside of [0, 1] are mapped back onto the image
+#[derive(Clone, Copy)]
+pub enum Wrap {
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+impl Wrap {
+    fn apply(self, t: f64) -> f64 {
+        match self {
+            Wrap::Clamp => utils::clamp(t, 0.0, 1.0),
+            Wrap::Repeat => t - t.floor(),
+            Wrap::Mirror => {
+                let t = t.rem_euclid(2.0);
+                if t > 1.0 {
+                    2.0 - t
+                } else {
+                    t
+                }
+            }
+        }
+    }
+}
+
 pub struct Image {
     data: Vec<u8>,
-    _width: i32,
-    _height: i32,
-    _bytes_per_scanline: i32,
-    _bytes_per_pixel: i32,
+    width: i32,
+    height: i32,
+    bytes_per_scanline: i32,
+    bytes_per_pixel: i32,
+    wrap: Wrap,
 }
 
 impl Image {
-    pub fn new(_file_path: &str) -> Image {
-        // TODO: Load image file into memory
+    pub fn new(file_path: &str) -> Result<Image, codec::Error> {
+        Image::new_with_wrap(file_path, Wrap::Clamp)
+    }
+
+    pub fn new_with_wrap(file_path: &str, wrap: Wrap) -> Result<Image, codec::Error> {
+        Ok(Image::from_bitmap(codec::load(Path::new(file_path))?, wrap))
+    }
+
+    pub fn from_bitmap(bitmap: Bitmap, wrap: Wrap) -> Image {
+        let to_i32 = |v: usize| i32::try_from(v).expect("Image dimensions fit in i32");
+        let bytes_per_pixel = to_i32(Bitmap::BYTES_PER_PIXEL);
+
         Image {
-            data: Vec::new(),
-            _width: 0,
-            _height: 0,
-            _bytes_per_scanline: 0,
-            _bytes_per_pixel: 3,
+            width: to_i32(bitmap.width),
+            height: to_i32(bitmap.height),
+            bytes_per_scanline: to_i32(bitmap.width) * bytes_per_pixel,
+            bytes_per_pixel,
+            data: bitmap.data,
+            wrap,
         }
     }
 }
 
 impl Texture for Image {
-    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
-        if self.data.is_empty() {
-            return Color::new(0.0, 1.0, 1.0);
-        }
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        // Flip V to image coordinates
+        let uu = self.wrap.apply(u);
+        let vv = 1.0 - self.wrap.apply(v);
 
-        /*let uu = utils::clamp(u, 0.0, 1.0);
-        let vv = 1.0 - utils::clamp(v, 0.0, 1.0);
-
-        let mut i = utils::float_to_int_truncate(uu * f64::from(self.width));
-        let mut j = utils::float_to_int_truncate(vv * f64::from(self.height));
-
-        if i >= self.width {
-            i = self.width - 1;
-        }
-        if j >= self.height {
-            j = self.height-1;
-        }
+        let i = utils::float_to_int_truncate(uu * f64::from(self.width)).clamp(0, self.width - 1);
+        let j = utils::float_to_int_truncate(vv * f64::from(self.height)).clamp(0, self.height - 1);
 
-        let color_scale = 1.0 / 255.0;*/
+        let color_scale = 1.0 / 255.0;
+        #[allow(clippy::cast_sign_loss)] // Indices are clamped to be non-negative
+        let offset = (j * self.bytes_per_scanline + i * self.bytes_per_pixel) as usize;
+        let pixel = &self.data[offset..offset + 3];
 
-        todo!()
+        Color::new(
+            color_scale * f64::from(pixel[0]),
+            color_scale * f64::from(pixel[1]),
+            color_scale * f64::from(pixel[2]),
+        )
     }
 }
@@ -2,9 +2,17 @@ pub mod checker;
 pub mod image;
 pub mod noise;
 pub mod solid_color;
+pub mod vertex_color;
 
+use crate::hittable::HitRecord;
 use crate::vec3::{Color, Point3};
 
 pub trait Texture: Sync + Send {
     fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+
+    // Color at a surface hit, which may use more of the hit than its texture coordinates and
+    // position, such as its vertex color
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.value(rec.u, rec.v, &rec.p)
+    }
 }
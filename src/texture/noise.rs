@@ -1,19 +1,20 @@
 use super::Texture;
 use crate::{
     perlin::Perlin,
+    utils::Rng,
     vec3::{Axis, Color, Point3},
 };
 
 pub struct Noise {
-    noise: Perlin,
+    perlin: Perlin,
     scale: f64,
     axis: Axis,
 }
 
 impl Noise {
-    pub fn new(scale: f64, axis: Axis) -> Noise {
+    pub fn new(scale: f64, axis: Axis, rng: &mut Rng) -> Noise {
         Noise {
-            noise: Perlin::default(),
+            perlin: Perlin::new(rng),
             scale,
             axis,
         }
@@ -25,6 +26,6 @@ impl Texture for Noise {
         let s = self.scale * p;
         Color::new_single(1.0)
             * 0.5
-            * (1.0 + (s.axis(&self.axis) + 10.0 * self.noise.turb(&s, 7)).sin())
+            * (1.0 + (s.axis(&self.axis) + 10.0 * self.perlin.turb(&s, 7)).sin())
     }
 }
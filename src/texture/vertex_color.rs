@@ -0,0 +1,31 @@
+use super::Texture;
+use crate::hittable::HitRecord;
+use crate::vec3::{Color, Point3};
+
+// The colors given to the corners of mesh triangles, blended across each face. Surfaces without
+// vertex colors show the fallback color instead
+pub struct VertexColor {
+    fallback: Color,
+}
+
+impl VertexColor {
+    pub fn new(fallback: Color) -> VertexColor {
+        VertexColor { fallback }
+    }
+}
+
+impl Default for VertexColor {
+    fn default() -> VertexColor {
+        VertexColor::new(Color::new_single(0.73))
+    }
+}
+
+impl Texture for VertexColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.fallback
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        rec.vertex_color.unwrap_or(self.fallback)
+    }
+}
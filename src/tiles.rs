@@ -0,0 +1,115 @@
+use std::str::FromStr;
+
+// Order in which the tiles of an image are handed to the render threads
+#[derive(Clone, Copy)]
+pub enum Order {
+    // Row by row from the top
+    Scanline,
+    // Outwards from the center, so the middle of the image appears first
+    Spiral,
+    // Along a Hilbert curve, which keeps consecutive tiles next to each other
+    Hilbert,
+}
+
+impl Order {
+    pub const ALL: [Order; 3] = [Order::Scanline, Order::Spiral, Order::Hilbert];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Order::Scanline => "scanline",
+            Order::Spiral => "spiral",
+            Order::Hilbert => "hilbert",
+        }
+    }
+}
+
+impl FromStr for Order {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Order, ()> {
+        let name = s.to_ascii_lowercase();
+        Order::ALL.into_iter().find(|o| o.name() == name).ok_or(())
+    }
+}
+
+// Rectangle of pixels from (x0, y0) up to but excluding (x1, y1), counted from the top left
+#[derive(Clone, Copy)]
+pub struct Tile {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl Tile {
+    pub fn width(&self) -> usize {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> usize {
+        self.y1 - self.y0
+    }
+}
+
+// Covers a width by height image with square tiles of `tile_size` pixels, clipped at the right and
+// bottom edges, in the given order
+pub fn split(width: usize, height: usize, tile_size: usize, order: Order) -> Vec<Tile> {
+    let columns = width.div_ceil(tile_size);
+    let rows = height.div_ceil(tile_size);
+
+    let mut grid: Vec<(usize, usize)> = (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (column, row)))
+        .collect();
+    match order {
+        Order::Scanline => {}
+        Order::Spiral => grid.sort_by(|&a, &b| {
+            spiral_key(a, columns, rows).total_cmp(&spiral_key(b, columns, rows))
+        }),
+        Order::Hilbert => {
+            let side = columns.max(rows).next_power_of_two();
+            grid.sort_by_key(|&(column, row)| hilbert_index(side, column, row));
+        }
+    }
+
+    grid.into_iter()
+        .map(|(column, row)| Tile {
+            x0: column * tile_size,
+            y0: row * tile_size,
+            x1: ((column + 1) * tile_size).min(width),
+            y1: ((row + 1) * tile_size).min(height),
+        })
+        .collect()
+}
+
+// Ring around the center tile, then the angle within the ring, folded into one number
+fn spiral_key((column, row): (usize, usize), columns: usize, rows: usize) -> f64 {
+    #[allow(clippy::cast_precision_loss)] // Tile counts are far below 2^52
+    let (dx, dy) = (
+        column as f64 - (columns as f64 - 1.0) / 2.0,
+        row as f64 - (rows as f64 - 1.0) / 2.0,
+    );
+    let ring = dx.abs().max(dy.abs()).round();
+    let angle = dy.atan2(dx) + std::f64::consts::PI;
+    ring * 8.0 + angle
+}
+
+// Distance along the Hilbert curve filling a `side` by `side` grid, `side` a power of two
+fn hilbert_index(side: usize, mut x: usize, mut y: usize) -> usize {
+    let mut index = 0;
+    let mut s = side / 2;
+    while s > 0 {
+        let rx = usize::from(x & s > 0);
+        let ry = usize::from(y & s > 0);
+        index += s * s * ((3 * rx) ^ ry);
+        // Rotate the quadrant so the curve inside it joins up with its neighbours
+        if ry == 0 {
+            if rx == 1 {
+                x = side - 1 - x;
+                y = side - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    index
+}
@@ -0,0 +1,122 @@
+use std::str::FromStr;
+
+use crate::vec3::Color;
+
+// Curve that compresses linear radiance into the [0, 1] range a display can show
+#[derive(Clone, Copy)]
+pub enum Operator {
+    // Cuts off everything above 1
+    Clamp,
+    // Divides by one plus the luminance, keeping the hue
+    Reinhard,
+    // Narkowicz's fit of the ACES filmic curve, which desaturates highlights
+    Aces,
+    // Hable's filmic curve from Uncharted 2, with a gentle toe and shoulder
+    Hable,
+}
+
+impl Operator {
+    pub const ALL: [Operator; 4] = [
+        Operator::Clamp,
+        Operator::Reinhard,
+        Operator::Aces,
+        Operator::Hable,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Operator::Clamp => "clamp",
+            Operator::Reinhard => "reinhard",
+            Operator::Aces => "aces",
+            Operator::Hable => "hable",
+        }
+    }
+}
+
+impl FromStr for Operator {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Operator, ()> {
+        let name = s.to_ascii_lowercase();
+        Operator::ALL
+            .into_iter()
+            .find(|o| o.name() == name)
+            .ok_or(())
+    }
+}
+
+// How linear radiance becomes display values for 8-bit output: scaled by the exposure, compressed
+// by the operator, then encoded with the sRGB transfer function
+#[derive(Clone, Copy)]
+pub struct ToneMap {
+    pub operator: Operator,
+    // In stops, so each step of 1 doubles the brightness
+    pub exposure: f64,
+}
+
+impl Default for ToneMap {
+    fn default() -> ToneMap {
+        ToneMap {
+            operator: Operator::Clamp,
+            exposure: 0.0,
+        }
+    }
+}
+
+impl ToneMap {
+    // This tone map with the operator and exposure replaced where given
+    pub fn with(self, operator: Option<Operator>, exposure: Option<f64>) -> ToneMap {
+        ToneMap {
+            operator: operator.unwrap_or(self.operator),
+            exposure: exposure.unwrap_or(self.exposure),
+        }
+    }
+
+    // sRGB encoded display value in [0, 1] of each channel
+    pub fn apply(&self, rgb: [f32; 3]) -> [f64; 3] {
+        let color = Color::new(f64::from(rgb[0]), f64::from(rgb[1]), f64::from(rgb[2]))
+            * self.exposure.exp2();
+        let mapped = match self.operator {
+            Operator::Clamp => color,
+            Operator::Reinhard => color / (1.0 + color.luminance().max(0.0)),
+            // The fit expects radiance scaled down to match the reference transform
+            Operator::Aces => map_channels(&(color * 0.6), aces),
+            Operator::Hable => map_channels(&(color * 2.0), |x| hable(x) / hable(HABLE_WHITE)),
+        };
+        [mapped.x(), mapped.y(), mapped.z()].map(|c| srgb_encode(c.clamp(0.0, 1.0)))
+    }
+}
+
+// Linear radiance that Hable's curve maps to white
+const HABLE_WHITE: f64 = 11.2;
+
+fn map_channels(color: &Color, f: impl Fn(f64) -> f64) -> Color {
+    Color::new(f(color.x()), f(color.y()), f(color.z()))
+}
+
+fn aces(x: f64) -> f64 {
+    let x = x.max(0.0);
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+fn hable(x: f64) -> f64 {
+    const SHOULDER: f64 = 0.15;
+    const LINEAR: f64 = 0.50;
+    const LINEAR_ANGLE: f64 = 0.10;
+    const TOE: f64 = 0.20;
+    const TOE_NUMERATOR: f64 = 0.02;
+    const TOE_DENOMINATOR: f64 = 0.30;
+    let x = x.max(0.0);
+    (x * (SHOULDER * x + LINEAR_ANGLE * LINEAR) + TOE * TOE_NUMERATOR)
+        / (x * (SHOULDER * x + LINEAR) + TOE * TOE_DENOMINATOR)
+        - TOE_NUMERATOR / TOE_DENOMINATOR
+}
+
+// Linear in the darkest tones, then a power curve of roughly gamma 2.2
+fn srgb_encode(linear: f64) -> f64 {
+    if linear <= 0.003_130_8 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
@@ -1,4 +1,9 @@
-use rand::Rng;
+use rand::Rng as _;
+use rand_pcg::Pcg32;
+
+// Every random number comes from a generator passed down explicitly, so a render depends only on
+// its seed and not on how the work is scheduled across threads
+pub type Rng = Pcg32;
 
 pub fn float_to_int_truncate(num: f64) -> i32 {
     #[allow(clippy::cast_possible_truncation)] // Truncation is fine
@@ -10,21 +15,40 @@ pub fn degrees_to_radians(degrees: f64) -> f64 {
     degrees * std::f64::consts::PI / 180.0
 }
 
+// Generator for building a scene, on a stream no pixel uses
+pub fn scene_rng(seed: u64) -> Rng {
+    Rng::new(mix(seed), u64::MAX >> 1)
+}
+
+// Generator for one sample of one pixel. Each pixel has its own stream and each sample its own
+// starting state, so samples can be traced in any order, on any thread
+pub fn sample_rng(seed: u64, pixel: u64, sample: u64) -> Rng {
+    Rng::new(mix(seed ^ mix(sample)), pixel)
+}
+
+// SplitMix64 finalizer, which spreads nearby seeds far apart
+pub fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
 // Range [0, 1)
-pub fn random_float() -> f64 {
-    rand::random::<f64>()
+pub fn random_float(rng: &mut Rng) -> f64 {
+    rng.gen::<f64>()
 }
 
 // Range [min,max)
-pub fn random_float_range(min: f64, max: f64) -> f64 {
+pub fn random_float_range(rng: &mut Rng, min: f64, max: f64) -> f64 {
     debug_assert!(min < max);
-    min + (max - min) * random_float()
+    min + (max - min) * random_float(rng)
 }
 
 // Range [min,max]
-pub fn random_int(min: i32, max: i32) -> i32 {
+pub fn random_int(rng: &mut Rng, min: i32, max: i32) -> i32 {
     debug_assert!(min < max);
-    rand::thread_rng().gen_range(min..=max)
+    rng.gen_range(min..=max)
 }
 
 pub fn clamp(value: f64, min: f64, max: f64) -> f64 {
@@ -1,7 +1,8 @@
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
 use std::fmt;
 use std::ops;
 
-use crate::utils;
+use crate::utils::{self, Rng};
 
 pub type Color = Vec3;
 pub type Point3 = Vec3;
@@ -78,6 +79,15 @@ impl Vec3 {
         self / self.length()
     }
 
+    // Perceived brightness of a linear Rec. 709 color
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.elements[0] + 0.7152 * self.elements[1] + 0.0722 * self.elements[2]
+    }
+
+    pub fn has_nan(&self) -> bool {
+        self.elements.iter().any(|e| e.is_nan())
+    }
+
     pub fn near_zero(&self) -> bool {
         let s = 1e-8;
         self.elements[0].abs() < s && self.elements[1].abs() < s && self.elements[2].abs() < s
@@ -94,55 +104,45 @@ impl Vec3 {
         r_out_perp + r_out_parallel
     }
 
-    pub fn random() -> Vec3 {
+    pub fn random(rng: &mut Rng) -> Vec3 {
         Vec3::new(
-            utils::random_float(),
-            utils::random_float(),
-            utils::random_float(),
+            utils::random_float(rng),
+            utils::random_float(rng),
+            utils::random_float(rng),
         )
     }
 
-    pub fn random_range(min: f64, max: f64) -> Vec3 {
+    pub fn random_range(rng: &mut Rng, min: f64, max: f64) -> Vec3 {
         Vec3::new(
-            utils::random_float_range(min, max),
-            utils::random_float_range(min, max),
-            utils::random_float_range(min, max),
+            utils::random_float_range(rng, min, max),
+            utils::random_float_range(rng, min, max),
+            utils::random_float_range(rng, min, max),
         )
     }
 
-    pub fn random_in_unit_sphere() -> Vec3 {
-        loop {
-            let p = Vec3::random_range(-1.0, 1.0);
-            if p.length_squared() < 1.0 {
-                return p;
-            }
-        }
+    // Uniform over the unit sphere as `(u0, u1)` covers the unit square
+    pub fn uniform_sphere((u0, u1): (f64, f64)) -> Vec3 {
+        let z = 1.0 - 2.0 * u0;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * u1;
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
     }
 
-    pub fn random_unit_vector() -> Vec3 {
-        Vec3::random_in_unit_sphere().unit_vector()
-    }
-
-    pub fn random_in_hemisphere(normal: &Vec3) -> Vec3 {
-        let in_unit_sphere = Vec3::random_in_unit_sphere();
-        if in_unit_sphere.dot(normal) > 0.0 {
-            in_unit_sphere
-        } else {
-            -in_unit_sphere
+    // Uniform over the unit disk in the xy plane. The concentric mapping keeps neighbouring points
+    // of the square together, so stratified samples stay stratified on the disk
+    pub fn concentric_disk((u0, u1): (f64, f64)) -> Vec3 {
+        let a = 2.0 * u0 - 1.0;
+        let b = 2.0 * u1 - 1.0;
+        if a == 0.0 && b == 0.0 {
+            return Vec3::default();
         }
-    }
 
-    pub fn random_in_unit_disk() -> Vec3 {
-        loop {
-            let p = Vec3::new(
-                utils::random_float_range(-1.0, 1.0),
-                utils::random_float_range(-1.0, 1.0),
-                0.0,
-            );
-            if p.length_squared() < 1.0 {
-                return p;
-            }
-        }
+        let (r, theta) = if a.abs() > b.abs() {
+            (a, FRAC_PI_4 * (b / a))
+        } else {
+            (b, FRAC_PI_2 - FRAC_PI_4 * (a / b))
+        };
+        Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
     }
 }
 